@@ -1,2 +1,12 @@
+pub mod diffs;
+pub mod errors;
+pub mod graphs;
+pub mod hashing;
+pub mod indexes;
+pub mod json;
+pub mod outputs;
 pub mod parsers;
+pub mod paths;
+pub mod rebuilds;
+pub mod renderers;
 pub mod types;
@@ -0,0 +1,669 @@
+pub mod errors;
+
+use crate::derivations::diffs::errors::DiffError;
+use crate::derivations::hashing::DerivationResolver;
+use crate::derivations::types::{
+    Derivation,
+    DerivationInput,
+    DerivationOutput,
+    DerivationVersion,
+};
+use crate::store_paths::types::StorePath;
+use crate::strings::types::ByteString;
+
+use core::fmt;
+use std::collections::{
+    BTreeMap,
+    BTreeSet,
+};
+use std::path::PathBuf;
+
+/// The largest number of cells in the table used to find the longest common
+/// subsequence of two sequences. Longer sequences are shown as replaced
+/// entirely, which keeps diffing huge environment variables fast.
+const MAX_TABLE_SIZE: usize = 1 << 22;
+
+/// Environment variables longer than this, or with several lines, are shown as
+/// word-level diffs.
+const MAX_INLINE_LENGTH: usize = 80;
+
+/// A value that is only in the old derivation, only in the new one, or in both
+/// with different contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Change<T> {
+    /// The value is only in the new derivation.
+    Added(T),
+    /// The value is only in the old derivation.
+    Removed(T),
+    /// The value changed from the first to the second.
+    Changed(T, T),
+}
+
+impl<T> Change<T> {
+    /// Converts the changed values with `convert`.
+    #[inline]
+    pub fn map<U>(self, mut convert: impl FnMut(T) -> U) -> Change<U> {
+        match self {
+            Self::Added(value) => Change::Added(convert(value)),
+            Self::Removed(value) => Change::Removed(convert(value)),
+            Self::Changed(old, new) => Change::Changed(convert(old), convert(new)),
+        }
+    }
+
+    /// Compares two optional values, returning `None` if they are equal.
+    fn between(old: Option<T>, new: Option<T>) -> Option<Self>
+    where
+        T: PartialEq, {
+        match (old, new) {
+            (Some(old), Some(new)) if old == new => None,
+            (Some(old), Some(new)) => Some(Self::Changed(old, new)),
+            (Some(old), None) => Some(Self::Removed(old)),
+            (None, Some(new)) => Some(Self::Added(new)),
+            (None, None) => None,
+        }
+    }
+}
+
+/// A part of a diff between two sequences.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DiffChunk<T> {
+    /// The part is in both sequences.
+    Same(T),
+    /// The part is only in the old sequence.
+    Removed(T),
+    /// The part is only in the new sequence.
+    Added(T),
+}
+
+/// Diffs two sequences item by item, keeping the longest common subsequence.
+fn diff_slices<'item, T: PartialEq>(old: &'item [T], new: &'item [T]) -> Vec<DiffChunk<&'item T>> {
+    let prefix = old.iter().zip(new).take_while(|&(old_item, new_item)| old_item == new_item).count();
+    let (old_rest, new_rest) = (&old[prefix..], &new[prefix..]);
+    let suffix = old_rest
+        .iter()
+        .rev()
+        .zip(new_rest.iter().rev())
+        .take_while(|&(old_item, new_item)| old_item == new_item)
+        .count();
+    let old_middle = &old_rest[..old_rest.len() - suffix];
+    let new_middle = &new_rest[..new_rest.len() - suffix];
+
+    let mut chunks = old[..prefix].iter().map(DiffChunk::Same).collect::<Vec<_>>();
+    if (old_middle.len() + 1).saturating_mul(new_middle.len() + 1) > MAX_TABLE_SIZE {
+        chunks.extend(old_middle.iter().map(DiffChunk::Removed));
+        chunks.extend(new_middle.iter().map(DiffChunk::Added));
+    } else {
+        // The length of the longest common subsequence of the remaining items,
+        // starting at each pair of positions.
+        let width = new_middle.len() + 1;
+        let mut lengths = vec![0_usize; (old_middle.len() + 1) * width];
+        for (old_index, old_item) in old_middle.iter().enumerate().rev() {
+            for (new_index, new_item) in new_middle.iter().enumerate().rev() {
+                lengths[old_index * width + new_index] = if old_item == new_item {
+                    lengths[(old_index + 1) * width + new_index + 1] + 1
+                } else {
+                    lengths[(old_index + 1) * width + new_index].max(lengths[old_index * width + new_index + 1])
+                };
+            }
+        }
+        let (mut old_index, mut new_index) = (0, 0);
+        while let (Some(old_item), Some(new_item)) = (old_middle.get(old_index), new_middle.get(new_index)) {
+            if old_item == new_item {
+                chunks.push(DiffChunk::Same(old_item));
+                old_index += 1;
+                new_index += 1;
+            } else if lengths[(old_index + 1) * width + new_index] >= lengths[old_index * width + new_index + 1] {
+                chunks.push(DiffChunk::Removed(old_item));
+                old_index += 1;
+            } else {
+                chunks.push(DiffChunk::Added(new_item));
+                new_index += 1;
+            }
+        }
+        chunks.extend(old_middle[old_index..].iter().map(DiffChunk::Removed));
+        chunks.extend(new_middle[new_index..].iter().map(DiffChunk::Added));
+    }
+    chunks.extend(old_rest[old_rest.len() - suffix..].iter().map(DiffChunk::Same));
+    chunks
+}
+
+/// Splits `text` into runs of whitespace and runs of everything else.
+fn split_words(text: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    let mut is_whitespace = None;
+    for (index, character) in text.char_indices() {
+        if is_whitespace.is_some_and(|is_whitespace| is_whitespace != character.is_whitespace()) {
+            words.push(&text[start..index]);
+            start = index;
+        }
+        is_whitespace = Some(character.is_whitespace());
+    }
+    if start < text.len() {
+        words.push(&text[start..]);
+    }
+    words
+}
+
+/// Diffs two texts word by word. Whitespace is kept, so joining the same and
+/// removed chunks gives `old` and joining the same and added chunks gives `new`.
+#[inline]
+#[must_use]
+pub fn diff_words(old: &str, new: &str) -> Vec<DiffChunk<String>> {
+    let mut chunks = Vec::<DiffChunk<String>>::new();
+    for chunk in diff_slices(&split_words(old), &split_words(new)) {
+        match (chunks.last_mut(), chunk) {
+            (Some(&mut DiffChunk::Same(ref mut text)), DiffChunk::Same(word))
+            | (Some(&mut DiffChunk::Removed(ref mut text)), DiffChunk::Removed(word))
+            | (Some(&mut DiffChunk::Added(ref mut text)), DiffChunk::Added(word)) => text.push_str(word),
+            (_, DiffChunk::Same(word)) => chunks.push(DiffChunk::Same((*word).to_owned())),
+            (_, DiffChunk::Removed(word)) => chunks.push(DiffChunk::Removed((*word).to_owned())),
+            (_, DiffChunk::Added(word)) => chunks.push(DiffChunk::Added((*word).to_owned())),
+        }
+    }
+    chunks
+}
+
+/// Compares two sets of store paths. Paths only in one of the sets are paired
+/// up when exactly one path on each side has a given name, since they are most
+/// likely the same thing built differently.
+///
+/// Changes are ordered by name.
+#[inline]
+#[must_use]
+pub fn pair_by_name<'path>(
+    old: &BTreeSet<&'path StorePath>,
+    new: &BTreeSet<&'path StorePath>,
+) -> Vec<Change<&'path StorePath>> {
+    let mut by_name = BTreeMap::<_, (Vec<_>, Vec<_>)>::new();
+    for &path in old.difference(new) {
+        by_name.entry(path.name()).or_default().0.push(path);
+    }
+    for &path in new.difference(old) {
+        by_name.entry(path.name()).or_default().1.push(path);
+    }
+    by_name
+        .into_values()
+        .flat_map(|(old_paths, new_paths)| match (old_paths.as_slice(), new_paths.as_slice()) {
+            (&[old_path], &[new_path]) => vec![Change::Changed(old_path, new_path)],
+            _ => old_paths.into_iter().map(Change::Removed).chain(new_paths.into_iter().map(Change::Added)).collect(),
+        })
+        .collect()
+}
+
+/// How an input derivation differs between two derivations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InputDerivationChange {
+    /// The input derivation is only used by the new derivation.
+    Added(StorePath, DerivationInput),
+    /// The input derivation is only used by the old derivation.
+    Removed(StorePath, DerivationInput),
+    /// Different input derivations with the same name are used, or different
+    /// outputs of the same one.
+    Changed(InputDerivationDiff),
+}
+
+impl InputDerivationChange {
+    /// Returns the path the change is sorted and shown by.
+    #[must_use]
+    const fn path(&self) -> &StorePath {
+        match *self {
+            Self::Added(ref path, _) | Self::Removed(ref path, _) => path,
+            Self::Changed(ref diff) => &diff.old,
+        }
+    }
+}
+
+/// The differences between two input derivations with the same name.
+#[expect(clippy::exhaustive_structs, reason = "Derivation format is very stable.")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputDerivationDiff {
+    pub old: StorePath,
+    pub new: StorePath,
+    /// The outputs used from the old and the new input derivation, if they
+    /// differ.
+    pub outputs: Option<(DerivationInput, DerivationInput)>,
+    /// The differences between the input derivations themselves, if they were
+    /// compared with `DerivationDiff::recursive`.
+    pub diff: Option<Box<DerivationDiff>>,
+}
+
+/// The differences between two derivations, like `nix-diff` shows them.
+///
+/// Input derivations and input sources with the same name are paired up, so a
+/// rebuilt dependency shows as changed rather than as removed and added.
+#[expect(clippy::exhaustive_structs, reason = "Derivation format is very stable.")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DerivationDiff {
+    pub version: Option<(DerivationVersion, DerivationVersion)>,
+    pub outputs: BTreeMap<String, Change<DerivationOutput>>,
+    pub input_drvs: Vec<InputDerivationChange>,
+    pub input_srcs: Vec<Change<StorePath>>,
+    pub system: Option<(String, String)>,
+    pub builder: Option<(PathBuf, PathBuf)>,
+    pub args: Option<Vec<DiffChunk<ByteString>>>,
+    pub env: BTreeMap<ByteString, Change<ByteString>>,
+}
+
+impl DerivationDiff {
+    /// Compares two derivations, without comparing their input derivations.
+    #[inline]
+    #[must_use]
+    pub fn new(old: &Derivation, new: &Derivation) -> Self {
+        let output_names = old.outputs.keys().chain(new.outputs.keys()).collect::<BTreeSet<_>>();
+        let outputs = output_names.into_iter().filter_map(|name| {
+            let change = Change::between(old.outputs.get(name), new.outputs.get(name))?;
+            Some((name.clone(), change.map(Clone::clone)))
+        });
+
+        let old_input_drvs = old.input_drvs.keys().collect();
+        let new_input_drvs = new.input_drvs.keys().collect();
+        let mut input_drvs = pair_by_name(&old_input_drvs, &new_input_drvs)
+            .into_iter()
+            .map(|change| match change {
+                Change::Added(path) => InputDerivationChange::Added(path.clone(), new.input_drvs[path].clone()),
+                Change::Removed(path) => InputDerivationChange::Removed(path.clone(), old.input_drvs[path].clone()),
+                Change::Changed(old_path, new_path) => {
+                    let outputs = (&old.input_drvs[old_path], &new.input_drvs[new_path]);
+                    InputDerivationChange::Changed(InputDerivationDiff {
+                        old: old_path.clone(),
+                        new: new_path.clone(),
+                        outputs: (outputs.0 != outputs.1).then(|| (outputs.0.clone(), outputs.1.clone())),
+                        diff: None,
+                    })
+                },
+            })
+            .collect::<Vec<_>>();
+        for path in old_input_drvs.intersection(&new_input_drvs) {
+            let outputs = (&old.input_drvs[*path], &new.input_drvs[*path]);
+            if outputs.0 != outputs.1 {
+                input_drvs.push(InputDerivationChange::Changed(InputDerivationDiff {
+                    old: (*path).clone(),
+                    new: (*path).clone(),
+                    outputs: Some((outputs.0.clone(), outputs.1.clone())),
+                    diff: None,
+                }));
+            }
+        }
+        input_drvs.sort_by(|first, second| first.path().name().cmp(second.path().name()));
+
+        let old_input_srcs = old.input_srcs.iter().collect();
+        let new_input_srcs = new.input_srcs.iter().collect();
+        let input_srcs = pair_by_name(&old_input_srcs, &new_input_srcs);
+
+        let env_keys = old.env.keys().chain(new.env.keys()).collect::<BTreeSet<_>>();
+        let env = env_keys.into_iter().filter_map(|key| {
+            let change = Change::between(old.env.get(key), new.env.get(key))?;
+            Some((key.clone(), change.map(Clone::clone)))
+        });
+
+        Self {
+            version: (old.version != new.version).then_some((old.version, new.version)),
+            outputs: outputs.collect(),
+            input_drvs,
+            input_srcs: input_srcs.into_iter().map(|change| change.map(Clone::clone)).collect(),
+            system: (old.system != new.system).then(|| (old.system.clone(), new.system.clone())),
+            builder: (old.builder != new.builder).then(|| (old.builder.clone(), new.builder.clone())),
+            args: (old.args != new.args)
+                .then(|| diff_slices(&old.args, &new.args).into_iter().map(|chunk| chunk.map(Clone::clone)).collect()),
+            env: env.collect(),
+        }
+    }
+
+    /// Compares two derivations and, recursively, the input derivations paired
+    /// up by name, to find the root cause of the differences. Input derivations
+    /// of `old` are loaded with `old_resolver` and those of `new` with
+    /// `new_resolver`.
+    ///
+    /// Each pair of input derivations is only compared the first time it is
+    /// found.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `DiffError` if an input derivation can not be loaded.
+    #[inline]
+    pub fn recursive<O, N>(
+        old: &Derivation,
+        new: &Derivation,
+        old_resolver: &mut O,
+        new_resolver: &mut N,
+    ) -> Result<Self, DiffError<O::Error>>
+    where
+        O: DerivationResolver,
+        N: DerivationResolver<Error = O::Error>, {
+        let mut diff = Self::new(old, new);
+        diff.compare_input_drvs(old_resolver, new_resolver, &mut BTreeSet::new())?;
+        Ok(diff)
+    }
+
+    /// Compares the pairs of different input derivations that have not been
+    /// compared yet.
+    fn compare_input_drvs<O, N>(
+        &mut self,
+        old_resolver: &mut O,
+        new_resolver: &mut N,
+        compared: &mut BTreeSet<(StorePath, StorePath)>,
+    ) -> Result<(), DiffError<O::Error>>
+    where
+        O: DerivationResolver,
+        N: DerivationResolver<Error = O::Error>, {
+        for change in &mut self.input_drvs {
+            let InputDerivationChange::Changed(ref mut input) = *change else {
+                continue;
+            };
+            if input.old == input.new || !compared.insert((input.old.clone(), input.new.clone())) {
+                continue;
+            }
+            let old = old_resolver.resolve(&input.old).map_err(|err| DiffError::Resolve(input.old.clone(), err))?;
+            let new = new_resolver.resolve(&input.new).map_err(|err| DiffError::Resolve(input.new.clone(), err))?;
+            let mut diff = Self::new(&old, &new);
+            diff.compare_input_drvs(old_resolver, new_resolver, compared)?;
+            input.diff = Some(Box::new(diff));
+        }
+        Ok(())
+    }
+
+    /// Returns whether the derivations are the same.
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Writes the differences, one per line, indented by `indent` levels.
+    fn write_indented(&self, formatter: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        let pad = "  ".repeat(indent);
+        if let Some((old, new)) = self.version {
+            writeln!(formatter, "{pad}version changed: {old:?} -> {new:?}")?;
+        }
+        for (name, change) in &self.outputs {
+            match *change {
+                Change::Added(ref output) => {
+                    writeln!(formatter, "{pad}output `{name}` added: {}", describe_output(output))?;
+                },
+                Change::Removed(ref output) => {
+                    writeln!(formatter, "{pad}output `{name}` removed: {}", describe_output(output))?;
+                },
+                Change::Changed(ref old, ref new) => {
+                    let (old, new) = (describe_output(old), describe_output(new));
+                    writeln!(formatter, "{pad}output `{name}` changed: {old} -> {new}")?;
+                },
+            }
+        }
+        for change in &self.input_drvs {
+            match *change {
+                InputDerivationChange::Added(ref path, ref input) => {
+                    let outputs = input.output_names().join(",");
+                    writeln!(formatter, "{pad}input derivation `{path}` added, using {outputs}")?;
+                },
+                InputDerivationChange::Removed(ref path, ref input) => {
+                    let outputs = input.output_names().join(",");
+                    writeln!(formatter, "{pad}input derivation `{path}` removed, was using {outputs}")?;
+                },
+                InputDerivationChange::Changed(ref input) => {
+                    if input.old == input.new {
+                        writeln!(formatter, "{pad}input derivation `{}` changed:", input.old)?;
+                    } else {
+                        writeln!(formatter, "{pad}input derivation changed: `{}` -> `{}`", input.old, input.new)?;
+                    }
+                    if let Some((ref old, ref new)) = input.outputs {
+                        let (old, new) = (old.output_names().join(","), new.output_names().join(","));
+                        writeln!(formatter, "{pad}  outputs changed: {old} -> {new}")?;
+                    }
+                    if let Some(ref diff) = input.diff {
+                        diff.write_indented(formatter, indent + 1)?;
+                    }
+                },
+            }
+        }
+        for change in &self.input_srcs {
+            match *change {
+                Change::Added(ref path) => writeln!(formatter, "{pad}input source `{path}` added")?,
+                Change::Removed(ref path) => writeln!(formatter, "{pad}input source `{path}` removed")?,
+                Change::Changed(ref old, ref new) => {
+                    writeln!(formatter, "{pad}input source changed: `{old}` -> `{new}`")?;
+                },
+            }
+        }
+        if let Some((ref old, ref new)) = self.system {
+            writeln!(formatter, "{pad}system changed: {old} -> {new}")?;
+        }
+        if let Some((ref old, ref new)) = self.builder {
+            writeln!(formatter, "{pad}builder changed: {} -> {}", old.display(), new.display())?;
+        }
+        if let Some(ref args) = self.args {
+            let args = args.iter().map(|chunk| chunk.as_ref().map(ToString::to_string));
+            writeln!(formatter, "{pad}arguments changed: {}", render_chunks(args, " "))?;
+        }
+        for (key, change) in &self.env {
+            let (action, text, has_own_lines) = match *change {
+                Change::Added(ref value) => ("added", value.to_string(), is_long(&value.to_str_lossy())),
+                Change::Removed(ref value) => ("removed", value.to_string(), is_long(&value.to_str_lossy())),
+                Change::Changed(ref old, ref new) => {
+                    let (old, new) = (old.to_str_lossy(), new.to_str_lossy());
+                    if is_long(&old) || is_long(&new) {
+                        ("changed", render_chunks(diff_words(&old, &new), ""), true)
+                    } else {
+                        ("changed", format!("{old} -> {new}"), false)
+                    }
+                },
+            };
+            if has_own_lines {
+                writeln!(formatter, "{pad}environment variable `{key}` {action}:")?;
+                for line in text.lines() {
+                    writeln!(formatter, "{pad}    {line}")?;
+                }
+            } else {
+                writeln!(formatter, "{pad}environment variable `{key}` {action}: {text}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T> DiffChunk<T> {
+    /// Converts the contents of the chunk with `convert`.
+    #[inline]
+    pub fn map<U>(self, convert: impl FnOnce(T) -> U) -> DiffChunk<U> {
+        match self {
+            Self::Same(value) => DiffChunk::Same(convert(value)),
+            Self::Removed(value) => DiffChunk::Removed(convert(value)),
+            Self::Added(value) => DiffChunk::Added(convert(value)),
+        }
+    }
+
+    /// Borrows the contents of the chunk.
+    #[inline]
+    pub const fn as_ref(&self) -> DiffChunk<&T> {
+        match *self {
+            Self::Same(ref value) => DiffChunk::Same(value),
+            Self::Removed(ref value) => DiffChunk::Removed(value),
+            Self::Added(ref value) => DiffChunk::Added(value),
+        }
+    }
+}
+
+/// Returns whether a value is shown on lines of its own, and diffed word by
+/// word if it changed.
+fn is_long(value: &str) -> bool {
+    value.len() > MAX_INLINE_LENGTH || value.contains('\n')
+}
+
+/// Renders chunks like `git diff --word-diff`, with removed text in `[-...-]`
+/// and added text in `{+...+}`, separated by `separator`.
+fn render_chunks(chunks: impl IntoIterator<Item = DiffChunk<String>>, separator: &str) -> String {
+    let rendered = chunks.into_iter().map(|chunk| match chunk {
+        DiffChunk::Same(text) => text,
+        DiffChunk::Removed(text) => format!("[-{text}-]"),
+        DiffChunk::Added(text) => format!("{{+{text}+}}"),
+    });
+    rendered.collect::<Vec<_>>().join(separator)
+}
+
+/// Describes an output by its path and hash, as far as it has them.
+fn describe_output(output: &DerivationOutput) -> String {
+    let path = output.path().map_or(String::new(), ToString::to_string);
+    let fields = [path, output.aterm_hash_algo(), output.aterm_hash()];
+    let fields = fields.into_iter().filter(|field| !field.is_empty()).collect::<Vec<_>>();
+    if fields.is_empty() { "deferred".to_owned() } else { fields.join(" ") }
+}
+
+/// Formats the differences one per line, with the differences of input
+/// derivations indented under them.
+impl fmt::Display for DerivationDiff {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_indented(formatter, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::derivations::errors::ReadDerivationError;
+    use crate::derivations::hashing::FileResolver;
+    use crate::derivations::parsers::parse_derivation_bytes;
+    use std::env;
+    use std::io;
+    use std::path::Path;
+
+    fn edge_case_resolver() -> FileResolver {
+        let manifest_directory = env::var_os("CARGO_MANIFEST_DIR").unwrap();
+        FileResolver::in_directory(Path::new(&manifest_directory).join("src/derivations/edge_case_derivations"))
+    }
+
+    fn store_path(base_name: &str) -> StorePath {
+        StorePath::new(format!("/nix/store/{base_name}")).unwrap()
+    }
+
+    #[test]
+    fn words() {
+        assert_eq!(split_words("make  install\n"), ["make", "  ", "install", "\n"]);
+        assert_eq!(diff_words("./configure --prefix=$out\nmake", "./configure --prefix=$out --enable-nls\nmake"), [
+            DiffChunk::Same("./configure --prefix=$out".to_owned()),
+            DiffChunk::Added(" --enable-nls".to_owned()),
+            DiffChunk::Same("\nmake".to_owned()),
+        ]);
+        assert_eq!(diff_words("a b c", "a x c"), [
+            DiffChunk::Same("a ".to_owned()),
+            DiffChunk::Removed("b".to_owned()),
+            DiffChunk::Added("x".to_owned()),
+            DiffChunk::Same(" c".to_owned()),
+        ]);
+        assert!(diff_words("", "").is_empty());
+    }
+
+    #[test]
+    fn identical() {
+        let derivation = edge_case_resolver()
+            .resolve(&store_path("qhh9g08r8f6an2hly0p4rjh7q73myz93-hello-2.12.1.drv"))
+            .unwrap();
+        let diff = DerivationDiff::new(&derivation, &derivation);
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "");
+    }
+
+    #[test]
+    fn fields() {
+        let old = parse_derivation_bytes(concat!(
+            r#"Derive([("out","/nix/store/00000000000000000000000000000000-a","","")],"#,
+            r#"[("/nix/store/00000000000000000000000000000000-b.drv",["out"]),"#,
+            r#"("/nix/store/00000000000000000000000000000000-c.drv",["out"]),"#,
+            r#"("/nix/store/00000000000000000000000000000000-d.drv",["out"])],"#,
+            r#"["/nix/store/00000000000000000000000000000000-source"],"x86_64-linux","/bin/sh",["-e","build.sh"],"#,
+            r#"[("name","a"),("old","1"),("out","/nix/store/00000000000000000000000000000000-a")])"#,
+        ).as_bytes())
+        .unwrap();
+        let new = parse_derivation_bytes(concat!(
+            r#"Derive([("out","/nix/store/11111111111111111111111111111111-a","","")],"#,
+            r#"[("/nix/store/00000000000000000000000000000000-b.drv",["dev","out"]),"#,
+            r#"("/nix/store/11111111111111111111111111111111-c.drv",["out"]),"#,
+            r#"("/nix/store/00000000000000000000000000000000-e.drv",["out"])],"#,
+            r#"["/nix/store/11111111111111111111111111111111-source"],"#,
+            r#""aarch64-linux","/bin/bash",["-e","-x","build.sh"],"#,
+            r#"[("name","a"),("new","2"),("out","/nix/store/11111111111111111111111111111111-a")])"#,
+        ).as_bytes())
+        .unwrap();
+        let diff = DerivationDiff::new(&old, &new);
+        assert_eq!(diff.input_drvs, [
+            InputDerivationChange::Changed(InputDerivationDiff {
+                old: store_path("00000000000000000000000000000000-b.drv"),
+                new: store_path("00000000000000000000000000000000-b.drv"),
+                outputs: Some((
+                    old.input_drvs[&store_path("00000000000000000000000000000000-b.drv")].clone(),
+                    new.input_drvs[&store_path("00000000000000000000000000000000-b.drv")].clone(),
+                )),
+                diff: None,
+            }),
+            InputDerivationChange::Changed(InputDerivationDiff {
+                old: store_path("00000000000000000000000000000000-c.drv"),
+                new: store_path("11111111111111111111111111111111-c.drv"),
+                outputs: None,
+                diff: None,
+            }),
+            InputDerivationChange::Removed(
+                store_path("00000000000000000000000000000000-d.drv"),
+                old.input_drvs[&store_path("00000000000000000000000000000000-d.drv")].clone(),
+            ),
+            InputDerivationChange::Added(
+                store_path("00000000000000000000000000000000-e.drv"),
+                new.input_drvs[&store_path("00000000000000000000000000000000-e.drv")].clone(),
+            ),
+        ]);
+        assert_eq!(diff.to_string(), concat!(
+            "output `out` changed: /nix/store/00000000000000000000000000000000-a -> ",
+            "/nix/store/11111111111111111111111111111111-a\n",
+            "input derivation `/nix/store/00000000000000000000000000000000-b.drv` changed:\n",
+            "  outputs changed: out -> dev,out\n",
+            "input derivation changed: `/nix/store/00000000000000000000000000000000-c.drv` -> ",
+            "`/nix/store/11111111111111111111111111111111-c.drv`\n",
+            "input derivation `/nix/store/00000000000000000000000000000000-d.drv` removed, was using out\n",
+            "input derivation `/nix/store/00000000000000000000000000000000-e.drv` added, using out\n",
+            "input source changed: `/nix/store/00000000000000000000000000000000-source` -> ",
+            "`/nix/store/11111111111111111111111111111111-source`\n",
+            "system changed: x86_64-linux -> aarch64-linux\n",
+            "builder changed: /bin/sh -> /bin/bash\n",
+            "arguments changed: -e {+-x+} build.sh\n",
+            "environment variable `new` added: 2\n",
+            "environment variable `old` removed: 1\n",
+            "environment variable `out` changed: /nix/store/00000000000000000000000000000000-a -> ",
+            "/nix/store/11111111111111111111111111111111-a\n",
+        ));
+    }
+
+    #[test]
+    fn recursive() {
+        let hello = store_path("qhh9g08r8f6an2hly0p4rjh7q73myz93-hello-2.12.1.drv");
+        let patched_hello = store_path("11111111111111111111111111111111-hello-2.12.1.drv");
+        let empty_env = store_path("nkqgqq2l1fpyifm974rf8yhxc1gzmihx-empty-env.drv");
+        let empty_env = edge_case_resolver().resolve(&empty_env).unwrap();
+        let mut patched_empty_env = empty_env.clone();
+        let input = patched_empty_env.input_drvs.remove(&hello).unwrap();
+        patched_empty_env.input_drvs.insert(patched_hello.clone(), input);
+
+        let mut new_resolver = |path: &StorePath| -> Result<Derivation, ReadDerivationError> {
+            let mut derivation = edge_case_resolver().resolve(if *path == patched_hello { &hello } else { path })?;
+            if *path == patched_hello {
+                let build_command = derivation.env.get_mut(b"buildCommand".as_slice()).unwrap();
+                *build_command = build_command.to_str_lossy().replace("make install", "make -j4 install").into();
+            }
+            Ok(derivation)
+        };
+        let diff =
+            DerivationDiff::recursive(&empty_env, &patched_empty_env, &mut edge_case_resolver(), &mut new_resolver)
+                .unwrap();
+        let [InputDerivationChange::Changed(ref input)] = *diff.input_drvs.as_slice() else {
+            panic!("expected one changed input derivation, got {:?}", diff.input_drvs);
+        };
+        let hello_diff = input.diff.as_ref().unwrap();
+        assert_eq!(hello_diff.env.keys().collect::<Vec<_>>(), ["buildCommand"]);
+        assert!(hello_diff.to_string().contains("make {+-j4 +}install"), "{hello_diff}");
+
+        let mut missing_resolver = |_: &StorePath| Err(ReadDerivationError::Io(io::ErrorKind::NotFound.into()));
+        let err = DerivationDiff::recursive(&empty_env, &patched_empty_env, &mut missing_resolver, &mut new_resolver);
+        assert!(matches!(err, Err(DiffError::Resolve(path, _)) if path == hello));
+    }
+}
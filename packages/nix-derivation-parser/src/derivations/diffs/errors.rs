@@ -0,0 +1,31 @@
+use crate::store_paths::types::StorePath;
+
+use core::fmt;
+use std::error::Error;
+
+/// An error produced when the input derivations of two derivations can not be
+/// compared.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum DiffError<E> {
+    /// The input derivation with the given path could not be loaded.
+    Resolve(StorePath, E),
+}
+
+impl<E: fmt::Display> fmt::Display for DiffError<E> {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Resolve(ref path, ref err) => write!(formatter, "failed to load `{path}`: {err}"),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for DiffError<E> {
+    #[inline]
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            Self::Resolve(_, ref err) => Some(err),
+        }
+    }
+}
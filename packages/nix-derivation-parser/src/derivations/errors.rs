@@ -0,0 +1,316 @@
+use crate::hashes::errors::HashError;
+use crate::positions::Position;
+use crate::store_paths::errors::StorePathError;
+use crate::store_paths::types::StorePath;
+use crate::strings::types::ByteString;
+
+use core::fmt;
+use std::error::Error;
+use std::io;
+
+/// A part of a derivation, used to describe where parsing failed.
+///
+/// Fields are named after the keys Nix uses for them in `nix derivation show`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DerivationField {
+    /// The `Derive(` or `DrvWithVersion(` header.
+    Header,
+    Outputs,
+    /// The output with the given name.
+    Output(String),
+    InputDrvs,
+    /// The input derivation with the given path.
+    InputDrv(StorePath),
+    /// The dynamic output of an input derivation with the given name.
+    DynamicOutput(String),
+    InputSrcs,
+    System,
+    Builder,
+    Args,
+    Env,
+    /// The environment variable with the given key.
+    EnvVar(ByteString),
+    /// The derivation with the given path, in a JSON object of derivations.
+    Derivation(String),
+    /// A key of a JSON derivation without a field of its own, e.g. `hashAlgo`.
+    Key(String),
+}
+
+impl fmt::Display for DerivationField {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Header => formatter.write_str("header"),
+            Self::Outputs => formatter.write_str("outputs"),
+            Self::Output(ref name) | Self::DynamicOutput(ref name) => write!(formatter, "`{name}`"),
+            Self::InputDrvs => formatter.write_str("inputDrvs"),
+            Self::InputDrv(ref path) => write!(formatter, "`{path}`"),
+            Self::InputSrcs => formatter.write_str("inputSrcs"),
+            Self::System => formatter.write_str("system"),
+            Self::Builder => formatter.write_str("builder"),
+            Self::Args => formatter.write_str("args"),
+            Self::Env => formatter.write_str("env"),
+            Self::EnvVar(ref key) => write!(formatter, "`{key}`"),
+            Self::Derivation(ref path) => write!(formatter, "`{path}`"),
+            Self::Key(ref key) => formatter.write_str(key),
+        }
+    }
+}
+
+/// What went wrong while parsing a derivation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DerivationParseErrorKind {
+    /// Expected the given token, such as `(` or `,`.
+    ExpectedToken(&'static str),
+    /// Expected a `Derive(` or `DrvWithVersion(` header.
+    ExpectedHeader,
+    /// Expected a double quoted string.
+    ExpectedString,
+    /// Expected a `,` before the next item of a list or a `]` ending it.
+    ExpectedListSeparator,
+    /// A string that must be valid UTF-8, such as an output name, is not.
+    InvalidUtf8,
+    /// A path that must be a store path, such as an input source, is not.
+    InvalidStorePath(String, StorePathError),
+    /// The path, hash algorithm and hash of an output do not fit together.
+    InvalidOutput(DerivationOutputError),
+    /// The `DrvWithVersion(` header names a version this crate does not know.
+    UnknownVersion(String),
+    /// Dynamic outputs are nested deeper than `MAX_DYNAMIC_OUTPUT_DEPTH`.
+    TooDeep,
+    /// The input ended before the derivation was complete.
+    UnexpectedEnd,
+    /// There is more input after the end of the derivation.
+    TrailingData,
+}
+
+impl fmt::Display for DerivationParseErrorKind {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::ExpectedToken(token) => write!(formatter, "expected `{token}`"),
+            Self::ExpectedHeader => formatter.write_str("expected `Derive(` or `DrvWithVersion(`"),
+            Self::ExpectedString => formatter.write_str("expected a string"),
+            Self::ExpectedListSeparator => formatter.write_str("expected `,` or `]`"),
+            Self::InvalidUtf8 => formatter.write_str("expected a valid UTF-8 string"),
+            Self::InvalidStorePath(ref path, ref err) => write!(formatter, "invalid store path `{path}`: {err}"),
+            Self::InvalidOutput(ref err) => write!(formatter, "invalid output: {err}"),
+            Self::UnknownVersion(ref version) => write!(formatter, "unknown derivation version `{version}`"),
+            Self::TooDeep => formatter.write_str("dynamic outputs are nested too deeply"),
+            Self::UnexpectedEnd => formatter.write_str("unexpected end of input"),
+            Self::TrailingData => formatter.write_str("unexpected data after the end of the derivation"),
+        }
+    }
+}
+
+/// An error produced when the fields of an output do not describe any kind of
+/// output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DerivationOutputError {
+    /// The hash algorithm is not one Nix supports.
+    InvalidHashAlgo(HashError),
+    /// The hash of a fixed output is not a valid digest for its algorithm.
+    InvalidHash(HashError),
+    /// A hash is given without a hash algorithm.
+    UnexpectedHash,
+    /// A floating or impure content-addressed output has a path.
+    UnexpectedPath,
+    /// A fixed-output has no path.
+    MissingPath,
+}
+
+impl fmt::Display for DerivationOutputError {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::InvalidHashAlgo(ref err) | Self::InvalidHash(ref err) => write!(formatter, "{err}"),
+            Self::UnexpectedHash => formatter.write_str("output has a hash but no hash algorithm"),
+            Self::UnexpectedPath => formatter.write_str("floating or impure output should not have a path"),
+            Self::MissingPath => formatter.write_str("fixed output has no path"),
+        }
+    }
+}
+
+impl Error for DerivationOutputError {
+    #[inline]
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            Self::InvalidHashAlgo(ref err) | Self::InvalidHash(ref err) => Some(err),
+            Self::UnexpectedHash | Self::UnexpectedPath | Self::MissingPath => None,
+        }
+    }
+}
+
+impl From<HashError> for DerivationOutputError {
+    #[inline]
+    fn from(err: HashError) -> Self {
+        Self::InvalidHashAlgo(err)
+    }
+}
+
+/// An error produced when a derivation can not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DerivationParseError {
+    /// What went wrong.
+    pub kind: DerivationParseErrorKind,
+    /// The fields that were being parsed, from the outermost to the innermost.
+    /// This is empty if the error is not inside any field, e.g. for trailing
+    /// data.
+    pub field: Vec<DerivationField>,
+    /// Where in the input the error occurred.
+    pub position: Position,
+}
+
+impl fmt::Display for DerivationParseError {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "{} at {}", self.kind, self.position)?;
+        for (index, field) in self.field.iter().enumerate() {
+            formatter.write_str(if index == 0 { " in " } else { " > " })?;
+            write!(formatter, "{field}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for DerivationParseError {}
+
+/// An error produced when a derivation can not be read from a reader.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ReadDerivationError {
+    /// Reading from the reader failed.
+    Io(io::Error),
+    /// What was read is not a valid derivation.
+    Parse(DerivationParseError),
+}
+
+impl fmt::Display for ReadDerivationError {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Io(ref err) => write!(formatter, "failed to read derivation: {err}"),
+            Self::Parse(ref err) => write!(formatter, "{err}"),
+        }
+    }
+}
+
+impl Error for ReadDerivationError {
+    #[inline]
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            Self::Io(ref err) => Some(err),
+            Self::Parse(ref err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for ReadDerivationError {
+    #[inline]
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<DerivationParseError> for ReadDerivationError {
+    #[inline]
+    fn from(err: DerivationParseError) -> Self {
+        Self::Parse(err)
+    }
+}
+
+/// An error produced when the hash of a derivation can not be computed.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum HashModuloError<E> {
+    /// The input derivation with the given path could not be loaded.
+    Resolve(StorePath, E),
+    /// An output is used from the input derivation with the given path, but it
+    /// does not have that output.
+    MissingOutput(StorePath, String),
+    /// The input derivation with the given path depends on itself, which only
+    /// happens in a corrupted store.
+    Cycle(StorePath),
+}
+
+impl<E: fmt::Display> fmt::Display for HashModuloError<E> {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Resolve(ref path, ref err) => write!(formatter, "failed to load `{path}`: {err}"),
+            Self::MissingOutput(ref path, ref output) => write!(formatter, "`{path}` has no output `{output}`"),
+            Self::Cycle(ref path) => write!(formatter, "`{path}` depends on itself"),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for HashModuloError<E> {
+    #[inline]
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            Self::Resolve(_, ref err) => Some(err),
+            Self::MissingOutput(..) | Self::Cycle(_) => None,
+        }
+    }
+}
+
+/// An error produced when the output paths of a derivation can not be computed.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum OutputPathError<E> {
+    /// The hash of the derivation could not be computed.
+    HashModulo(HashModuloError<E>),
+    /// The computed path of the output with the given name is not a valid store
+    /// path, e.g. because the derivation name contains invalid characters.
+    InvalidPath(String, StorePathError),
+}
+
+impl<E: fmt::Display> fmt::Display for OutputPathError<E> {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::HashModulo(ref err) => write!(formatter, "{err}"),
+            Self::InvalidPath(ref output, ref err) => write!(formatter, "invalid path for output `{output}`: {err}"),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for OutputPathError<E> {
+    #[inline]
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            Self::HashModulo(ref err) => Some(err),
+            Self::InvalidPath(_, ref err) => Some(err),
+        }
+    }
+}
+
+impl<E> From<HashModuloError<E>> for OutputPathError<E> {
+    #[inline]
+    fn from(err: HashModuloError<E>) -> Self {
+        Self::HashModulo(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let error = DerivationParseError {
+            kind: DerivationParseErrorKind::ExpectedToken(","),
+            field: vec![DerivationField::Env, DerivationField::EnvVar("buildPhase".into())],
+            position: Position {
+                offset: 2344,
+                line: 1,
+                column: 2345,
+            },
+        };
+        assert_eq!(error.to_string(), "expected `,` at line 1, column 2345 (byte 2344) in env > `buildPhase`");
+    }
+}
@@ -0,0 +1,570 @@
+pub mod errors;
+pub mod exporters;
+
+use crate::derivations::errors::ReadDerivationError;
+use crate::derivations::graphs::errors::{
+    CycleError,
+    GraphError,
+};
+use crate::derivations::hashing::{
+    DerivationResolver,
+    FileResolver,
+};
+use crate::derivations::types::{
+    Derivation,
+    DerivationInput,
+};
+use crate::store_paths::types::StorePath;
+
+use std::collections::{
+    BTreeMap,
+    BTreeSet,
+};
+use std::path::PathBuf;
+
+/// A use of an input derivation: `from` uses the outputs in `input` of `to`.
+#[expect(clippy::exhaustive_structs, reason = "Derivation format is very stable.")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DerivationEdge<'graph> {
+    pub from: &'graph StorePath,
+    pub to: &'graph StorePath,
+    pub input: &'graph DerivationInput,
+}
+
+/// The closure of one or more derivations: the derivations themselves and,
+/// recursively, all of their input derivations.
+///
+/// Every derivation is loaded and parsed once, however many derivations use it.
+/// Queries are keyed by the paths of the `.drv` files.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DerivationGraph {
+    nodes: BTreeMap<StorePath, Derivation>,
+}
+
+impl DerivationGraph {
+    /// Loads the closure of the derivations at `roots` with `resolver`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `GraphError` if a path in the closure is not a `.drv` path or
+    /// its derivation can not be loaded.
+    #[inline]
+    pub fn load<R: DerivationResolver>(
+        resolver: &mut R,
+        roots: impl IntoIterator<Item = StorePath>,
+    ) -> Result<Self, GraphError<R::Error>> {
+        let mut nodes = BTreeMap::new();
+        let mut pending = roots.into_iter().collect::<Vec<_>>();
+        pending.reverse();
+        while let Some(path) = pending.pop() {
+            if nodes.contains_key(&path) {
+                continue;
+            }
+            if !path.is_derivation() {
+                return Err(GraphError::NotDerivation(path));
+            }
+            let derivation = match resolver.resolve(&path) {
+                Ok(derivation) => derivation,
+                Err(err) => return Err(GraphError::Resolve(path, err)),
+            };
+            pending.extend(derivation.input_drvs.keys().rev().filter(|input| !nodes.contains_key(*input)).cloned());
+            nodes.insert(path, derivation);
+        }
+        Ok(Self { nodes })
+    }
+
+    /// Loads the closure of the derivations at `roots` from the `.drv` files in
+    /// `directory`, which is usually the store directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `GraphError` if a path in the closure is not a `.drv` path or
+    /// its file can not be read or parsed.
+    #[inline]
+    pub fn load_from_directory(
+        directory: impl Into<PathBuf>,
+        roots: impl IntoIterator<Item = StorePath>,
+    ) -> Result<Self, GraphError<ReadDerivationError>> {
+        Self::load(&mut FileResolver::in_directory(directory), roots)
+    }
+
+    /// Returns every derivation in the graph by its path.
+    #[inline]
+    #[must_use]
+    pub const fn nodes(&self) -> &BTreeMap<StorePath, Derivation> {
+        &self.nodes
+    }
+
+    /// Returns the derivation at `path`, if it is in the graph.
+    #[inline]
+    #[must_use]
+    pub fn get(&self, path: &StorePath) -> Option<&Derivation> {
+        self.nodes.get(path)
+    }
+
+    /// Returns whether the derivation at `path` is in the graph.
+    #[inline]
+    #[must_use]
+    pub fn contains(&self, path: &StorePath) -> bool {
+        self.nodes.contains_key(path)
+    }
+
+    /// Returns the number of derivations in the graph.
+    #[inline]
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns whether the graph has no derivations.
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Returns every use of an input derivation in the graph, ordered by the
+    /// paths of the derivations that use them.
+    #[inline]
+    pub fn edges(&self) -> impl Iterator<Item = DerivationEdge<'_>> {
+        self.nodes.keys().flat_map(|path| self.dependencies(path))
+    }
+
+    /// Returns the uses of input derivations by the derivation at `path`, which
+    /// are empty if it is not in the graph.
+    #[inline]
+    pub fn dependencies<'graph>(&'graph self, path: &StorePath) -> impl Iterator<Item = DerivationEdge<'graph>> {
+        self.nodes.get_key_value(path).into_iter().flat_map(|(from, derivation)| {
+            derivation.input_drvs.iter().map(move |(to, input)| DerivationEdge { from, to, input })
+        })
+    }
+
+    /// Returns the paths of the input derivations of the derivation at `path`
+    /// that are in the graph.
+    fn inputs<'graph>(&'graph self, path: &StorePath) -> impl Iterator<Item = &'graph StorePath> {
+        self.dependencies(path).map(|edge| edge.to).filter(|input| self.contains(input))
+    }
+
+    /// Returns the derivations no other derivation in the graph uses, e.g. the
+    /// ones the graph was loaded from.
+    #[inline]
+    #[must_use]
+    pub fn roots(&self) -> BTreeSet<&StorePath> {
+        let used = self.edges().map(|edge| edge.to).collect::<BTreeSet<_>>();
+        self.nodes.keys().filter(|path| !used.contains(path)).collect()
+    }
+
+    /// Returns the derivations without input derivations in the graph.
+    #[inline]
+    #[must_use]
+    pub fn leaves(&self) -> BTreeSet<&StorePath> {
+        self.nodes.keys().filter(|path| self.inputs(path).next().is_none()).collect()
+    }
+
+    /// Returns every derivation in the order they can be built in, with each
+    /// derivation after all of its input derivations.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CycleError` with one of the cycles if the derivations depend
+    /// on each other in a cycle.
+    #[inline]
+    pub fn topological_order(&self) -> Result<Vec<&StorePath>, CycleError> {
+        // Whether each visited derivation is finished, i.e. all of its inputs
+        // are in the order.
+        let mut finished = BTreeMap::new();
+        let mut order = Vec::with_capacity(self.nodes.len());
+        for start in self.nodes.keys() {
+            if finished.contains_key(start) {
+                continue;
+            }
+            finished.insert(start, false);
+            let mut stack = vec![(start, self.inputs(start))];
+            while let Some(&mut (path, ref mut inputs)) = stack.last_mut() {
+                let Some(input) = inputs.next() else {
+                    finished.insert(path, true);
+                    order.push(path);
+                    stack.pop();
+                    continue;
+                };
+                match finished.get(input) {
+                    None => {
+                        finished.insert(input, false);
+                        stack.push((input, self.inputs(input)));
+                    },
+                    Some(false) => {
+                        let on_cycle = stack.iter().map(|&(path, _)| path).skip_while(|&path| path != input);
+                        let cycle = on_cycle.chain([input]).cloned().collect();
+                        return Err(CycleError { cycle });
+                    },
+                    Some(true) => {},
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    /// Returns the depth of every derivation: the length of the longest chain of
+    /// derivations using it, which is 0 for the roots.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CycleError` if the derivations depend on each other in a
+    /// cycle.
+    #[inline]
+    pub fn depths(&self) -> Result<BTreeMap<&StorePath, usize>, CycleError> {
+        let order = self.topological_order()?;
+        let mut depths = order.iter().map(|&path| (path, 0)).collect::<BTreeMap<_, _>>();
+        for path in order.into_iter().rev() {
+            let depth = depths.get(path).copied().unwrap_or_default() + 1;
+            for input in self.inputs(path) {
+                depths.entry(input).and_modify(|input_depth| *input_depth = depth.max(*input_depth));
+            }
+        }
+        Ok(depths)
+    }
+
+    /// Returns the derivations reachable from `path` through `next`, including
+    /// the one at `path` if it is in the graph.
+    fn reachable<'graph, F, I>(&'graph self, path: &StorePath, mut next: F) -> BTreeSet<&'graph StorePath>
+    where
+        F: FnMut(&'graph StorePath) -> I,
+        I: IntoIterator<Item = &'graph StorePath> {
+        let mut reached = BTreeSet::new();
+        let mut pending = self.nodes.get_key_value(path).map(|(path, _)| path).into_iter().collect::<Vec<_>>();
+        while let Some(path) = pending.pop() {
+            if reached.insert(path) {
+                pending.extend(next(path).into_iter().filter(|next_path| !reached.contains(next_path)));
+            }
+        }
+        reached
+    }
+
+    /// Returns the derivation at `path` and every derivation it depends on,
+    /// directly or indirectly, like `nix-store --query --requisites`.
+    #[inline]
+    #[must_use]
+    pub fn requisites(&self, path: &StorePath) -> BTreeSet<&StorePath> {
+        self.reachable(path, |path| self.inputs(path))
+    }
+
+    /// Returns the derivation at `path` and every derivation that depends on it,
+    /// directly or indirectly, like `nix-store --query --referrers-closure`.
+    #[inline]
+    #[must_use]
+    pub fn referrers(&self, path: &StorePath) -> BTreeSet<&StorePath> {
+        let mut referrers = BTreeMap::<_, Vec<_>>::new();
+        for edge in self.edges() {
+            referrers.entry(edge.to).or_default().push(edge.from);
+        }
+        self.reachable(path, |path| referrers.get(path).cloned().unwrap_or_default())
+    }
+
+    /// Finds the shortest chains of input derivations from the derivation at
+    /// `root` to the nearest derivations for which `is_target` is true, like
+    /// `nix why-depends --derivation`. Each edge tells which outputs are used
+    /// from the next derivation.
+    ///
+    /// Returns the edges of one chain in order, or if `all`, the edges of every
+    /// shortest chain ordered by their distance from `root`. Chains share their
+    /// edges, so this stays small where the number of chains grows
+    /// exponentially. Returns `None` if no target can be reached, and no edges
+    /// if `root` itself is a target.
+    #[inline]
+    pub fn why_depends<'graph>(
+        &'graph self,
+        root: &StorePath,
+        mut is_target: impl FnMut(&StorePath) -> bool,
+        all: bool,
+    ) -> Option<Vec<DerivationEdge<'graph>>> {
+        let (root, _) = self.nodes.get_key_value(root)?;
+        // The edges that reach each derivation with the shortest chains from
+        // the root, found one distance at a time until a target is reached.
+        let mut predecessors = BTreeMap::<_, Vec<_>>::new();
+        let mut distances = BTreeMap::from([(root, 0_usize)]);
+        let mut layer = vec![root];
+        let mut distance = 0;
+        let targets = loop {
+            let targets = layer.iter().copied().filter(|path| is_target(path)).collect::<Vec<_>>();
+            if !targets.is_empty() {
+                break targets;
+            }
+            if layer.is_empty() {
+                return None;
+            }
+            distance += 1;
+            let mut next_layer = Vec::new();
+            for edge in layer.iter().flat_map(|path| self.dependencies(path)).filter(|edge| self.contains(edge.to)) {
+                match distances.get(edge.to) {
+                    None => {
+                        distances.insert(edge.to, distance);
+                        next_layer.push(edge.to);
+                        predecessors.entry(edge.to).or_default().push(edge);
+                    },
+                    Some(&to_distance) if to_distance == distance => {
+                        predecessors.entry(edge.to).or_default().push(edge);
+                    },
+                    Some(_) => {},
+                }
+            }
+            layer = next_layer;
+        };
+
+        // Walks back from the targets, keeping only the edges that lead to
+        // them and visiting each derivation once.
+        let limit = if all { usize::MAX } else { 1 };
+        let mut visited = BTreeSet::new();
+        let mut pending = targets.into_iter().take(limit).collect::<Vec<_>>();
+        let mut edges = Vec::new();
+        while let Some(path) = pending.pop() {
+            if !visited.insert(path) {
+                continue;
+            }
+            for &edge in predecessors.get(path).into_iter().flatten().take(limit) {
+                edges.push(edge);
+                pending.push(edge.from);
+            }
+        }
+        edges.sort_by_key(|edge| (distances.get(edge.from).copied(), edge.from, edge.to));
+        Some(edges)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::derivations::parsers::parse_derivation_bytes;
+    use std::env;
+    use std::path::Path;
+
+    fn edge_case_directory() -> PathBuf {
+        Path::new(&env::var_os("CARGO_MANIFEST_DIR").unwrap()).join("src/derivations/edge_case_derivations")
+    }
+
+    fn store_path(base_name: &str) -> StorePath {
+        StorePath::new(format!("/nix/store/{base_name}")).unwrap()
+    }
+
+    #[test]
+    fn load_closure() {
+        let empty_env = store_path("nkqgqq2l1fpyifm974rf8yhxc1gzmihx-empty-env.drv");
+        let hello = store_path("qhh9g08r8f6an2hly0p4rjh7q73myz93-hello-2.12.1.drv");
+        let tarball = store_path("0dkjx5z90qw7ci7qfhnl1p5l2ny9ba75-hello-2.12.1.tar.gz.drv");
+        let tools = store_path("13z54gdcxxfdiy5wrpghjshkr6235d93-bootstrap-tools.drv");
+        let stage0 = store_path("2pgbrs5z50fdncls60v4z5jnalm4l97b-bootstrap-stage0.drv");
+        let graph = DerivationGraph::load_from_directory(edge_case_directory(), [empty_env.clone()]).unwrap();
+
+        assert_eq!(graph.nodes().keys().collect::<Vec<_>>(), [&tarball, &tools, &stage0, &empty_env, &hello]);
+        assert_eq!(graph.get(&hello).and_then(Derivation::name), Some("hello-2.12.1"));
+        let edges = graph
+            .edges()
+            .map(|edge| (edge.from, edge.to, edge.input.value.iter().map(String::as_str).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        assert_eq!(edges, [
+            (&empty_env, &hello, vec!["doc", "man"]),
+            (&hello, &tarball, vec!["out"]),
+            (&hello, &tools, vec!["out"]),
+            (&hello, &stage0, vec!["out"]),
+        ]);
+        assert_eq!(graph.dependencies(&tarball).count(), 0);
+    }
+
+    fn edge_case_graph() -> DerivationGraph {
+        let roots = [
+            store_path("nkqgqq2l1fpyifm974rf8yhxc1gzmihx-empty-env.drv"),
+            store_path("ng1vcj72jjzc3a029xxvlb3xkrf4kl2h-use-hello.drv"),
+        ];
+        DerivationGraph::load_from_directory(edge_case_directory(), roots).unwrap()
+    }
+
+    fn base_names<'path>(paths: impl IntoIterator<Item = &'path StorePath>) -> Vec<&'path str> {
+        paths.into_iter().map(StorePath::base_name).collect()
+    }
+
+    #[test]
+    fn roots_and_leaves() {
+        let graph = edge_case_graph();
+        assert_eq!(base_names(graph.roots()), [
+            "ng1vcj72jjzc3a029xxvlb3xkrf4kl2h-use-hello.drv",
+            "nkqgqq2l1fpyifm974rf8yhxc1gzmihx-empty-env.drv",
+        ]);
+        assert_eq!(base_names(graph.leaves()), [
+            "0dkjx5z90qw7ci7qfhnl1p5l2ny9ba75-hello-2.12.1.tar.gz.drv",
+            "13z54gdcxxfdiy5wrpghjshkr6235d93-bootstrap-tools.drv",
+            "2pgbrs5z50fdncls60v4z5jnalm4l97b-bootstrap-stage0.drv",
+            "hnm5s456v5wky252cv71ni8cqaphf11i-hello.drv.drv",
+        ]);
+    }
+
+    #[test]
+    fn topological_order() {
+        let graph = edge_case_graph();
+        let order = graph.topological_order().unwrap();
+        assert_eq!(order.len(), graph.len());
+        for edge in graph.edges() {
+            let position = |path| order.iter().position(|&ordered| ordered == path).unwrap();
+            assert!(position(edge.to) < position(edge.from), "{} is built before {}", edge.from, edge.to);
+        }
+    }
+
+    #[test]
+    fn depths() {
+        let graph = edge_case_graph();
+        let depths = graph.depths().unwrap();
+        let depths = depths.iter().map(|(path, &depth)| (path.base_name(), depth)).collect::<Vec<_>>();
+        assert_eq!(depths, [
+            ("0dkjx5z90qw7ci7qfhnl1p5l2ny9ba75-hello-2.12.1.tar.gz.drv", 2),
+            ("13z54gdcxxfdiy5wrpghjshkr6235d93-bootstrap-tools.drv", 2),
+            ("2pgbrs5z50fdncls60v4z5jnalm4l97b-bootstrap-stage0.drv", 2),
+            ("hnm5s456v5wky252cv71ni8cqaphf11i-hello.drv.drv", 1),
+            ("ng1vcj72jjzc3a029xxvlb3xkrf4kl2h-use-hello.drv", 0),
+            ("nkqgqq2l1fpyifm974rf8yhxc1gzmihx-empty-env.drv", 0),
+            ("qhh9g08r8f6an2hly0p4rjh7q73myz93-hello-2.12.1.drv", 1),
+        ]);
+    }
+
+    #[test]
+    fn requisites_and_referrers() {
+        let graph = edge_case_graph();
+        let hello = store_path("qhh9g08r8f6an2hly0p4rjh7q73myz93-hello-2.12.1.drv");
+        assert_eq!(base_names(graph.requisites(&hello)), [
+            "0dkjx5z90qw7ci7qfhnl1p5l2ny9ba75-hello-2.12.1.tar.gz.drv",
+            "13z54gdcxxfdiy5wrpghjshkr6235d93-bootstrap-tools.drv",
+            "2pgbrs5z50fdncls60v4z5jnalm4l97b-bootstrap-stage0.drv",
+            "qhh9g08r8f6an2hly0p4rjh7q73myz93-hello-2.12.1.drv",
+        ]);
+        let tarball = store_path("0dkjx5z90qw7ci7qfhnl1p5l2ny9ba75-hello-2.12.1.tar.gz.drv");
+        assert_eq!(base_names(graph.referrers(&tarball)), [
+            "0dkjx5z90qw7ci7qfhnl1p5l2ny9ba75-hello-2.12.1.tar.gz.drv",
+            "nkqgqq2l1fpyifm974rf8yhxc1gzmihx-empty-env.drv",
+            "qhh9g08r8f6an2hly0p4rjh7q73myz93-hello-2.12.1.drv",
+        ]);
+        assert!(graph.requisites(&store_path("00000000000000000000000000000000-missing.drv")).is_empty());
+    }
+
+    #[test]
+    fn cycle() {
+        let mut resolver = |path: &StorePath| {
+            let input = match path.name() {
+                "a.drv" => "/nix/store/00000000000000000000000000000000-b.drv",
+                "b.drv" => "/nix/store/00000000000000000000000000000000-c.drv",
+                _ => "/nix/store/00000000000000000000000000000000-b.drv",
+            };
+            let drv = format!(r#"Derive([("out","","","")],[("{input}",["out"])],[],"x86_64-linux","/bin/sh",[],[])"#);
+            parse_derivation_bytes(drv.as_bytes())
+        };
+        let root = store_path("00000000000000000000000000000000-a.drv");
+        let graph = DerivationGraph::load(&mut resolver, [root]).unwrap();
+        let err = graph.topological_order().unwrap_err();
+        assert_eq!(base_names(&err.cycle), [
+            "00000000000000000000000000000000-b.drv",
+            "00000000000000000000000000000000-c.drv",
+            "00000000000000000000000000000000-b.drv",
+        ]);
+        assert!(graph.depths().is_err());
+        assert_eq!(graph.roots().len(), 1);
+        assert_eq!(graph.requisites(&store_path("00000000000000000000000000000000-c.drv")).len(), 2);
+    }
+
+    #[test]
+    fn why_depends() {
+        let graph = edge_case_graph();
+        let empty_env = store_path("nkqgqq2l1fpyifm974rf8yhxc1gzmihx-empty-env.drv");
+        let is_bootstrap = |path: &StorePath| path.name().starts_with("bootstrap-");
+        let chain = graph.why_depends(&empty_env, is_bootstrap, false).unwrap();
+        let hops = chain.iter().map(|edge| (edge.to.base_name(), edge.input.output_names())).collect::<Vec<_>>();
+        assert_eq!(hops, [
+            ("qhh9g08r8f6an2hly0p4rjh7q73myz93-hello-2.12.1.drv", vec!["doc".to_owned(), "man".to_owned()]),
+            ("13z54gdcxxfdiy5wrpghjshkr6235d93-bootstrap-tools.drv", vec!["out".to_owned()]),
+        ]);
+        assert_eq!(chain[0].from, &empty_env);
+
+        let edges = graph.why_depends(&empty_env, is_bootstrap, true).unwrap();
+        let targets = edges.iter().filter(|edge| is_bootstrap(edge.to)).map(|edge| edge.to);
+        assert_eq!(base_names(targets), [
+            "13z54gdcxxfdiy5wrpghjshkr6235d93-bootstrap-tools.drv",
+            "2pgbrs5z50fdncls60v4z5jnalm4l97b-bootstrap-stage0.drv",
+        ]);
+
+        assert_eq!(graph.why_depends(&empty_env, |path| *path == empty_env, false), Some(Vec::new()));
+        let use_hello = store_path("ng1vcj72jjzc3a029xxvlb3xkrf4kl2h-use-hello.drv");
+        assert_eq!(graph.why_depends(&use_hello, is_bootstrap, true), None);
+    }
+
+    #[test]
+    fn why_depends_all_shortest_chains() {
+        let mut resolver = |path: &StorePath| {
+            let inputs = match path.name() {
+                "a.drv" => concat!(
+                    r#"[("/nix/store/00000000000000000000000000000000-b.drv",["out"]),"#,
+                    r#"("/nix/store/00000000000000000000000000000000-c.drv",["dev"])]"#,
+                ),
+                "b.drv" | "c.drv" => r#"[("/nix/store/00000000000000000000000000000000-d.drv",["out"])]"#,
+                _ => "[]",
+            };
+            let drv = format!(r#"Derive([("out","","","")],{inputs},[],"x86_64-linux","/bin/sh",[],[])"#);
+            parse_derivation_bytes(drv.as_bytes())
+        };
+        let root = store_path("00000000000000000000000000000000-a.drv");
+        let graph = DerivationGraph::load(&mut resolver, [root.clone()]).unwrap();
+        let edges = graph.why_depends(&root, |path| path.name() == "d.drv", true).unwrap();
+        let edges = edges.iter().map(|edge| (edge.from.name(), edge.to.name())).collect::<Vec<_>>();
+        assert_eq!(edges, [("a.drv", "b.drv"), ("a.drv", "c.drv"), ("b.drv", "d.drv"), ("c.drv", "d.drv")]);
+    }
+
+    #[test]
+    fn why_depends_all_many_chains() {
+        // 40 diamonds in a row have 2^40 shortest chains but only 160 edges.
+        let diamonds = 40;
+        let path = |name: String| format!("/nix/store/00000000000000000000000000000000-{name}.drv");
+        let mut resolver = |drv_path: &StorePath| {
+            let name = drv_path.name().strip_suffix(".drv").unwrap();
+            let inputs = match name.split_once('-') {
+                Some((_, index)) => vec![path(format!("join{index}"))],
+                None => match name.strip_prefix("join").unwrap().parse::<usize>().unwrap() {
+                    index if index == diamonds => Vec::new(),
+                    index => vec![path(format!("left-{}", index + 1)), path(format!("right-{}", index + 1))],
+                },
+            };
+            let inputs = inputs.iter().map(|input| format!(r#"("{input}",["out"])"#)).collect::<Vec<_>>().join(",");
+            let drv = format!(r#"Derive([("out","","","")],[{inputs}],[],"x86_64-linux","/bin/sh",[],[])"#);
+            parse_derivation_bytes(drv.as_bytes())
+        };
+        let root = store_path("00000000000000000000000000000000-join0.drv");
+        let graph = DerivationGraph::load(&mut resolver, [root.clone()]).unwrap();
+        let target = format!("join{diamonds}.drv");
+        let edges = graph.why_depends(&root, |path| path.name() == target, true).unwrap();
+        assert_eq!(edges.len(), diamonds * 4);
+    }
+
+    #[test]
+    fn shared_inputs_loaded_once() {
+        let mut loaded = Vec::new();
+        let mut resolver = |path: &StorePath| {
+            loaded.push(path.clone());
+            FileResolver::in_directory(edge_case_directory()).resolve(path)
+        };
+        let roots = [
+            store_path("nkqgqq2l1fpyifm974rf8yhxc1gzmihx-empty-env.drv"),
+            store_path("qhh9g08r8f6an2hly0p4rjh7q73myz93-hello-2.12.1.drv"),
+        ];
+        let graph = DerivationGraph::load(&mut resolver, roots).unwrap();
+        assert_eq!(graph.len(), 5);
+        assert_eq!(loaded.len(), 5);
+    }
+
+    #[test]
+    fn load_errors() {
+        let missing = store_path("00000000000000000000000000000000-missing.drv");
+        assert!(matches!(
+            DerivationGraph::load_from_directory(edge_case_directory(), [missing.clone()]),
+            Err(GraphError::Resolve(path, ReadDerivationError::Io(_))) if path == missing
+        ));
+
+        let source = store_path("00000000000000000000000000000000-source");
+        assert!(matches!(
+            DerivationGraph::load_from_directory(edge_case_directory(), [source.clone()]),
+            Err(GraphError::NotDerivation(path)) if path == source
+        ));
+    }
+}
@@ -0,0 +1,60 @@
+use crate::store_paths::types::StorePath;
+
+use core::fmt;
+use std::error::Error;
+
+/// An error produced when a derivation graph can not be loaded.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum GraphError<E> {
+    /// The path is not the path of a derivation.
+    NotDerivation(StorePath),
+    /// The derivation with the given path could not be loaded.
+    Resolve(StorePath, E),
+}
+
+impl<E: fmt::Display> fmt::Display for GraphError<E> {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::NotDerivation(ref path) => write!(formatter, "`{path}` is not a `.drv` path"),
+            Self::Resolve(ref path, ref err) => write!(formatter, "failed to load `{path}`: {err}"),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for GraphError<E> {
+    #[inline]
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            Self::Resolve(_, ref err) => Some(err),
+            Self::NotDerivation(_) => None,
+        }
+    }
+}
+
+/// An error produced when the derivations of a graph depend on each other in a
+/// cycle, which only happens in corrupted stores.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CycleError {
+    /// The paths on the cycle, each one an input of the one before it. The first
+    /// path is repeated at the end.
+    pub cycle: Vec<StorePath>,
+}
+
+impl fmt::Display for CycleError {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("dependency cycle: ")?;
+        for (index, path) in self.cycle.iter().enumerate() {
+            if index > 0 {
+                formatter.write_str(" -> ")?;
+            }
+            write!(formatter, "`{path}`")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for CycleError {}
@@ -0,0 +1,368 @@
+use crate::derivations::graphs::{
+    DerivationEdge,
+    DerivationGraph,
+};
+use crate::store_paths::types::StorePath;
+
+use std::collections::{
+    BTreeMap,
+    BTreeSet,
+};
+use std::io::{
+    self,
+    Write,
+};
+
+/// Returns whether `name` matches the glob `pattern`, in which `*` matches any
+/// run of characters and `?` matches any one character.
+#[inline]
+#[must_use]
+pub fn matches_glob(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    let (mut pattern_index, mut name_index) = (0, 0);
+    // The position after the last `*` and the position in `name` it matches up
+    // to, to backtrack to when the rest of the pattern does not match.
+    let mut backtrack = None;
+    while name_index < name.len() {
+        match pattern.get(pattern_index) {
+            Some(&'*') => {
+                pattern_index += 1;
+                backtrack = Some((pattern_index, name_index));
+            },
+            Some(&character) if character == '?' || name.get(name_index) == Some(&character) => {
+                pattern_index += 1;
+                name_index += 1;
+            },
+            _ => {
+                let Some((star_index, star_name_index)) = backtrack else {
+                    return false;
+                };
+                pattern_index = star_index;
+                name_index = star_name_index + 1;
+                backtrack = Some((star_index, name_index));
+            },
+        }
+    }
+    pattern.get(pattern_index..).is_some_and(|rest| rest.iter().all(|&character| character == '*'))
+}
+
+/// Returns the name a derivation is shown with: the name of its store path
+/// without `.drv`.
+fn node_label(path: &StorePath) -> &str {
+    path.name().strip_suffix(".drv").unwrap_or(path.name())
+}
+
+/// Returns the label of an edge: the names of the outputs that are used.
+fn edge_label(edge: &DerivationEdge<'_>) -> String {
+    edge.input.output_names().join(",")
+}
+
+/// Selects which part of a `DerivationGraph` is exported.
+///
+/// By default every derivation is exported.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExportOptions {
+    max_depth: Option<usize>,
+    pattern: Option<String>,
+}
+
+impl ExportOptions {
+    /// Creates options that export every derivation.
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            max_depth: None,
+            pattern: None,
+        }
+    }
+
+    /// Only exports derivations at most `max_depth` input derivations away from
+    /// a root of the graph.
+    #[inline]
+    #[must_use]
+    pub const fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Only exports derivations whose names match the glob `pattern`, e.g.
+    /// `hello-*`, and the edges between them. Names are matched without `.drv`.
+    #[inline]
+    #[must_use]
+    pub fn with_pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.pattern = Some(pattern.into());
+        self
+    }
+
+    /// Returns the paths of the derivations in `graph` that are exported.
+    fn select<'graph>(&self, graph: &'graph DerivationGraph) -> BTreeSet<&'graph StorePath> {
+        let mut selected = match self.max_depth {
+            None => graph.nodes().keys().collect(),
+            Some(max_depth) => {
+                let mut reached = BTreeSet::new();
+                let mut frontier = graph.roots();
+                for _ in 0..=max_depth {
+                    reached.extend(frontier.iter().copied());
+                    frontier = frontier
+                        .iter()
+                        .flat_map(|path| graph.dependencies(path))
+                        .map(|edge| edge.to)
+                        .filter(|path| graph.contains(path) && !reached.contains(path))
+                        .collect();
+                }
+                reached
+            },
+        };
+        if let Some(ref pattern) = self.pattern {
+            selected.retain(|path| matches_glob(pattern, node_label(path)));
+        }
+        selected
+    }
+}
+
+/// The derivations and edges of a `DerivationGraph` that are exported.
+struct Selection<'graph> {
+    nodes: BTreeSet<&'graph StorePath>,
+    edges: Vec<DerivationEdge<'graph>>,
+}
+
+impl<'graph> Selection<'graph> {
+    fn new(graph: &'graph DerivationGraph, options: &ExportOptions) -> Self {
+        let nodes = options.select(graph);
+        let edges = graph.edges().filter(|edge| nodes.contains(edge.from) && nodes.contains(edge.to)).collect();
+        Self { nodes, edges }
+    }
+}
+
+/// Renders a string as a quoted DOT identifier. Line breaks are escaped too,
+/// since the output names in edge labels may contain them.
+fn write_dot_string<W: Write>(writer: &mut W, string: &str) -> io::Result<()> {
+    writer.write_all(b"\"")?;
+    for character in string.chars() {
+        match character {
+            '\\' => writer.write_all(b"\\\\")?,
+            '"' => writer.write_all(b"\\\"")?,
+            '\n' => writer.write_all(b"\\n")?,
+            '\r' => writer.write_all(b"\\r")?,
+            _ => writer.write_all(character.encode_utf8(&mut [0; 4]).as_bytes())?,
+        }
+    }
+    writer.write_all(b"\"")
+}
+
+/// Renders the selected part of `graph` in the GraphViz DOT language. Nodes are
+/// identified by their store paths and edges point to input derivations.
+///
+/// # Errors
+///
+/// Returns an error if writing to `writer` fails.
+#[inline]
+pub fn write_dot<W: Write>(writer: &mut W, graph: &DerivationGraph, options: &ExportOptions) -> io::Result<()> {
+    let selection = Selection::new(graph, options);
+    writer.write_all(b"digraph derivations {\n")?;
+    for path in selection.nodes {
+        writer.write_all(b"  ")?;
+        write_dot_string(writer, path.as_str())?;
+        writer.write_all(b" [label=")?;
+        write_dot_string(writer, node_label(path))?;
+        writer.write_all(b"];\n")?;
+    }
+    for edge in selection.edges {
+        writer.write_all(b"  ")?;
+        write_dot_string(writer, edge.from.as_str())?;
+        writer.write_all(b" -> ")?;
+        write_dot_string(writer, edge.to.as_str())?;
+        writer.write_all(b" [label=")?;
+        write_dot_string(writer, &edge_label(&edge))?;
+        writer.write_all(b"];\n")?;
+    }
+    writer.write_all(b"}\n")
+}
+
+/// Escapes the characters XML gives a special meaning.
+fn escape_xml(string: &str) -> String {
+    string.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Renders the selected part of `graph` as GraphML. Nodes are identified by
+/// their store paths and have a `name` attribute; edges have an `outputs`
+/// attribute.
+///
+/// # Errors
+///
+/// Returns an error if writing to `writer` fails.
+#[inline]
+pub fn write_graphml<W: Write>(writer: &mut W, graph: &DerivationGraph, options: &ExportOptions) -> io::Result<()> {
+    let selection = Selection::new(graph, options);
+    writer.write_all(b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n")?;
+    writer.write_all(b"<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n")?;
+    writer.write_all(b"  <key id=\"name\" for=\"node\" attr.name=\"name\" attr.type=\"string\"/>\n")?;
+    writer.write_all(b"  <key id=\"outputs\" for=\"edge\" attr.name=\"outputs\" attr.type=\"string\"/>\n")?;
+    writer.write_all(b"  <graph id=\"derivations\" edgedefault=\"directed\">\n")?;
+    for path in selection.nodes {
+        writeln!(
+            writer,
+            "    <node id=\"{}\"><data key=\"name\">{}</data></node>",
+            escape_xml(path.as_str()),
+            escape_xml(node_label(path)),
+        )?;
+    }
+    for edge in selection.edges {
+        writeln!(
+            writer,
+            "    <edge source=\"{}\" target=\"{}\"><data key=\"outputs\">{}</data></edge>",
+            escape_xml(edge.from.as_str()),
+            escape_xml(edge.to.as_str()),
+            escape_xml(&edge_label(&edge)),
+        )?;
+    }
+    writer.write_all(b"  </graph>\n</graphml>\n")
+}
+
+/// Escapes a Mermaid label, which can not contain double quotes or line
+/// breaks. Line breaks become `<br>`, so other tags and the `#` that starts
+/// entity codes are escaped too.
+fn escape_mermaid(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len());
+    for character in string.chars() {
+        match character {
+            '#' => escaped.push_str("#35;"),
+            '"' => escaped.push_str("#quot;"),
+            '<' => escaped.push_str("#lt;"),
+            '>' => escaped.push_str("#gt;"),
+            '\n' => escaped.push_str("<br>"),
+            '\r' => escaped.push_str("#13;"),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+/// Renders the selected part of `graph` as a Mermaid flowchart. Mermaid ids can
+/// not be store paths, so nodes are numbered in the order of their paths.
+///
+/// # Errors
+///
+/// Returns an error if writing to `writer` fails.
+#[inline]
+pub fn write_mermaid<W: Write>(writer: &mut W, graph: &DerivationGraph, options: &ExportOptions) -> io::Result<()> {
+    let selection = Selection::new(graph, options);
+    let ids = selection.nodes.iter().enumerate().map(|(index, &path)| (path, index)).collect::<BTreeMap<_, _>>();
+    writer.write_all(b"flowchart TD\n")?;
+    for (path, id) in &ids {
+        writeln!(writer, "  n{id}[\"{}\"]", escape_mermaid(node_label(path)))?;
+    }
+    for edge in selection.edges {
+        let (Some(from), Some(to)) = (ids.get(edge.from), ids.get(edge.to)) else {
+            continue;
+        };
+        writeln!(writer, "  n{from} -->|\"{}\"| n{to}", escape_mermaid(&edge_label(&edge)))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::path::Path;
+
+    fn edge_case_graph() -> DerivationGraph {
+        let manifest_directory = env::var_os("CARGO_MANIFEST_DIR").unwrap();
+        let directory = Path::new(&manifest_directory).join("src/derivations/edge_case_derivations");
+        let root = StorePath::new("/nix/store/nkqgqq2l1fpyifm974rf8yhxc1gzmihx-empty-env.drv").unwrap();
+        DerivationGraph::load_from_directory(directory, [root]).unwrap()
+    }
+
+    fn export(
+        write: fn(&mut Vec<u8>, &DerivationGraph, &ExportOptions) -> io::Result<()>,
+        options: &ExportOptions,
+    ) -> String {
+        let mut exported = Vec::new();
+        write(&mut exported, &edge_case_graph(), options).unwrap();
+        String::from_utf8(exported).unwrap()
+    }
+
+    #[test]
+    fn glob() {
+        assert!(matches_glob("hello-*", "hello-2.12.1"));
+        assert!(matches_glob("*hello*", "hello-2.12.1.tar.gz"));
+        assert!(matches_glob("bootstrap-stage?", "bootstrap-stage0"));
+        assert!(matches_glob("*a*b", "aab"));
+        assert!(matches_glob("*", ""));
+        assert!(!matches_glob("hello", "hello-2.12.1"));
+        assert!(!matches_glob("*-stage?", "bootstrap-stage10"));
+    }
+
+    #[test]
+    fn dot() {
+        assert_eq!(export(write_dot, &ExportOptions::new().with_max_depth(1)), concat!(
+            "digraph derivations {\n",
+            "  \"/nix/store/nkqgqq2l1fpyifm974rf8yhxc1gzmihx-empty-env.drv\" [label=\"empty-env\"];\n",
+            "  \"/nix/store/qhh9g08r8f6an2hly0p4rjh7q73myz93-hello-2.12.1.drv\" [label=\"hello-2.12.1\"];\n",
+            "  \"/nix/store/nkqgqq2l1fpyifm974rf8yhxc1gzmihx-empty-env.drv\" -> ",
+            "\"/nix/store/qhh9g08r8f6an2hly0p4rjh7q73myz93-hello-2.12.1.drv\" [label=\"doc,man\"];\n",
+            "}\n",
+        ));
+    }
+
+    #[test]
+    fn graphml() {
+        assert_eq!(export(write_graphml, &ExportOptions::new().with_max_depth(1)), concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"name\" for=\"node\" attr.name=\"name\" attr.type=\"string\"/>\n",
+            "  <key id=\"outputs\" for=\"edge\" attr.name=\"outputs\" attr.type=\"string\"/>\n",
+            "  <graph id=\"derivations\" edgedefault=\"directed\">\n",
+            "    <node id=\"/nix/store/nkqgqq2l1fpyifm974rf8yhxc1gzmihx-empty-env.drv\">",
+            "<data key=\"name\">empty-env</data></node>\n",
+            "    <node id=\"/nix/store/qhh9g08r8f6an2hly0p4rjh7q73myz93-hello-2.12.1.drv\">",
+            "<data key=\"name\">hello-2.12.1</data></node>\n",
+            "    <edge source=\"/nix/store/nkqgqq2l1fpyifm974rf8yhxc1gzmihx-empty-env.drv\" ",
+            "target=\"/nix/store/qhh9g08r8f6an2hly0p4rjh7q73myz93-hello-2.12.1.drv\">",
+            "<data key=\"outputs\">doc,man</data></edge>\n",
+            "  </graph>\n",
+            "</graphml>\n",
+        ));
+    }
+
+    #[test]
+    fn mermaid() {
+        assert_eq!(export(write_mermaid, &ExportOptions::new()), concat!(
+            "flowchart TD\n",
+            "  n0[\"hello-2.12.1.tar.gz\"]\n",
+            "  n1[\"bootstrap-tools\"]\n",
+            "  n2[\"bootstrap-stage0\"]\n",
+            "  n3[\"empty-env\"]\n",
+            "  n4[\"hello-2.12.1\"]\n",
+            "  n3 -->|\"doc,man\"| n4\n",
+            "  n4 -->|\"out\"| n0\n",
+            "  n4 -->|\"out\"| n1\n",
+            "  n4 -->|\"out\"| n2\n",
+        ));
+    }
+
+    #[test]
+    fn dot_escapes() {
+        let mut escaped = Vec::new();
+        write_dot_string(&mut escaped, "a\\b\"c\nd\re").unwrap();
+        assert_eq!(String::from_utf8(escaped).unwrap(), r#""a\\b\"c\nd\re""#);
+    }
+
+    #[test]
+    fn mermaid_escapes() {
+        assert_eq!(escape_mermaid("a#b\"c\nd\re<f>"), "a#35;b#quot;c<br>d#13;e#lt;f#gt;");
+    }
+
+    #[test]
+    fn pattern() {
+        assert_eq!(export(write_mermaid, &ExportOptions::new().with_pattern("hello-*")), concat!(
+            "flowchart TD\n",
+            "  n0[\"hello-2.12.1.tar.gz\"]\n",
+            "  n1[\"hello-2.12.1\"]\n",
+            "  n1 -->|\"out\"| n0\n",
+        ));
+    }
+}
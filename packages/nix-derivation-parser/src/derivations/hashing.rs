@@ -0,0 +1,386 @@
+use crate::derivations::errors::{
+    HashModuloError,
+    ReadDerivationError,
+};
+use crate::derivations::parsers::read_derivation;
+use crate::derivations::renderers::render_derivation_modulo;
+use crate::derivations::types::{
+    Derivation,
+    DerivationHash,
+    DerivationHashKind,
+    DerivationInput,
+    DerivationOutput,
+};
+use crate::hashes::types::{
+    Hash,
+    HashEncoding,
+};
+use crate::store_paths::types::StorePath;
+
+use std::collections::{
+    BTreeMap,
+    BTreeSet,
+};
+use std::fs::File;
+use std::path::PathBuf;
+
+/// Loads derivations by their store paths, e.g. from the store or from memory.
+///
+/// Any `FnMut(&StorePath) -> Result<Derivation, E>` is a resolver.
+pub trait DerivationResolver {
+    /// The error produced when a derivation can not be loaded.
+    type Error;
+
+    /// Loads the derivation at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the derivation can not be loaded.
+    fn resolve(&mut self, path: &StorePath) -> Result<Derivation, Self::Error>;
+}
+
+impl<F, E> DerivationResolver for F
+where
+    F: FnMut(&StorePath) -> Result<Derivation, E> {
+    type Error = E;
+
+    #[inline]
+    fn resolve(&mut self, path: &StorePath) -> Result<Derivation, E> {
+        self(path)
+    }
+}
+
+/// Reads derivations from their `.drv` files.
+///
+/// By default each file is read from its store path. A resolver made with
+/// `in_directory` reads files with the same base names from another directory,
+/// e.g. `.drv` files copied out of the store.
+#[derive(Debug, Clone, Default)]
+pub struct FileResolver {
+    directory: Option<PathBuf>,
+}
+
+impl FileResolver {
+    /// Creates a resolver that reads derivations from their store paths.
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { directory: None }
+    }
+
+    /// Creates a resolver that reads derivations from `directory`.
+    #[inline]
+    #[must_use]
+    pub fn in_directory(directory: impl Into<PathBuf>) -> Self {
+        Self { directory: Some(directory.into()) }
+    }
+}
+
+impl DerivationResolver for FileResolver {
+    type Error = ReadDerivationError;
+
+    #[inline]
+    fn resolve(&mut self, path: &StorePath) -> Result<Derivation, ReadDerivationError> {
+        let file = match self.directory {
+            Some(ref directory) => File::open(directory.join(path.base_name()))?,
+            None => File::open(path)?,
+        };
+        read_derivation(file)
+    }
+}
+
+/// Computes Nix's `hashDerivationModulo`, following `derivations.cc`.
+///
+/// The hash of every input derivation is remembered, so hashing many
+/// derivations of the same closure loads and hashes each input only once.
+#[derive(Debug)]
+pub struct DerivationHasher<R> {
+    resolver: R,
+    hashes: BTreeMap<StorePath, DerivationHash>,
+    /// The input derivations whose hashes are being computed, to detect cycles.
+    in_progress: BTreeSet<StorePath>,
+}
+
+impl<R: DerivationResolver> DerivationHasher<R> {
+    /// Creates a hasher that loads input derivations with `resolver`.
+    #[inline]
+    #[must_use]
+    pub const fn new(resolver: R) -> Self {
+        Self {
+            resolver,
+            hashes: BTreeMap::new(),
+            in_progress: BTreeSet::new(),
+        }
+    }
+
+    /// Computes the hash of `derivation`, with each of its input derivations
+    /// replaced by their own hashes.
+    ///
+    /// If `mask_outputs`, the paths of the outputs are left out, which is how Nix
+    /// hashes a derivation to compute those paths. Input derivations are always
+    /// hashed with their output paths.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `HashModuloError` if an input derivation can not be loaded,
+    /// lacks an output that is used from it, or depends on itself.
+    #[inline]
+    pub fn hash_derivation_modulo(
+        &mut self,
+        derivation: &Derivation,
+        mask_outputs: bool,
+    ) -> Result<DerivationHash, HashModuloError<R::Error>> {
+        if derivation.is_fixed_output() {
+            let hashes = derivation.outputs.iter().filter_map(|(name, output)| match *output {
+                DerivationOutput::CaFixed { ref path, method, ref hash } => {
+                    let base16 = hash.encode(HashEncoding::Base16);
+                    let fingerprint = format!("fixed:out:{}{}:{base16}:{path}", method.prefix(), hash.algo());
+                    Some((name.clone(), Hash::sha256(fingerprint.as_bytes())))
+                },
+                _ => None,
+            });
+            return Ok(DerivationHash {
+                hashes: hashes.collect(),
+                kind: DerivationHashKind::Regular,
+            });
+        }
+
+        // Like Nix, impure derivations are never hashed: their outputs all share a
+        // constant hash, since they may differ on every build anyway.
+        if derivation.is_impure() {
+            let hash = Hash::sha256(b"impure");
+            return Ok(DerivationHash {
+                hashes: derivation.outputs.keys().map(|name| (name.clone(), hash.clone())).collect(),
+                kind: DerivationHashKind::Deferred,
+            });
+        }
+
+        // Like Nix, deferred input-addressed outputs do not make the hash deferred
+        // by themselves, only floating content-addressed outputs do.
+        let is_floating = derivation.outputs.values().any(|output| matches!(*output, DerivationOutput::CaFloating { .. }));
+        let mut kind = if is_floating {
+            DerivationHashKind::Deferred
+        } else {
+            DerivationHashKind::Regular
+        };
+        // Like Nix, inputs are keyed by the base-16 hash of the output they use, so
+        // outputs of the same derivation with the same hash are grouped together.
+        let mut input_drvs = BTreeMap::<String, DerivationInput>::new();
+        for (path, input) in &derivation.input_drvs {
+            // Like Nix, inputs that use dynamic outputs are left out, since they have
+            // to be built and resolved first.
+            if !input.dynamic_outputs.is_empty() {
+                kind = DerivationHashKind::Deferred;
+                continue;
+            }
+            let input_hash = self.path_hash_modulo(path)?;
+            if input_hash.kind == DerivationHashKind::Deferred {
+                kind = DerivationHashKind::Deferred;
+            }
+            for output in &input.value {
+                let hash = input_hash
+                    .hashes
+                    .get(output)
+                    .ok_or_else(|| HashModuloError::MissingOutput(path.clone(), output.clone()))?;
+                input_drvs.entry(hash.encode(HashEncoding::Base16)).or_default().value.insert(output.clone());
+            }
+        }
+        let hash = Hash::sha256(&render_derivation_modulo(derivation, &input_drvs, mask_outputs));
+        Ok(DerivationHash {
+            hashes: derivation.outputs.keys().map(|name| (name.clone(), hash.clone())).collect(),
+            kind,
+        })
+    }
+
+    /// Loads the derivation at `path` and computes its hash with its output
+    /// paths, or returns the hash computed before.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `HashModuloError` if the derivation or one of its inputs can not
+    /// be loaded, lacks an output that is used from it, or depends on itself.
+    #[inline]
+    pub fn path_hash_modulo(&mut self, path: &StorePath) -> Result<&DerivationHash, HashModuloError<R::Error>> {
+        if !self.hashes.contains_key(path) {
+            if !self.in_progress.insert(path.clone()) {
+                return Err(HashModuloError::Cycle(path.clone()));
+            }
+            let hash = self
+                .resolver
+                .resolve(path)
+                .map_err(|err| HashModuloError::Resolve(path.clone(), err))
+                .and_then(|derivation| self.hash_derivation_modulo(&derivation, false));
+            self.in_progress.remove(path);
+            self.hashes.insert(path.clone(), hash?);
+        }
+        Ok(self.hashes.get(path).expect("The hash was just inserted."))
+    }
+
+    /// Returns the resolver.
+    #[inline]
+    #[must_use]
+    pub fn into_resolver(self) -> R {
+        self.resolver
+    }
+}
+
+impl Derivation {
+    /// Computes Nix's `hashDerivationModulo` for this derivation, loading input
+    /// derivations with `resolver`. See `DerivationHasher::hash_derivation_modulo`.
+    ///
+    /// Use a `DerivationHasher` to hash several derivations from the same
+    /// closure, so shared inputs are only hashed once.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `HashModuloError` if an input derivation can not be loaded or
+    /// lacks an output that is used from it.
+    #[inline]
+    pub fn hash_modulo<R: DerivationResolver>(
+        &self,
+        resolver: R,
+        mask_outputs: bool,
+    ) -> Result<DerivationHash, HashModuloError<R::Error>> {
+        DerivationHasher::new(resolver).hash_derivation_modulo(self, mask_outputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::derivations::parsers::parse_derivation;
+    use std::path::Path;
+
+    fn edge_cases() -> PathBuf {
+        Path::new(&std::env::var_os("CARGO_MANIFEST_DIR").unwrap()).join("src/derivations/edge_case_derivations")
+    }
+
+    fn store_path(base_name: &str) -> StorePath {
+        StorePath::new(format!("/nix/store/{base_name}")).unwrap()
+    }
+
+    fn hex(hash: &DerivationHash, output: &str) -> String {
+        hash.hashes[output].encode(HashEncoding::Base16)
+    }
+
+    #[test]
+    fn fixed_output() {
+        let mut hasher = DerivationHasher::new(FileResolver::in_directory(edge_cases()));
+        let fetch = store_path("0dkjx5z90qw7ci7qfhnl1p5l2ny9ba75-hello-2.12.1.tar.gz.drv");
+        let hash = hasher.path_hash_modulo(&fetch).unwrap();
+        assert_eq!(hash.kind, DerivationHashKind::Regular);
+        assert_eq!(hex(hash, "out"), "796494f6dd0e991c3407b5bbcbbbcff794bd0cf6d2198a9ebd2c3f4099c43a90");
+    }
+
+    #[test]
+    fn input_addressed() {
+        let hello = store_path("qhh9g08r8f6an2hly0p4rjh7q73myz93-hello-2.12.1.drv");
+        let derivation = FileResolver::in_directory(edge_cases()).resolve(&hello).unwrap();
+        let masked = derivation.hash_modulo(FileResolver::in_directory(edge_cases()), true).unwrap();
+        assert_eq!(masked.hashes.keys().collect::<Vec<_>>(), ["doc", "man", "out"]);
+        assert_eq!(hex(&masked, "doc"), "111f065b159d22c3d892d92e6549e4fff320973e9defb763e5115e1491c6df51");
+        let mut hasher = DerivationHasher::new(FileResolver::in_directory(edge_cases()));
+        let unmasked = hasher.path_hash_modulo(&hello).unwrap();
+        assert_eq!(hex(unmasked, "out"), "5dfc1530fcd1ed63ceb743ddb4fd1d2da324eb88286b43116ca5c9da9bb0acb0");
+    }
+
+    #[test]
+    fn memoized() {
+        let mut loaded = Vec::new();
+        let mut hasher = DerivationHasher::new(|path: &StorePath| {
+            loaded.push(path.name().to_string());
+            FileResolver::in_directory(edge_cases()).resolve(path)
+        });
+        let empty_env = store_path("nkqgqq2l1fpyifm974rf8yhxc1gzmihx-empty-env.drv");
+        let derivation = hasher.resolver.resolve(&empty_env).unwrap();
+        let hash = hasher.hash_derivation_modulo(&derivation, true).unwrap();
+        assert_eq!(hex(&hash, "out"), "27329ed3b21bc9c85d76f0751d2ed5dac858a7792bd181be6686cb5072f6780b");
+        hasher.path_hash_modulo(&store_path("qhh9g08r8f6an2hly0p4rjh7q73myz93-hello-2.12.1.drv")).unwrap();
+        drop(hasher);
+        assert_eq!(loaded, [
+            "empty-env.drv",
+            "hello-2.12.1.drv",
+            "hello-2.12.1.tar.gz.drv",
+            "bootstrap-tools.drv",
+            "bootstrap-stage0.drv",
+        ]);
+    }
+
+    #[test]
+    fn deferred() {
+        let hello = store_path("hnm5s456v5wky252cv71ni8cqaphf11i-hello.drv.drv");
+        let mut hasher = DerivationHasher::new(FileResolver::in_directory(edge_cases()));
+        assert_eq!(hasher.path_hash_modulo(&hello).unwrap().kind, DerivationHashKind::Deferred);
+
+        // Deferred input-addressed outputs alone do not make the hash deferred.
+        let derivation = parse_derivation(r#"Derive([("out","","","")],[],[],"x86_64-linux","/bin/sh",[],[])"#).unwrap();
+        assert_eq!(hasher.hash_derivation_modulo(&derivation, true).unwrap().kind, DerivationHashKind::Regular);
+    }
+
+    #[test]
+    fn dynamic_inputs() {
+        let mut loaded = Vec::new();
+        let mut hasher = DerivationHasher::new(|path: &StorePath| {
+            loaded.push(path.name().to_string());
+            FileResolver::in_directory(edge_cases()).resolve(path)
+        });
+        let use_hello = store_path("ng1vcj72jjzc3a029xxvlb3xkrf4kl2h-use-hello.drv");
+        let hash = hasher.path_hash_modulo(&use_hello).unwrap();
+        assert_eq!(hash.kind, DerivationHashKind::Deferred);
+        let derivation = hasher.resolver.resolve(&use_hello).unwrap();
+        let expected = Hash::sha256(&render_derivation_modulo(&derivation, &BTreeMap::new(), false));
+        assert_eq!(hasher.path_hash_modulo(&use_hello).unwrap().hashes["out"], expected);
+        drop(hasher);
+        // The input producing `hello.drv` is never loaded.
+        assert_eq!(loaded, ["use-hello.drv", "use-hello.drv"]);
+    }
+
+    #[test]
+    fn cycle() {
+        let first = store_path("00000000000000000000000000000000-first.drv");
+        let second = store_path("11111111111111111111111111111111-second.drv");
+        let resolve = |path: &StorePath| {
+            let input = if *path == first { &second } else { &first };
+            parse_derivation(
+                &format!(r#"Derive([("out","","","")],[("{input}",["out"])],[],"x86_64-linux","/bin/sh",[],[])"#),
+            )
+        };
+        let error = DerivationHasher::new(resolve).path_hash_modulo(&first).unwrap_err();
+        assert!(matches!(error, HashModuloError::Cycle(ref path) if *path == first));
+        assert_eq!(error.to_string(), format!("`{first}` depends on itself"));
+    }
+
+    #[test]
+    fn impure() {
+        let impure = store_path("0fji8fg0z6gi3zyvsad7gxamx4ca2477-impure.drv");
+        let resolve = |path: &StorePath| {
+            assert_eq!(*path, impure);
+            parse_derivation(r#"Derive([("out","","r:sha256","impure")],[],[],"x86_64-linux","/bin/sh",[],[])"#)
+        };
+        let mut hasher = DerivationHasher::new(resolve);
+        let hash = hasher.path_hash_modulo(&impure).unwrap();
+        assert_eq!(hash.kind, DerivationHashKind::Deferred);
+        assert_eq!(hex(hash, "out"), "ddffc26775f61118da8597d1f2f62c544ec92461b31b5b1075ed8689a7ee8292");
+
+        // Dependents see the constant hash, not one of the impure derivation.
+        let dependent =
+            parse_derivation(
+                r#"Derive([("out","","","")],[("/nix/store/0fji8fg0z6gi3zyvsad7gxamx4ca2477-impure.drv",["out"])],[],"x86_64-linux","/bin/sh",[],[])"#,
+            ).unwrap();
+        let input_drvs = BTreeMap::from([(Hash::sha256(b"impure").encode(HashEncoding::Base16), DerivationInput {
+            value: ["out".to_string()].into(),
+            dynamic_outputs: BTreeMap::new(),
+        })]);
+        let hash = hasher.hash_derivation_modulo(&dependent, true).unwrap();
+        assert_eq!(hash.kind, DerivationHashKind::Deferred);
+        assert_eq!(hash.hashes["out"], Hash::sha256(&render_derivation_modulo(&dependent, &input_drvs, true)));
+    }
+
+    #[test]
+    fn missing_input() {
+        let hello = store_path("qhh9g08r8f6an2hly0p4rjh7q73myz93-hello-2.12.1.drv");
+        let derivation = FileResolver::in_directory(edge_cases()).resolve(&hello).unwrap();
+        let error = derivation.hash_modulo(FileResolver::in_directory("/nonexistent"), true).unwrap_err();
+        assert!(matches!(error, HashModuloError::Resolve(_, ReadDerivationError::Io(_))));
+    }
+}
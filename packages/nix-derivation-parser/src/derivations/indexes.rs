@@ -0,0 +1,266 @@
+pub mod errors;
+
+use crate::derivations::graphs::DerivationGraph;
+use crate::derivations::indexes::errors::IndexError;
+use crate::derivations::parsers::read_derivation;
+use crate::derivations::types::Derivation;
+use crate::store_paths::types::StorePath;
+
+use core::ops::Bound;
+use std::collections::{
+    BTreeMap,
+    BTreeSet,
+};
+use std::fs::{
+    self,
+    File,
+};
+use std::path::Path;
+
+/// The derivation output that produces a store path.
+#[expect(clippy::exhaustive_structs, reason = "Derivation format is very stable.")]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OutputDeriver {
+    pub drv_path: StorePath,
+    pub output: String,
+}
+
+/// An index from output paths to the derivations that produce them.
+///
+/// Usually one derivation produces a path, but several fixed-output
+/// derivations can produce the same one, e.g. when the same file is fetched
+/// from different URLs. Outputs whose paths are not known in advance are not
+/// indexed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutputIndex {
+    /// The output paths and their derivers by the hash parts of the paths.
+    outputs: BTreeMap<String, (StorePath, BTreeSet<OutputDeriver>)>,
+}
+
+impl OutputIndex {
+    /// Creates an empty index.
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { outputs: BTreeMap::new() }
+    }
+
+    /// Indexes the outputs of `derivation`, which is stored at `drv_path`.
+    #[inline]
+    pub fn insert(&mut self, drv_path: &StorePath, derivation: &Derivation) {
+        for (name, output) in &derivation.outputs {
+            let Some(path) = output.path() else {
+                continue;
+            };
+            let (_, derivers) =
+                self.outputs.entry(path.hash_part().to_owned()).or_insert_with(|| (path.clone(), BTreeSet::new()));
+            derivers.insert(OutputDeriver {
+                drv_path: drv_path.clone(),
+                output: name.clone(),
+            });
+        }
+    }
+
+    /// Indexes the outputs of every derivation in `graph`.
+    #[inline]
+    #[must_use]
+    pub fn from_graph(graph: &DerivationGraph) -> Self {
+        let mut index = Self::new();
+        for (drv_path, derivation) in graph.nodes() {
+            index.insert(drv_path, derivation);
+        }
+        index
+    }
+
+    /// Indexes the outputs of every `.drv` file in `directory`, which is usually
+    /// a store directory. If the files were copied out of the store, their
+    /// paths are taken to be in the store directory of their outputs.
+    ///
+    /// A store directory can contain files that are being written or that are
+    /// not derivations at all, so `.drv` files that can not be read or parsed
+    /// are skipped, and returned with their errors alongside the index.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `IndexError` if the directory can not be read.
+    #[inline]
+    pub fn load_directory(directory: &Path) -> Result<(Self, Vec<IndexError>), IndexError> {
+        let mut index = Self::new();
+        let mut skipped = Vec::new();
+        for entry in fs::read_dir(directory)? {
+            let file_path = entry?.path();
+            let Some(file_name) = file_path.file_name().and_then(|file_name| file_name.to_str()) else {
+                continue;
+            };
+            if !file_name.ends_with(".drv") || !file_path.is_file() {
+                continue;
+            }
+            let derivation = match File::open(&file_path).map_err(Into::into).and_then(read_derivation) {
+                Ok(derivation) => derivation,
+                Err(err) => {
+                    skipped.push(IndexError::Read(file_path, err));
+                    continue;
+                },
+            };
+            let store_dir = derivation.store_dir().unwrap_or(directory);
+            match StorePath::new(store_dir.join(file_name).to_string_lossy()) {
+                Ok(drv_path) => index.insert(&drv_path, &derivation),
+                Err(err) => skipped.push(IndexError::InvalidPath(file_path, err)),
+            }
+        }
+        Ok((index, skipped))
+    }
+
+    /// Returns the number of indexed output paths.
+    #[inline]
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.outputs.len()
+    }
+
+    /// Returns whether no output paths are indexed.
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+
+    /// Returns the derivers of the output at `path`.
+    #[inline]
+    #[must_use]
+    pub fn get(&self, path: &StorePath) -> Option<&BTreeSet<OutputDeriver>> {
+        self.outputs.get(path.hash_part()).filter(|&(output, _)| output == path).map(|(_, derivers)| derivers)
+    }
+
+    /// Returns the output that contains `path`, e.g. `/nix/store/...-hello/bin/hello`,
+    /// and its derivers.
+    #[inline]
+    #[must_use]
+    pub fn find_containing(&self, path: &Path) -> Option<(&StorePath, &BTreeSet<OutputDeriver>)> {
+        path.ancestors().find_map(|ancestor| {
+            let ancestor = StorePath::new(ancestor.to_str()?).ok()?;
+            let (output, derivers) = self.outputs.get(ancestor.hash_part())?;
+            (*output == ancestor).then_some((output, derivers))
+        })
+    }
+
+    /// Returns the outputs whose hash parts start with `prefix`, and their
+    /// derivers, ordered by hash part.
+    #[inline]
+    pub fn find_by_hash_prefix<'index>(
+        &'index self,
+        prefix: &'index str,
+    ) -> impl Iterator<Item = (&'index StorePath, &'index BTreeSet<OutputDeriver>)> {
+        self.outputs
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(move |&(hash_part, _)| hash_part.starts_with(prefix))
+            .map(|(_, (output, derivers))| (output, derivers))
+    }
+
+    /// Returns every indexed output and its derivers, ordered by hash part.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (&StorePath, &BTreeSet<OutputDeriver>)> {
+        self.outputs.values().map(|(output, derivers)| (output, derivers))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::derivations::parsers::parse_derivation_bytes;
+    use std::env;
+    use std::path::PathBuf;
+
+    fn edge_case_directory() -> PathBuf {
+        let manifest_dir = env::var_os("CARGO_MANIFEST_DIR").unwrap();
+        Path::new(&manifest_dir).join("src/derivations/edge_case_derivations")
+    }
+
+    fn store_path(base_name: &str) -> StorePath {
+        StorePath::new(format!("/nix/store/{base_name}")).unwrap()
+    }
+
+    fn deriver(drv_base_name: &str, output: &str) -> OutputDeriver {
+        OutputDeriver {
+            drv_path: store_path(drv_base_name),
+            output: output.to_owned(),
+        }
+    }
+
+    #[test]
+    fn directory() {
+        let (index, skipped) = OutputIndex::load_directory(&edge_case_directory()).unwrap();
+        assert!(skipped.is_empty(), "{skipped:?}");
+        let hello_doc = store_path("6vcls8yi0wga3m7f9nc8lv8nj8k7nx2n-hello-2.12.1-doc");
+        assert_eq!(
+            index.get(&hello_doc),
+            Some(&BTreeSet::from([deriver("qhh9g08r8f6an2hly0p4rjh7q73myz93-hello-2.12.1.drv", "doc")])),
+        );
+        let tarball = store_path("pa10z4ngm0g83kx9mssrqzz30s84vq7k-hello-2.12.1.tar.gz");
+        assert_eq!(
+            index.get(&tarball),
+            Some(&BTreeSet::from([deriver("0dkjx5z90qw7ci7qfhnl1p5l2ny9ba75-hello-2.12.1.tar.gz.drv", "out")])),
+        );
+        assert_eq!(index.get(&store_path("6vcls8yi0wga3m7f9nc8lv8nj8k7nx2n-other")), None);
+    }
+
+    #[test]
+    fn directory_with_bad_files() {
+        let directory = env::temp_dir().join(format!("nix-derivation-parser-index-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let hello = "qhh9g08r8f6an2hly0p4rjh7q73myz93-hello-2.12.1.drv";
+        fs::copy(edge_case_directory().join(hello), directory.join(hello)).unwrap();
+        fs::copy(edge_case_directory().join(hello), directory.join("hello.drv")).unwrap();
+        fs::write(directory.join("00000000000000000000000000000000-broken.drv"), "Derive(").unwrap();
+        let loaded = OutputIndex::load_directory(&directory);
+        fs::remove_dir_all(&directory).unwrap();
+
+        let (index, mut skipped) = loaded.unwrap();
+        assert_eq!(index.len(), 3);
+        skipped.sort_by_key(ToString::to_string);
+        let [IndexError::Read(ref broken, _), IndexError::InvalidPath(ref invalid, _)] = *skipped.as_slice() else {
+            panic!("expected a read and an invalid path error, got {skipped:?}");
+        };
+        assert_eq!(broken.file_name().unwrap(), "00000000000000000000000000000000-broken.drv");
+        assert_eq!(invalid.file_name().unwrap(), "hello.drv");
+    }
+
+    #[test]
+    fn graph() {
+        let root = store_path("nkqgqq2l1fpyifm974rf8yhxc1gzmihx-empty-env.drv");
+        let graph = DerivationGraph::load_from_directory(edge_case_directory(), [root]).unwrap();
+        let index = OutputIndex::from_graph(&graph);
+        assert_eq!(index.len(), 7);
+        assert_eq!(index.iter().map(|(_, derivers)| derivers.len()).sum::<usize>(), 7);
+        assert!(index.iter().all(|(_, derivers)| derivers.iter().all(|deriver| graph.contains(&deriver.drv_path))));
+    }
+
+    #[test]
+    fn lookups() {
+        let (index, _) = OutputIndex::load_directory(&edge_case_directory()).unwrap();
+        let hello = store_path("5xg9ljjhn60bwkv20dhpdhl7fxr06akb-hello-2.12.1");
+        let binary = Path::new("/nix/store/5xg9ljjhn60bwkv20dhpdhl7fxr06akb-hello-2.12.1/bin/hello");
+        let (output, derivers) = index.find_containing(binary).unwrap();
+        assert_eq!(output, &hello);
+        assert_eq!(derivers, &BTreeSet::from([deriver("qhh9g08r8f6an2hly0p4rjh7q73myz93-hello-2.12.1.drv", "out")]));
+        assert!(index.find_containing(Path::new("/nix/store/00000000000000000000000000000000-missing")).is_none());
+
+        let found = index.find_by_hash_prefix("5xg9").map(|(output, _)| output).collect::<Vec<_>>();
+        assert_eq!(found, [&hello]);
+        assert_eq!(index.find_by_hash_prefix("").count(), index.len());
+        assert_eq!(index.find_by_hash_prefix("eeee").count(), 0);
+    }
+
+    #[test]
+    fn shared_fixed_output() {
+        let mut index = OutputIndex::new();
+        let output = "/nix/store/pa10z4ngm0g83kx9mssrqzz30s84vq7k-hello-2.12.1.tar.gz";
+        let hash = "8d99142afd92576f30b0cd7cb42a8dc6809998bc5d607d88761f512e26c7db20";
+        let text =
+            format!(r#"Derive([("out","{output}","sha256","{hash}")],[],[],"x86_64-linux","builtin:fetchurl",[],[])"#);
+        let derivation = parse_derivation_bytes(text.as_bytes()).unwrap();
+        index.insert(&store_path("00000000000000000000000000000000-hello-2.12.1.tar.gz.drv"), &derivation);
+        index.insert(&store_path("11111111111111111111111111111111-hello-2.12.1.tar.gz.drv"), &derivation);
+        assert_eq!(index.get(&StorePath::new(output).unwrap()).map(BTreeSet::len), Some(2));
+    }
+}
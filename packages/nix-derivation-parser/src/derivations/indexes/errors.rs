@@ -0,0 +1,48 @@
+use crate::derivations::errors::ReadDerivationError;
+use crate::store_paths::errors::StorePathError;
+
+use core::fmt;
+use std::error::Error;
+use std::io;
+use std::path::PathBuf;
+
+/// An error produced when an index of a store directory can not be built.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum IndexError {
+    /// The directory could not be read.
+    Io(io::Error),
+    /// The name of the `.drv` file at the given path is not a valid store path.
+    InvalidPath(PathBuf, StorePathError),
+    /// The `.drv` file at the given path could not be read.
+    Read(PathBuf, ReadDerivationError),
+}
+
+impl fmt::Display for IndexError {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Io(ref err) => write!(formatter, "failed to read store directory: {err}"),
+            Self::InvalidPath(ref path, ref err) => write!(formatter, "{}: invalid store path: {err}", path.display()),
+            Self::Read(ref path, ref err) => write!(formatter, "{}: {err}", path.display()),
+        }
+    }
+}
+
+impl Error for IndexError {
+    #[inline]
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            Self::Io(ref err) => Some(err),
+            Self::InvalidPath(_, ref err) => Some(err),
+            Self::Read(_, ref err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for IndexError {
+    #[inline]
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
@@ -0,0 +1,661 @@
+pub mod errors;
+
+use crate::derivations::errors::{
+    DerivationField,
+    DerivationOutputError,
+};
+use crate::derivations::json::errors::{
+    JsonDerivationError,
+    JsonDerivationErrorKind,
+};
+use crate::derivations::types::{
+    ContentAddressMethod,
+    Derivation,
+    DerivationInput,
+    DerivationOutput,
+    DerivationVersion,
+};
+use crate::hashes::types::{
+    Hash,
+    HashAlgo,
+    HashEncoding,
+};
+use crate::json::renderers::render_compact_json;
+use crate::json::types::JsonValue;
+use crate::store_paths::types::StorePath;
+use crate::strings::types::ByteString;
+
+use std::collections::{
+    BTreeMap,
+    BTreeSet,
+};
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+/// A version of the JSON format `nix derivation show` prints derivations in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum JsonFormat {
+    /// The original format, without a `version`. The method of a
+    /// content-addressed output is a prefix of its `hashAlgo`, e.g. `r:sha256`,
+    /// like in a `.drv` file.
+    #[default]
+    Unversioned,
+    /// Version 3, in which content-addressed outputs have a separate `method`,
+    /// e.g. `nar`.
+    Version3,
+    /// Version 4, which is like version 3, except that input derivations and
+    /// sources are nested in `inputs` as `drvs` and `srcs`, and store paths are
+    /// written without the store directory.
+    Version4,
+}
+
+impl JsonFormat {
+    /// Returns the `version` written in the format, if any.
+    #[inline]
+    #[must_use]
+    pub const fn version(self) -> Option<u8> {
+        match self {
+            Self::Unversioned => None,
+            Self::Version3 => Some(3),
+            Self::Version4 => Some(4),
+        }
+    }
+
+    /// Writes a store path the way the format does.
+    fn store_path(self, path: &StorePath) -> String {
+        match self {
+            Self::Unversioned | Self::Version3 => path.to_string(),
+            Self::Version4 => path.base_name().to_owned(),
+        }
+    }
+}
+
+/// Returns the content-addressing method and hash algorithm of an output, if it
+/// is content-addressed.
+#[expect(clippy::single_call_fn, reason = "Conversion functions are not inlined for readability.")]
+const fn method_and_hash_algo(output: &DerivationOutput) -> Option<(ContentAddressMethod, HashAlgo)> {
+    match *output {
+        DerivationOutput::CaFixed { method, ref hash, .. } => Some((method, hash.algo())),
+        DerivationOutput::CaFloating { method, hash_algo } | DerivationOutput::Impure { method, hash_algo } => {
+            Some((method, hash_algo))
+        },
+        DerivationOutput::InputAddressed(_) | DerivationOutput::Deferred => None,
+    }
+}
+
+/// Converts an output to JSON: only the fields a `.drv` file has for that kind
+/// of output are included.
+#[expect(clippy::single_call_fn, reason = "Conversion functions are not inlined for readability.")]
+fn output_to_json(output: &DerivationOutput, format: JsonFormat) -> JsonValue {
+    let mut fields = BTreeMap::new();
+    if let Some(path) = output.path() {
+        fields.insert("path".to_owned(), JsonValue::String(format.store_path(path)));
+    }
+    if let Some((method, hash_algo)) = method_and_hash_algo(output) {
+        if format == JsonFormat::Unversioned {
+            fields.insert("hashAlgo".to_owned(), JsonValue::String(output.aterm_hash_algo()));
+        } else {
+            fields.insert("method".to_owned(), JsonValue::String(method.name().to_owned()));
+            fields.insert("hashAlgo".to_owned(), JsonValue::String(hash_algo.to_string()));
+        }
+    }
+    if output.is_fixed_output() {
+        fields.insert("hash".to_owned(), JsonValue::String(output.aterm_hash()));
+    }
+    if matches!(*output, DerivationOutput::Impure { .. }) {
+        fields.insert("impure".to_owned(), JsonValue::Bool(true));
+    }
+    JsonValue::Object(fields)
+}
+
+/// Converts the outputs used from an input derivation to JSON, nesting the
+/// outputs of dynamic outputs.
+fn input_to_json(input: &DerivationInput) -> JsonValue {
+    JsonValue::object([
+        (
+            "dynamicOutputs",
+            JsonValue::object(input.dynamic_outputs.iter().map(|(name, input)| (name.clone(), input_to_json(input)))),
+        ),
+        ("outputs", JsonValue::strings(input.value.iter().cloned())),
+    ])
+}
+
+/// Converts a derivation to the JSON object `nix derivation show` prints for
+/// it in `format`. Strings that are not valid UTF-8 are converted lossily.
+///
+/// Structured attributes are left in the `__json` environment variable.
+#[inline]
+#[must_use]
+pub fn derivation_to_json(derivation: &Derivation, format: JsonFormat) -> JsonValue {
+    let input_drvs = JsonValue::object(
+        derivation.input_drvs.iter().map(|(path, input)| (format.store_path(path), input_to_json(input))),
+    );
+    let input_srcs = JsonValue::strings(derivation.input_srcs.iter().map(|path| format.store_path(path)));
+    let mut fields = BTreeMap::from([
+        ("args".to_owned(), JsonValue::strings(derivation.args.iter().map(ToString::to_string))),
+        ("builder".to_owned(), JsonValue::String(derivation.builder.to_string_lossy().into_owned())),
+        (
+            "env".to_owned(),
+            JsonValue::object(
+                derivation.env.iter().map(|(key, value)| (key.to_string(), JsonValue::String(value.to_string()))),
+            ),
+        ),
+        (
+            "outputs".to_owned(),
+            JsonValue::object(
+                derivation.outputs.iter().map(|(name, output)| (name.clone(), output_to_json(output, format))),
+            ),
+        ),
+        ("system".to_owned(), JsonValue::String(derivation.system.clone())),
+    ]);
+    if format == JsonFormat::Version4 {
+        fields.insert("inputs".to_owned(), JsonValue::object([("drvs", input_drvs), ("srcs", input_srcs)]));
+    } else {
+        fields.insert("inputDrvs".to_owned(), input_drvs);
+        fields.insert("inputSrcs".to_owned(), input_srcs);
+    }
+    if let Some(name) = derivation.name() {
+        fields.insert("name".to_owned(), JsonValue::String(name.to_owned()));
+    }
+    if let Some(version) = format.version() {
+        fields.insert("version".to_owned(), JsonValue::Number(version.to_string()));
+    }
+    JsonValue::Object(fields)
+}
+
+/// Converts derivations to the JSON object `nix derivation show` prints for
+/// them in `format`, keyed by their paths.
+#[inline]
+pub fn derivations_to_json<'drv>(
+    derivations: impl IntoIterator<Item = (&'drv StorePath, &'drv Derivation)>,
+    format: JsonFormat,
+) -> JsonValue {
+    JsonValue::object(
+        derivations
+            .into_iter()
+            .map(|(path, derivation)| (format.store_path(path), derivation_to_json(derivation, format))),
+    )
+}
+
+/// Returns the field a key of a JSON derivation stands for in errors.
+fn key_field(key: &str) -> DerivationField {
+    match key {
+        "outputs" => DerivationField::Outputs,
+        "inputDrvs" | "drvs" => DerivationField::InputDrvs,
+        "inputSrcs" | "srcs" => DerivationField::InputSrcs,
+        "system" => DerivationField::System,
+        "builder" => DerivationField::Builder,
+        "args" => DerivationField::Args,
+        "env" => DerivationField::Env,
+        _ => DerivationField::Key(key.to_owned()),
+    }
+}
+
+/// Returns `value` as a string.
+fn expect_str(value: &JsonValue) -> Result<&str, JsonDerivationError> {
+    value.as_str().ok_or_else(|| JsonDerivationError::new(JsonDerivationErrorKind::ExpectedType("a string")))
+}
+
+/// Returns `value` as an array.
+fn expect_array(value: &JsonValue) -> Result<&[JsonValue], JsonDerivationError> {
+    value.as_array().ok_or_else(|| JsonDerivationError::new(JsonDerivationErrorKind::ExpectedType("an array")))
+}
+
+/// Returns `value` as an object.
+fn expect_object(value: &JsonValue) -> Result<&BTreeMap<String, JsonValue>, JsonDerivationError> {
+    value.as_object().ok_or_else(|| JsonDerivationError::new(JsonDerivationErrorKind::ExpectedType("an object")))
+}
+
+/// Converts the value of `key` in `object` with `convert`, if there is one,
+/// recording the key in errors.
+fn convert_optional_key<'value, T>(
+    object: &'value BTreeMap<String, JsonValue>,
+    key: &'static str,
+    convert: impl FnOnce(&'value JsonValue) -> Result<T, JsonDerivationError>,
+) -> Result<Option<T>, JsonDerivationError> {
+    object.get(key).map(convert).transpose().map_err(|err| err.in_field(key_field(key)))
+}
+
+/// Converts the value of `key` in `object` with `convert`, recording the key in
+/// errors. Fails if the key is missing.
+fn convert_key<'value, T>(
+    object: &'value BTreeMap<String, JsonValue>,
+    key: &'static str,
+    convert: impl FnOnce(&'value JsonValue) -> Result<T, JsonDerivationError>,
+) -> Result<T, JsonDerivationError> {
+    convert_optional_key(object, key, convert)?
+        .ok_or_else(|| JsonDerivationError::new(JsonDerivationErrorKind::MissingKey(key)))
+}
+
+/// Converts a store path, which is taken to be in `store_dir` if it is written
+/// without a store directory.
+fn store_path_from_json(path: &str, store_dir: &Path) -> Result<StorePath, JsonDerivationError> {
+    let full_path = if path.starts_with('/') {
+        path.to_owned()
+    } else {
+        store_dir.join(path).to_string_lossy().into_owned()
+    };
+    StorePath::new(full_path)
+        .map_err(|err| JsonDerivationError::new(JsonDerivationErrorKind::InvalidStorePath(path.to_owned(), err)))
+}
+
+/// Converts a set of strings, such as output names.
+fn strings_from_json(value: &JsonValue) -> Result<BTreeSet<String>, JsonDerivationError> {
+    expect_array(value)?.iter().map(|value| expect_str(value).map(str::to_owned)).collect()
+}
+
+/// Converts an output from any of the formats.
+///
+/// The hash of a fixed output can be in any encoding Nix reads, e.g. SRI.
+#[expect(clippy::single_call_fn, reason = "Conversion functions are not inlined for readability.")]
+fn output_from_json(value: &JsonValue, store_dir: &Path) -> Result<DerivationOutput, JsonDerivationError> {
+    let fields = expect_object(value)?;
+    // Older versions of Nix write an empty path for outputs without one.
+    let path = convert_optional_key(fields, "path", expect_str)?
+        .filter(|path| !path.is_empty())
+        .map(|path| store_path_from_json(path, store_dir).map_err(|err| err.in_field(key_field("path"))))
+        .transpose()?;
+    let hash_algo = convert_optional_key(fields, "hashAlgo", expect_str)?.unwrap_or_default();
+    let aterm_hash_algo = match convert_optional_key(fields, "method", expect_str)? {
+        Some(method) => {
+            let method = ContentAddressMethod::from_name(method).ok_or_else(|| {
+                JsonDerivationError::new(JsonDerivationErrorKind::UnknownMethod(method.to_owned()))
+                    .in_field(key_field("method"))
+            })?;
+            format!("{}{hash_algo}", method.prefix())
+        },
+        None => hash_algo.to_owned(),
+    };
+    let invalid_output = |err| JsonDerivationError::new(JsonDerivationErrorKind::InvalidOutput(err));
+    let hash = match convert_optional_key(fields, "hash", expect_str)? {
+        Some(hash) if !hash.is_empty() && !aterm_hash_algo.is_empty() => {
+            let (_, algo) = ContentAddressMethod::parse_with_algo(&aterm_hash_algo)
+                .map_err(|err| invalid_output(DerivationOutputError::InvalidHashAlgo(err)))?;
+            Hash::parse(hash, Some(algo))
+                .map_err(|err| invalid_output(DerivationOutputError::InvalidHash(err)))?
+                .encode(HashEncoding::Base16)
+        },
+        Some(hash) => hash.to_owned(),
+        None if fields.get("impure") == Some(&JsonValue::Bool(true)) => "impure".to_owned(),
+        None => String::new(),
+    };
+    DerivationOutput::from_aterm_fields(path, &aterm_hash_algo, &hash).map_err(invalid_output)
+}
+
+/// Converts the outputs used from an input derivation: either a list of output
+/// names, as older versions of Nix write them, or an object with `outputs` and
+/// `dynamicOutputs`.
+fn input_from_json(value: &JsonValue) -> Result<DerivationInput, JsonDerivationError> {
+    if value.as_array().is_some() {
+        return Ok(DerivationInput {
+            value: strings_from_json(value)?,
+            dynamic_outputs: BTreeMap::new(),
+        });
+    }
+    let fields = expect_object(value)?;
+    let dynamic_outputs = convert_optional_key(fields, "dynamicOutputs", |dynamic_outputs| {
+        expect_object(dynamic_outputs)?
+            .iter()
+            .map(|(name, input)| {
+                let input =
+                    input_from_json(input).map_err(|err| err.in_field(DerivationField::DynamicOutput(name.clone())))?;
+                Ok((name.clone(), input))
+            })
+            .collect()
+    })?;
+    Ok(DerivationInput {
+        value: convert_optional_key(fields, "outputs", strings_from_json)?.unwrap_or_default(),
+        dynamic_outputs: dynamic_outputs.unwrap_or_default(),
+    })
+}
+
+/// Converts the input derivations and sources of a derivation, which are the
+/// values of `drvs_key` and `srcs_key` in `fields`.
+fn inputs_from_json(
+    fields: &BTreeMap<String, JsonValue>,
+    drvs_key: &'static str,
+    srcs_key: &'static str,
+    store_dir: &Path,
+) -> Result<(BTreeMap<StorePath, DerivationInput>, BTreeSet<StorePath>), JsonDerivationError> {
+    let input_drvs = convert_key(fields, drvs_key, |input_drvs| {
+        expect_object(input_drvs)?
+            .iter()
+            .map(|(path, input)| {
+                let path = store_path_from_json(path, store_dir)?;
+                let input =
+                    input_from_json(input).map_err(|err| err.in_field(DerivationField::InputDrv(path.clone())))?;
+                Ok((path, input))
+            })
+            .collect()
+    })?;
+    let input_srcs = convert_key(fields, srcs_key, |input_srcs| {
+        expect_array(input_srcs)?.iter().map(|path| store_path_from_json(expect_str(path)?, store_dir)).collect()
+    })?;
+    Ok((input_drvs, input_srcs))
+}
+
+/// Converts a derivation from the JSON object `nix derivation show` prints for
+/// it, in any of the formats. Store paths written without a store directory
+/// are taken to be in `store_dir`.
+///
+/// Structured attributes are written into the `__json` environment variable
+/// without any whitespace, like Nix does.
+///
+/// # Errors
+///
+/// Returns a `JsonDerivationError` if `value` does not describe a derivation.
+#[inline]
+pub fn derivation_from_json(value: &JsonValue, store_dir: &Path) -> Result<Derivation, JsonDerivationError> {
+    let fields = expect_object(value)?;
+    let format = match fields.get("version") {
+        None => JsonFormat::Unversioned,
+        Some(JsonValue::Number(version)) if version == "3" => JsonFormat::Version3,
+        Some(JsonValue::Number(version)) if version == "4" => JsonFormat::Version4,
+        Some(version) => {
+            let kind = JsonDerivationErrorKind::UnknownVersion(render_compact_json(version));
+            return Err(JsonDerivationError::new(kind).in_field(key_field("version")));
+        },
+    };
+    let outputs = convert_key(fields, "outputs", |outputs| {
+        expect_object(outputs)?
+            .iter()
+            .map(|(name, output)| {
+                let output = output_from_json(output, store_dir)
+                    .map_err(|err| err.in_field(DerivationField::Output(name.clone())))?;
+                Ok((name.clone(), output))
+            })
+            .collect::<Result<BTreeMap<_, _>, _>>()
+    })?;
+    let (input_drvs, input_srcs) = if format == JsonFormat::Version4 {
+        convert_key(fields, "inputs", |inputs| inputs_from_json(expect_object(inputs)?, "drvs", "srcs", store_dir))?
+    } else {
+        inputs_from_json(fields, "inputDrvs", "inputSrcs", store_dir)?
+    };
+    let args = convert_key(fields, "args", |args| {
+        expect_array(args)?.iter().map(|arg| expect_str(arg).map(ByteString::from)).collect::<Result<Vec<_>, _>>()
+    })?;
+    let mut env = convert_key(fields, "env", |env| {
+        expect_object(env)?
+            .iter()
+            .map(|(key, value)| {
+                let value =
+                    expect_str(value).map_err(|err| err.in_field(DerivationField::EnvVar(key.as_str().into())))?;
+                Ok((ByteString::from(key.as_str()), ByteString::from(value)))
+            })
+            .collect::<Result<BTreeMap<_, _>, _>>()
+    })?;
+    if let Some(structured_attrs) = fields.get("structuredAttrs") {
+        env.insert(ByteString::from("__json"), ByteString::from(render_compact_json(structured_attrs)));
+    }
+    // Like Nix, only use the version of the ATerm format that supports dynamic
+    // outputs if there are any.
+    let version = if input_drvs.values().any(|input| !input.dynamic_outputs.is_empty()) {
+        DerivationVersion::DynamicDerivations
+    } else {
+        DerivationVersion::Traditional
+    };
+    Ok(Derivation {
+        version,
+        outputs,
+        input_drvs,
+        input_srcs,
+        system: convert_key(fields, "system", expect_str)?.to_owned(),
+        builder: PathBuf::from(convert_key(fields, "builder", expect_str)?),
+        args,
+        env,
+    })
+}
+
+/// Converts derivations from the JSON object `nix derivation show` prints for
+/// them, keyed by their paths, as `derivation_from_json` does.
+///
+/// # Errors
+///
+/// Returns a `JsonDerivationError` if `value` is not an object of derivations.
+#[inline]
+pub fn derivations_from_json(
+    value: &JsonValue,
+    store_dir: &Path,
+) -> Result<BTreeMap<StorePath, Derivation>, JsonDerivationError> {
+    expect_object(value)?
+        .iter()
+        .map(|(path, derivation)| {
+            let in_derivation = |err: JsonDerivationError| err.in_field(DerivationField::Derivation(path.clone()));
+            let drv_path = store_path_from_json(path, store_dir).map_err(in_derivation)?;
+            let derivation = derivation_from_json(derivation, store_dir).map_err(in_derivation)?;
+            Ok((drv_path, derivation))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::derivations::parsers::{
+        parse_derivation,
+        read_derivation,
+    };
+    use crate::json::parsers::parse_json;
+    use crate::json::renderers::render_json;
+    use crate::store_paths::types::DEFAULT_STORE_DIR;
+    use std::env;
+    use std::fs::{
+        self,
+        File,
+    };
+
+    const FORMATS: [JsonFormat; 3] = [JsonFormat::Unversioned, JsonFormat::Version3, JsonFormat::Version4];
+
+    fn fixed_output_derivation() -> Derivation {
+        parse_derivation(
+            r#"DrvWithVersion("xp-dyn-drv",[("out","/nix/store/pa10z4ngm0g83kx9mssrqzz30s84vq7k-hello-2.12.1.tar.gz","r:sha256","8d99142afd92576f30b0cd7cb42a8dc6809998bc5d607d88761f512e26c7db20")],[("/nix/store/hnm5s456v5wky252cv71ni8cqaphf11i-hello.drv.drv",(["out"],[("out",["out"])]))],["/nix/store/x3q2qs9rmnrvq9xxnyk0nyjmyq8qrdyq-builder.sh"],"x86_64-linux","builtin:fetchurl",["-e"],[("name","hello-2.12.1.tar.gz"),("out","/nix/store/pa10z4ngm0g83kx9mssrqzz30s84vq7k-hello-2.12.1.tar.gz")])"#,
+        )
+        .unwrap()
+    }
+
+    fn from_json(input: &str) -> Result<Derivation, JsonDerivationError> {
+        derivation_from_json(&parse_json(input).unwrap(), Path::new(DEFAULT_STORE_DIR))
+    }
+
+    #[test]
+    fn unversioned() {
+        let expected = r#"{
+  "args": [
+    "-e"
+  ],
+  "builder": "builtin:fetchurl",
+  "env": {
+    "name": "hello-2.12.1.tar.gz",
+    "out": "/nix/store/pa10z4ngm0g83kx9mssrqzz30s84vq7k-hello-2.12.1.tar.gz"
+  },
+  "inputDrvs": {
+    "/nix/store/hnm5s456v5wky252cv71ni8cqaphf11i-hello.drv.drv": {
+      "dynamicOutputs": {
+        "out": {
+          "dynamicOutputs": {},
+          "outputs": [
+            "out"
+          ]
+        }
+      },
+      "outputs": [
+        "out"
+      ]
+    }
+  },
+  "inputSrcs": [
+    "/nix/store/x3q2qs9rmnrvq9xxnyk0nyjmyq8qrdyq-builder.sh"
+  ],
+  "name": "hello-2.12.1.tar.gz",
+  "outputs": {
+    "out": {
+      "hash": "8d99142afd92576f30b0cd7cb42a8dc6809998bc5d607d88761f512e26c7db20",
+      "hashAlgo": "r:sha256",
+      "path": "/nix/store/pa10z4ngm0g83kx9mssrqzz30s84vq7k-hello-2.12.1.tar.gz"
+    }
+  },
+  "system": "x86_64-linux"
+}"#;
+        assert_eq!(render_json(&derivation_to_json(&fixed_output_derivation(), JsonFormat::Unversioned)), expected);
+    }
+
+    #[test]
+    fn version4() {
+        let expected = r#"{
+  "args": [
+    "-e"
+  ],
+  "builder": "builtin:fetchurl",
+  "env": {
+    "name": "hello-2.12.1.tar.gz",
+    "out": "/nix/store/pa10z4ngm0g83kx9mssrqzz30s84vq7k-hello-2.12.1.tar.gz"
+  },
+  "inputs": {
+    "drvs": {
+      "hnm5s456v5wky252cv71ni8cqaphf11i-hello.drv.drv": {
+        "dynamicOutputs": {
+          "out": {
+            "dynamicOutputs": {},
+            "outputs": [
+              "out"
+            ]
+          }
+        },
+        "outputs": [
+          "out"
+        ]
+      }
+    },
+    "srcs": [
+      "x3q2qs9rmnrvq9xxnyk0nyjmyq8qrdyq-builder.sh"
+    ]
+  },
+  "name": "hello-2.12.1.tar.gz",
+  "outputs": {
+    "out": {
+      "hash": "8d99142afd92576f30b0cd7cb42a8dc6809998bc5d607d88761f512e26c7db20",
+      "hashAlgo": "sha256",
+      "method": "nar",
+      "path": "pa10z4ngm0g83kx9mssrqzz30s84vq7k-hello-2.12.1.tar.gz"
+    }
+  },
+  "system": "x86_64-linux",
+  "version": 4
+}"#;
+        assert_eq!(render_json(&derivation_to_json(&fixed_output_derivation(), JsonFormat::Version4)), expected);
+    }
+
+    #[test]
+    fn output_kinds() {
+        let derivation = parse_derivation(
+            r#"Derive([("a","","",""),("b","","r:sha256",""),("c","","text:sha256","impure")],[],[],"x86_64-linux","/bin/sh",[],[])"#,
+        )
+        .unwrap();
+        let json = derivation_to_json(&derivation, JsonFormat::Version3);
+        let hash_algo = JsonValue::String("sha256".to_owned());
+        let nar = JsonValue::String("nar".to_owned());
+        assert_eq!(
+            json.get("outputs"),
+            Some(&JsonValue::object([
+                ("a", JsonValue::object::<String>([])),
+                ("b", JsonValue::object([("hashAlgo", hash_algo.clone()), ("method", nar)])),
+                (
+                    "c",
+                    JsonValue::object([
+                        ("hashAlgo", hash_algo),
+                        ("impure", JsonValue::Bool(true)),
+                        ("method", JsonValue::String("text".to_owned())),
+                    ]),
+                ),
+            ])),
+        );
+        assert_eq!(json.get("name"), None);
+        assert_eq!(json.get("version"), Some(&JsonValue::Number("3".to_owned())));
+        for format in FORMATS {
+            let json = derivation_to_json(&derivation, format);
+            let converted = derivation_from_json(&json, Path::new(DEFAULT_STORE_DIR));
+            assert_eq!(converted, Ok(derivation.clone()));
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let manifest_dir = env::var_os("CARGO_MANIFEST_DIR").unwrap();
+        for entry in fs::read_dir(Path::new(&manifest_dir).join("src/derivations/edge_case_derivations")).unwrap() {
+            let path = entry.unwrap().path();
+            let derivation = read_derivation(File::open(&path).unwrap()).unwrap();
+            let file_name = path.file_name().unwrap().to_str().unwrap();
+            let drv_path = StorePath::new(format!("/nix/store/{file_name}")).unwrap();
+            for format in FORMATS {
+                let json = render_json(&derivations_to_json([(&drv_path, &derivation)], format));
+                let converted = derivations_from_json(&parse_json(&json).unwrap(), Path::new(DEFAULT_STORE_DIR));
+                let expected = BTreeMap::from([(drv_path.clone(), derivation.clone())]);
+                assert_eq!(converted, Ok(expected), "{} as {format:?}", path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn older_nix() {
+        let derivation = from_json(
+            r#"{
+              "args": [],
+              "builder": "/bin/sh",
+              "env": {},
+              "inputDrvs": {"/nix/store/0dkjx5z90qw7ci7qfhnl1p5l2ny9ba75-hello-2.12.1.tar.gz.drv": ["out"]},
+              "inputSrcs": [],
+              "outputs": {
+                "out": {
+                  "path": "/nix/store/pa10z4ngm0g83kx9mssrqzz30s84vq7k-hello-2.12.1.tar.gz",
+                  "hashAlgo": "sha256",
+                  "hash": "sha256-jZkUKv2SV28wsM18tCqNxoCZmLxdYH2Idh9RLibH2yA="
+                },
+                "dev": {"path": ""}
+              },
+              "system": "x86_64-linux"
+            }"#,
+        )
+        .unwrap();
+        let tarball = StorePath::new("/nix/store/0dkjx5z90qw7ci7qfhnl1p5l2ny9ba75-hello-2.12.1.tar.gz.drv").unwrap();
+        assert_eq!(derivation.input_drvs[&tarball].value, BTreeSet::from(["out".to_owned()]));
+        assert_eq!(
+            derivation.outputs["out"].aterm_hash(),
+            "8d99142afd92576f30b0cd7cb42a8dc6809998bc5d607d88761f512e26c7db20",
+        );
+        assert_eq!(derivation.outputs["dev"], DerivationOutput::Deferred);
+        assert_eq!(derivation.version, DerivationVersion::Traditional);
+    }
+
+    #[test]
+    fn structured_attrs() {
+        let derivation = from_json(
+            r#"{"args": [], "builder": "/bin/sh", "env": {}, "inputDrvs": {}, "inputSrcs": [], "outputs": {}, "system": "x86_64-linux", "version": 3, "structuredAttrs": {"b": [1, true], "a": "x"}}"#,
+        )
+        .unwrap();
+        assert_eq!(derivation.env.get(b"__json".as_slice()), Some(&ByteString::from(r#"{"a":"x","b":[1,true]}"#)));
+    }
+
+    #[test]
+    fn errors() {
+        let error = |input: &str| from_json(input).unwrap_err().to_string();
+        assert_eq!(error("[]"), "expected an object");
+        assert_eq!(error(r#"{"version": 5}"#), "unknown derivation JSON version `5` in version");
+        assert_eq!(
+            error(r#"{"outputs": {"out": {"method": "zip", "hashAlgo": "sha256"}}}"#),
+            "unknown content-addressing method `zip` in outputs > `out` > method",
+        );
+        assert_eq!(
+            error(r#"{"outputs": {"out": {"path": "/out"}}}"#),
+            "invalid store path `/out`: not an absolute path directly inside a store directory in outputs > `out` > path",
+        );
+        assert_eq!(error(r#"{"outputs": {}, "inputDrvs": {}, "inputSrcs": [1]}"#), "expected a string in inputSrcs");
+        assert_eq!(error(r#"{"outputs": {}, "version": 4}"#), "missing `inputs`");
+        assert_eq!(
+            error(r#"{"args": [], "builder": "", "env": {"a": null}, "inputDrvs": {}, "inputSrcs": [], "outputs": {}}"#),
+            "expected a string in env > `a`",
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use crate::derivations::errors::{
+    DerivationField,
+    DerivationOutputError,
+};
+use crate::store_paths::errors::StorePathError;
+
+use core::fmt;
+use std::error::Error;
+
+/// What in a JSON derivation does not fit the format `nix derivation show`
+/// prints.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum JsonDerivationErrorKind {
+    /// Expected a value of the given type, e.g. `string`.
+    ExpectedType(&'static str),
+    /// The object has no value for the given key.
+    MissingKey(&'static str),
+    /// A path that must be a store path, such as an input source, is not.
+    InvalidStorePath(String, StorePathError),
+    /// The fields of an output do not fit together.
+    InvalidOutput(DerivationOutputError),
+    /// The content-addressing method of an output is not one Nix knows.
+    UnknownMethod(String),
+    /// The `version` is not one this crate knows.
+    UnknownVersion(String),
+}
+
+impl fmt::Display for JsonDerivationErrorKind {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::ExpectedType(expected) => write!(formatter, "expected {expected}"),
+            Self::MissingKey(key) => write!(formatter, "missing `{key}`"),
+            Self::InvalidStorePath(ref path, ref err) => write!(formatter, "invalid store path `{path}`: {err}"),
+            Self::InvalidOutput(ref err) => write!(formatter, "invalid output: {err}"),
+            Self::UnknownMethod(ref method) => write!(formatter, "unknown content-addressing method `{method}`"),
+            Self::UnknownVersion(ref version) => write!(formatter, "unknown derivation JSON version `{version}`"),
+        }
+    }
+}
+
+/// An error produced when JSON does not describe a derivation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct JsonDerivationError {
+    /// What went wrong.
+    pub kind: JsonDerivationErrorKind,
+    /// The fields that were being converted, from the outermost to the
+    /// innermost.
+    pub field: Vec<DerivationField>,
+}
+
+impl JsonDerivationError {
+    /// Creates an error outside of any field.
+    #[inline]
+    #[must_use]
+    pub const fn new(kind: JsonDerivationErrorKind) -> Self {
+        Self { kind, field: Vec::new() }
+    }
+
+    /// Records that the error occurred inside `field`, which is outside any
+    /// field recorded before.
+    #[inline]
+    #[must_use]
+    pub fn in_field(mut self, field: DerivationField) -> Self {
+        self.field.insert(0, field);
+        self
+    }
+}
+
+impl fmt::Display for JsonDerivationError {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "{}", self.kind)?;
+        for (index, field) in self.field.iter().enumerate() {
+            formatter.write_str(if index == 0 { " in " } else { " > " })?;
+            write!(formatter, "{field}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for JsonDerivationError {}
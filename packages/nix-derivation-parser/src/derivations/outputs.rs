@@ -0,0 +1,297 @@
+use crate::derivations::errors::OutputPathError;
+use crate::derivations::hashing::{
+    DerivationHasher,
+    DerivationResolver,
+};
+use crate::derivations::types::{
+    ContentAddressMethod,
+    Derivation,
+    DerivationOutput,
+    OutputMismatch,
+};
+use crate::hashes::types::{
+    Hash,
+    HashAlgo,
+    HashEncoding,
+};
+use crate::store_paths::errors::StorePathError;
+use crate::store_paths::types::{
+    StorePath,
+    DEFAULT_STORE_DIR,
+};
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Returns the name of the path of an output of a derivation named `name`.
+///
+/// The `out` output is named after the derivation, other outputs get their name
+/// appended, e.g. `hello-2.12.1-man`.
+#[inline]
+#[must_use]
+pub fn output_path_name(name: &str, output: &str) -> String {
+    if output == "out" {
+        name.to_string()
+    } else {
+        format!("{name}-{output}")
+    }
+}
+
+/// Makes the path of an input-addressed output from the masked
+/// `hashDerivationModulo` of its derivation, following `makeOutputPath` in Nix.
+///
+/// # Errors
+///
+/// Returns a `StorePathError` if the path would not be a valid store path.
+#[inline]
+pub fn make_output_path(store_dir: &Path, output: &str, hash: &Hash, name: &str) -> Result<StorePath, StorePathError> {
+    StorePath::make(store_dir, &format!("output:{output}"), hash, &output_path_name(name, output))
+}
+
+/// Makes the path of a fixed output without references from its content hash,
+/// following `makeFixedOutputPath` in Nix.
+///
+/// Recursive SHA-256 and Git hashes are used directly. Any other hash is hashed
+/// once more, so that the same file fetched with another method gets another
+/// path.
+///
+/// # Errors
+///
+/// Returns a `StorePathError` if the path would not be a valid store path.
+#[inline]
+pub fn make_fixed_output_path(
+    store_dir: &Path,
+    method: ContentAddressMethod,
+    hash: &Hash,
+    name: &str,
+) -> Result<StorePath, StorePathError> {
+    match method {
+        ContentAddressMethod::Recursive if hash.algo() == HashAlgo::Sha256 => {
+            StorePath::make(store_dir, "source", hash, name)
+        },
+        ContentAddressMethod::Git => StorePath::make(store_dir, "source", hash, name),
+        ContentAddressMethod::Text => StorePath::make(store_dir, "text", hash, name),
+        ContentAddressMethod::Flat | ContentAddressMethod::Recursive => {
+            let base16 = hash.encode(HashEncoding::Base16);
+            let inner = Hash::sha256(format!("fixed:out:{}{}:{base16}:", method.prefix(), hash.algo()).as_bytes());
+            StorePath::make(store_dir, "output:out", &inner, name)
+        },
+    }
+}
+
+impl<R: DerivationResolver> DerivationHasher<R> {
+    /// Computes the paths Nix gives the outputs of `derivation` when it is named
+    /// `name` and lives in `store_dir`.
+    ///
+    /// Only input-addressed and fixed outputs have paths known in advance, so
+    /// other outputs are left out.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `OutputPathError` if the hash of the derivation can not be
+    /// computed or a computed path is invalid.
+    #[inline]
+    pub fn output_paths(
+        &mut self,
+        derivation: &Derivation,
+        name: &str,
+        store_dir: &Path,
+    ) -> Result<BTreeMap<String, StorePath>, OutputPathError<R::Error>> {
+        let is_input_addressed = |output: &DerivationOutput| matches!(*output, DerivationOutput::InputAddressed(_));
+        let masked = if derivation.outputs.values().any(is_input_addressed) {
+            Some(self.hash_derivation_modulo(derivation, true)?)
+        } else {
+            None
+        };
+        derivation
+            .outputs
+            .iter()
+            .filter_map(|(output, kind)| {
+                let path = match (kind, masked.as_ref().and_then(|masked| masked.hashes.get(output))) {
+                    (&DerivationOutput::InputAddressed(_), Some(hash)) => {
+                        make_output_path(store_dir, output, hash, name)
+                    },
+                    (&DerivationOutput::CaFixed { method, ref hash, .. }, _) => {
+                        make_fixed_output_path(store_dir, method, hash, &output_path_name(name, output))
+                    },
+                    _ => return None,
+                };
+                Some(
+                    path
+                        .map(|path| (output.clone(), path))
+                        .map_err(|err| OutputPathError::InvalidPath(output.clone(), err)),
+                )
+            })
+            .collect()
+    }
+
+    /// Checks that the recorded output paths of `derivation`, named `name`, are
+    /// the ones Nix computes, and that the environment variable named after each
+    /// output holds its path, like `Derivation::checkInvariants` in Nix.
+    ///
+    /// Returns every mismatch found, so an empty list means the derivation is
+    /// consistent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `OutputPathError` if the hash of the derivation can not be
+    /// computed or a computed path is invalid.
+    #[inline]
+    pub fn verify_outputs(
+        &mut self,
+        derivation: &Derivation,
+        name: &str,
+    ) -> Result<Vec<OutputMismatch>, OutputPathError<R::Error>> {
+        let store_dir = derivation
+            .outputs
+            .values()
+            .find_map(DerivationOutput::path)
+            .map_or(Path::new(DEFAULT_STORE_DIR), StorePath::store_dir);
+        let computed = self.output_paths(derivation, name, store_dir)?;
+        let mut mismatches = Vec::new();
+        for (output, kind) in &derivation.outputs {
+            let (Some(recorded), Some(computed)) = (kind.path(), computed.get(output)) else {
+                continue;
+            };
+            if recorded != computed {
+                mismatches.push(OutputMismatch::Path {
+                    output: output.clone(),
+                    recorded: recorded.clone(),
+                    computed: computed.clone(),
+                });
+            }
+            let value = derivation.env.get(output.as_bytes());
+            if value.is_none_or(|value| value != recorded.as_str()) {
+                mismatches.push(OutputMismatch::Env {
+                    output: output.clone(),
+                    path: recorded.clone(),
+                    value: value.cloned(),
+                });
+            }
+        }
+        Ok(mismatches)
+    }
+}
+
+impl Derivation {
+    /// Checks the output paths of the derivation, named `name`, loading input
+    /// derivations with `resolver`. See `DerivationHasher::verify_outputs`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `OutputPathError` if the hash of the derivation can not be
+    /// computed or a computed path is invalid.
+    #[inline]
+    pub fn verify_outputs<R: DerivationResolver>(
+        &self,
+        name: &str,
+        resolver: R,
+    ) -> Result<Vec<OutputMismatch>, OutputPathError<R::Error>> {
+        DerivationHasher::new(resolver).verify_outputs(self, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::derivations::errors::{
+        HashModuloError,
+        ReadDerivationError,
+    };
+    use crate::derivations::hashing::FileResolver;
+    use crate::derivations::parsers::parse_derivation_bytes;
+    use std::fs;
+    use std::io;
+    use std::path::PathBuf;
+
+    fn directory(name: &str) -> PathBuf {
+        Path::new(&std::env::var_os("CARGO_MANIFEST_DIR").unwrap()).join("src/derivations").join(name)
+    }
+
+    /// Verifies every derivation in `name`, returning the files with mismatches.
+    ///
+    /// The fixtures are not whole closures, so derivations with input
+    /// derivations missing from the directory are skipped.
+    fn verify_directory(name: &str) -> Vec<(String, Vec<OutputMismatch>)> {
+        let mut hasher = DerivationHasher::new(FileResolver::in_directory(directory(name)));
+        let mut failures = Vec::new();
+        for entry in fs::read_dir(directory(name)).unwrap() {
+            let path = entry.unwrap().path();
+            let derivation = parse_derivation_bytes(&fs::read(&path).unwrap()).unwrap();
+            let file_name = path.file_name().unwrap().to_str().unwrap().to_string();
+            let drv_name = file_name.get(33..).unwrap().strip_suffix(".drv").unwrap();
+            match hasher.verify_outputs(&derivation, drv_name) {
+                Ok(mismatches) if mismatches.is_empty() => {},
+                Ok(mismatches) => failures.push((file_name, mismatches)),
+                Err(OutputPathError::HashModulo(HashModuloError::Resolve(_, ReadDerivationError::Io(ref err))))
+                    if err.kind() == io::ErrorKind::NotFound => {},
+                Err(err) => panic!("{file_name}: {err}"),
+            }
+        }
+        failures.sort();
+        failures
+    }
+
+    #[test]
+    fn release_packages() {
+        assert_eq!(verify_directory("release_packages"), []);
+    }
+
+    #[test]
+    fn release_packages_ca() {
+        assert_eq!(verify_directory("release_packages_ca"), []);
+    }
+
+    #[test]
+    fn misc_derivations() {
+        assert_eq!(verify_directory("misc_derivations"), []);
+    }
+
+    #[test]
+    fn edge_case_derivations() {
+        let empty_env = StorePath::new("/nix/store/yj9wanf1s3wdvk65nv3p1yhyyzw29knh-empty-env").unwrap();
+        assert_eq!(verify_directory("edge_case_derivations"), [(
+            "nkqgqq2l1fpyifm974rf8yhxc1gzmihx-empty-env.drv".to_string(),
+            vec![OutputMismatch::Env {
+                output: "out".to_string(),
+                path: empty_env,
+                value: None,
+            }],
+        )]);
+    }
+
+    #[test]
+    fn fixed_output_paths() {
+        // The paths of the fixed outputs of the edge case derivations.
+        let store_dir = Path::new(DEFAULT_STORE_DIR);
+        let sha256 = |hex: &str| Hash::parse(hex, Some(HashAlgo::Sha256)).unwrap();
+        let hash = sha256("8d99142afd92576f30b0cd7cb42a8dc6809998bc5d607d88761f512e26c7db20");
+        assert_eq!(
+            make_fixed_output_path(store_dir, ContentAddressMethod::Flat, &hash, "hello-2.12.1.tar.gz"),
+            StorePath::new("/nix/store/pa10z4ngm0g83kx9mssrqzz30s84vq7k-hello-2.12.1.tar.gz")
+        );
+        let hash = sha256("7f41fcb8375371d995edf29be1d203d9c4758550050d73a14cdc347944b8e50f");
+        assert_eq!(
+            make_fixed_output_path(store_dir, ContentAddressMethod::Recursive, &hash, "bootstrap-tools"),
+            StorePath::new("/nix/store/waqfyli5yfyx45x53bs9wk3kacz7hmls-bootstrap-tools")
+        );
+    }
+
+    #[test]
+    fn tampered_output() {
+        let edge_cases = directory("edge_case_derivations");
+        let hello = fs::read(edge_cases.join("qhh9g08r8f6an2hly0p4rjh7q73myz93-hello-2.12.1.drv")).unwrap();
+        let mut derivation = parse_derivation_bytes(&hello).unwrap();
+        let tampered = StorePath::new("/nix/store/gwihsgkd13xmk8vwfn2k1nkdi9bys42x-hello-2.12.1-man").unwrap();
+        let man = derivation.outputs.insert("man".to_string(), DerivationOutput::InputAddressed(tampered.clone()));
+        derivation.env.insert("man".into(), tampered.as_str().into());
+        let mismatches = derivation.verify_outputs("hello-2.12.1", FileResolver::in_directory(edge_cases));
+        let computed = StorePath::new("/nix/store/dqqqvfg18cgpicphcby1281ck8dapj1g-hello-2.12.1-man").unwrap();
+        assert_eq!(man, Some(DerivationOutput::InputAddressed(computed.clone())));
+        assert!(matches!(
+            mismatches.unwrap().as_slice(),
+            [OutputMismatch::Path { output, recorded, computed: expected }]
+                if output == "man" && *recorded == tampered && *expected == computed
+        ));
+    }
+}
@@ -1,190 +1,527 @@
+use crate::derivations::errors::{
+    DerivationField,
+    DerivationParseError,
+    DerivationParseErrorKind,
+    ReadDerivationError,
+};
 use crate::derivations::types::{
+    BorrowedDerivation,
+    BorrowedDerivationInput,
+    BorrowedEnvironment,
+    BorrowedEnvironmentVariable,
     Derivation,
     DerivationInput,
     DerivationOutput,
+    DerivationVersion,
 };
-use crate::strings::parsers::parse_string;
+use crate::positions::Position;
+use crate::store_paths::types::StorePath;
+use crate::strings::parsers::parse_borrowed_string;
+use crate::strings::types::ByteString;
 
 extern crate alloc;
 
+use alloc::borrow::Cow;
 use alloc::string::String;
-use core::num::ParseIntError;
+use core::str;
 use nom::{
-    bytes::complete::tag,
+    branch::alt,
     combinator::{
-        all_consuming,
         map,
-        opt,
+        value,
     },
     error::{
-        FromExternalError,
+        ErrorKind,
         ParseError,
     },
-    multi::{
-        fold_many1,
-        separated_list0,
-        separated_list1,
-    },
     sequence::{
         delimited,
         preceded,
         separated_pair,
+        terminated,
         tuple,
     },
+    Err,
     IResult,
 };
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{
+    BTreeMap,
+    BTreeSet,
+};
+use std::io::Read;
+use std::ffi::{
+    OsStr,
+    OsString,
+};
+use std::os::unix::ffi::{
+    OsStrExt,
+    OsStringExt,
+};
+use std::path::{
+    Path,
+    PathBuf,
+};
 
-/// Parses a list of `DerivationOutput`s.
+/// The number of levels dynamic outputs of an input derivation can be nested
+/// in. Deeper inputs are rejected rather than overflowing the stack.
+pub const MAX_DYNAMIC_OUTPUT_DEPTH: usize = 64;
+
+/// The error used while parsing. It only borrows the input, and is turned into a
+/// `DerivationParseError` once parsing has failed.
+#[derive(Debug, PartialEq)]
+struct ParserError<'input> {
+    /// The remaining input where the error occurred.
+    input: &'input [u8],
+    kind: DerivationParseErrorKind,
+    /// The fields that were being parsed, from the innermost to the outermost.
+    field: Vec<DerivationField>,
+}
+
+impl<'input> ParserError<'input> {
+    /// Creates an error at `input`. Running out of input is always reported as
+    /// an unexpected end, no matter what was expected.
+    fn new(input: &'input [u8], kind: DerivationParseErrorKind) -> Self {
+        Self {
+            input,
+            kind: if input.is_empty() {
+                DerivationParseErrorKind::UnexpectedEnd
+            } else {
+                kind
+            },
+            field: Vec::new(),
+        }
+    }
+
+    /// Records that the error occurred inside `field`.
+    fn in_field(mut self, field: DerivationField) -> Self {
+        self.field.push(field);
+        self
+    }
+
+    /// Turns the error into a `DerivationParseError`, where `input` is the input
+    /// given to the parser and `start` is the position of its first byte.
+    fn into_parse_error(self, input: &[u8], start: Position) -> DerivationParseError {
+        let mut field = self.field;
+        field.reverse();
+        DerivationParseError {
+            kind: self.kind,
+            field,
+            position: start.advance(input.get(..input.len() - self.input.len()).unwrap_or(input)),
+        }
+    }
+}
+
+impl<'input> ParseError<&'input [u8]> for ParserError<'input> {
+    // The only nom parser used here that fails on its own is the one for the
+    // opening quote of a string.
+    #[inline]
+    fn from_error_kind(input: &'input [u8], _kind: ErrorKind) -> Self {
+        Self::new(input, DerivationParseErrorKind::ExpectedString)
+    }
+
+    #[inline]
+    fn append(_input: &'input [u8], _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+
+    // Report the alternative that got the furthest.
+    #[inline]
+    fn or(self, other: Self) -> Self {
+        if other.input.len() < self.input.len() {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+type ParserResult<'input, O> = IResult<&'input [u8], O, ParserError<'input>>;
+
+/// Fails with an error of the given kind at `input`.
+fn fail<O>(input: &[u8], kind: DerivationParseErrorKind) -> ParserResult<'_, O> {
+    Err(Err::Error(ParserError::new(input, kind)))
+}
+
+/// Returns the empty remainder of `input`, where errors about running out of
+/// input are reported.
+fn end_of(input: &[u8]) -> &[u8] {
+    input.get(input.len()..).unwrap_or_default()
+}
+
+/// Records that any error in `result` occurred inside the field returned by
+/// `field`.
+fn add_field<'input, O>(
+    result: ParserResult<'input, O>,
+    field: impl FnOnce() -> DerivationField,
+) -> ParserResult<'input, O> {
+    result.map_err(|err| err.map(|error| error.in_field(field())))
+}
+
+/// Records that any error from `parser` occurred inside `field`.
+fn in_field<'input, O, F>(field: DerivationField, mut parser: F) -> impl FnMut(&'input [u8]) -> ParserResult<'input, O>
+where
+    F: FnMut(&'input [u8]) -> ParserResult<'input, O> {
+    move |input| add_field(parser(input), || field.clone())
+}
+
+/// Parses exactly `expected`.
+fn token<'input>(expected: &'static str) -> impl FnMut(&'input [u8]) -> ParserResult<'input, ()> {
+    move |input: &'input [u8]| match input.strip_prefix(expected.as_bytes()) {
+        Some(remaining) => Ok((remaining, ())),
+        // The input stops partway through `expected`.
+        None if expected.as_bytes().starts_with(input) => fail(end_of(input), DerivationParseErrorKind::UnexpectedEnd),
+        None => fail(input, DerivationParseErrorKind::ExpectedToken(expected)),
+    }
+}
+
+/// Parses the `[` starting a list, returning whether the list has any items.
+fn parse_list_start(input: &[u8]) -> ParserResult<'_, bool> {
+    let (input, ()) = token("[")(input)?;
+    match input.strip_prefix(b"]") {
+        Some(remaining) => Ok((remaining, false)),
+        None if input.is_empty() => fail(input, DerivationParseErrorKind::UnexpectedEnd),
+        None => Ok((input, true)),
+    }
+}
+
+/// Parses the `[` starting the list of outputs.
 ///
 /// There must be at least one derivation output.
+fn parse_outputs_start(input: &[u8]) -> ParserResult<'_, bool> {
+    match parse_list_start(input)? {
+        (remaining, true) => Ok((remaining, true)),
+        // Point at the closing bracket, where an output was expected.
+        (_, false) => fail(input.get(1..).unwrap_or(input), DerivationParseErrorKind::ExpectedToken("(")),
+    }
+}
+
+/// Parses the `,` between two items of a list or the `]` ending it, returning
+/// whether another item follows.
+fn parse_list_separator(input: &[u8]) -> ParserResult<'_, bool> {
+    match input.split_first() {
+        Some((b',', remaining)) => Ok((remaining, true)),
+        Some((b']', remaining)) => Ok((remaining, false)),
+        _ => fail(input, DerivationParseErrorKind::ExpectedListSeparator),
+    }
+}
+
+/// Parses a list of items surrounded by square brackets and separated by
+/// commas, where `start` parses the opening bracket.
+///
+/// Unlike `separated_list0`, an error inside an item is reported as is instead
+/// of ending the list early.
+fn parse_list_from<'input, O, S, F>(
+    mut start: S,
+    mut item: F,
+) -> impl FnMut(&'input [u8]) -> ParserResult<'input, Vec<O>>
+where
+    S: FnMut(&'input [u8]) -> ParserResult<'input, bool>,
+    F: FnMut(&'input [u8]) -> ParserResult<'input, O> {
+    move |input| {
+        let (mut input, mut more) = start(input)?;
+        let mut items = Vec::new();
+        while more {
+            let (remaining, parsed) = item(input)?;
+            items.push(parsed);
+            (input, more) = parse_list_separator(remaining)?;
+        }
+        Ok((input, items))
+    }
+}
+
+/// Parses a list of items surrounded by square brackets and separated by
+/// commas. The list can be empty.
+fn parse_list<'input, O, F>(item: F) -> impl FnMut(&'input [u8]) -> ParserResult<'input, Vec<O>>
+where
+    F: FnMut(&'input [u8]) -> ParserResult<'input, O> {
+    parse_list_from(parse_list_start, item)
+}
+
+/// Collects parsed outputs into a map. Like Nix, the first output with a given
+/// name wins.
+fn collect_outputs<K: Ord, V>(outputs: Vec<(K, V)>) -> BTreeMap<K, V> {
+    outputs.into_iter().fold(BTreeMap::new(), |mut map, (key, value)| {
+        map.entry(key).or_insert(value);
+        map
+    })
+}
+
+/// Parses a string. Running out of input inside the string is an error.
+fn parse_byte_string(input: &[u8]) -> ParserResult<'_, Cow<'_, [u8]>> {
+    parse_borrowed_string(input).map_err(|err| match err {
+        Err::Incomplete(_) => Err::Error(ParserError::new(end_of(input), DerivationParseErrorKind::UnexpectedEnd)),
+        Err::Error(_) | Err::Failure(_) => err,
+    })
+}
+
+/// Parses a string that must be valid UTF-8.
+fn parse_utf8_string(input: &[u8]) -> ParserResult<'_, Cow<'_, str>> {
+    let (remaining, string) = parse_byte_string(input)?;
+    let string = match string {
+        Cow::Borrowed(bytes) => str::from_utf8(bytes).ok().map(Cow::Borrowed),
+        Cow::Owned(bytes) => String::from_utf8(bytes).ok().map(Cow::Owned),
+    };
+    match string {
+        Some(string) => Ok((remaining, string)),
+        None => fail(input, DerivationParseErrorKind::InvalidUtf8),
+    }
+}
+
+/// Parses a path. Paths may contain arbitrary bytes.
+fn parse_path(input: &[u8]) -> ParserResult<'_, Cow<'_, Path>> {
+    map(parse_byte_string, |string| match string {
+        Cow::Borrowed(bytes) => Cow::Borrowed(Path::new(OsStr::from_bytes(bytes))),
+        Cow::Owned(bytes) => Cow::Owned(PathBuf::from(OsString::from_vec(bytes))),
+    })(input)
+}
+
+/// Parses a store path, as used for input derivations and input sources.
+fn parse_store_path(input: &[u8]) -> ParserResult<'_, StorePath> {
+    let (remaining, path) = parse_utf8_string(input)?;
+    match StorePath::new(&*path) {
+        Ok(store_path) => Ok((remaining, store_path)),
+        Err(err) => fail(input, DerivationParseErrorKind::InvalidStorePath(path.into_owned(), err)),
+    }
+}
+
+/// Parses the path of an output, which is empty if it is not known in advance.
 #[expect(clippy::single_call_fn, reason = "Parser functions are not inlined for readability.")]
-fn parse_derivation_outputs(input: &str) -> IResult<&str, HashMap<String, DerivationOutput>> {
-    delimited(
-        tag("["),
-        fold_many1(tuple((parse_derivation_output, opt(tag(",")))), HashMap::new, |mut map, ((key, value), _)| {
-            map.insert(key, value);
-            map
-        }),
-        tag("]"),
-    )(input)
+fn parse_output_path(input: &[u8]) -> ParserResult<'_, Option<StorePath>> {
+    if input.starts_with(br#""""#) {
+        return Ok((input.get(2..).unwrap_or_default(), None));
+    }
+    map(parse_store_path, Some)(input)
+}
+
+/// Parses a list of `DerivationOutput`s.
+///
+/// There must be at least one derivation output.
+#[cfg_attr(
+    not(test),
+    expect(clippy::single_call_fn, reason = "Parser functions are not inlined for readability.")
+)]
+fn parse_derivation_outputs(input: &[u8]) -> ParserResult<'_, BTreeMap<Cow<'_, str>, DerivationOutput>> {
+    map(parse_list_from(parse_outputs_start, parse_derivation_output), collect_outputs)(input)
 }
 
 /// Parses a single `DerivationOutput`.
+fn parse_derivation_output(input: &[u8]) -> ParserResult<'_, (Cow<'_, str>, DerivationOutput)> {
+    let (input, key) = preceded(token("("), parse_utf8_string)(input)?;
+    let (input, output) =
+        add_field(parse_derivation_output_fields(input), || DerivationField::Output(key.to_string()))?;
+    Ok((input, (key, output)))
+}
+
+/// Parses the path, hash algorithm and hash of an output, and checks that they
+/// describe a kind of output.
 #[expect(clippy::single_call_fn, reason = "Parser functions are not inlined for readability.")]
-fn parse_derivation_output(input: &str) -> IResult<&str, (String, DerivationOutput)> {
-    delimited(
-        tag("("),
-        map(
-            tuple(
-                (
-                    parse_string,
-                    preceded(tag(","), parse_string),
-                    preceded(tag(","), parse_string),
-                    preceded(tag(","), parse_string),
-                ),
-            ),
-            |(key, path, hash_algo, hash)| {
-                (key, DerivationOutput {
-                    path: PathBuf::from(path),
-                    hash_algo,
-                    hash,
-                })
-            },
-        ),
-        tag(")"),
-    )(input)
+fn parse_derivation_output_fields(input: &[u8]) -> ParserResult<'_, DerivationOutput> {
+    let (input, path) = delimited(token(","), parse_output_path, token(","))(input)?;
+    let (remaining, (hash_algo, hash)) =
+        terminated(separated_pair(parse_utf8_string, token(","), parse_utf8_string), token(")"))(input)?;
+    match DerivationOutput::from_aterm_fields(path, &hash_algo, &hash) {
+        Ok(output) => Ok((remaining, output)),
+        // Point at the hash algorithm, which decides what kind of output this is.
+        Err(err) => fail(input, DerivationParseErrorKind::InvalidOutput(err)),
+    }
 }
 
 /// Parses a list of `DerivationInput`s.
 ///
-/// There must be at least one derivation input.
-#[expect(clippy::single_call_fn, reason = "Parser functions are not inlined for readability.")]
-fn parse_derivation_inputs(input: &str) -> IResult<&str, HashMap<PathBuf, DerivationInput>> {
-    delimited(
-        tag("["),
-        fold_many1(tuple((parse_derivation_input, opt(tag(",")))), HashMap::new, |mut map, ((key, value), _)| {
-            map.insert(key, value);
-            map
-        }),
-        tag("]"),
-    )(input)
+/// This list can be empty, e.g. for `builtin:fetchurl` derivations.
+#[cfg_attr(
+    not(test),
+    expect(clippy::single_call_fn, reason = "Parser functions are not inlined for readability.")
+)]
+fn parse_derivation_inputs(
+    version: DerivationVersion,
+    input: &[u8],
+) -> ParserResult<'_, BTreeMap<StorePath, BorrowedDerivationInput<'_>>> {
+    // Like Nix, the last input with a given path wins.
+    map(parse_list(|input| parse_derivation_input(version, input)), |inputs| inputs.into_iter().collect())(input)
 }
 
-/// Parses a single `DerivationInput`.
+/// Parses a list of output names.
+///
+/// This list can be empty.
+fn parse_output_names(input: &[u8]) -> ParserResult<'_, Vec<Cow<'_, str>>> {
+    parse_list(parse_utf8_string)(input)
+}
+
+/// Parses a single dynamic output of an input derivation: the name of the output
+/// and the outputs of the derivation it produces, nested `depth` levels deep.
 #[expect(clippy::single_call_fn, reason = "Parser functions are not inlined for readability.")]
-fn parse_derivation_input(input: &str) -> IResult<&str, (PathBuf, DerivationInput)> {
-    delimited(
-        tag("("),
-        map(
-            separated_pair(
-                parse_string,
-                tag(","),
-                delimited(tag("["), separated_list1(tag(","), parse_string), tag("]")),
+fn parse_dynamic_output(
+    version: DerivationVersion,
+    depth: usize,
+    input: &[u8],
+) -> ParserResult<'_, (Cow<'_, str>, BorrowedDerivationInput<'_>)> {
+    let (input, name) = preceded(token("("), parse_utf8_string)(input)?;
+    let (input, outputs) =
+        add_field(
+            delimited(token(","), |input| parse_derivation_input_outputs(version, depth, input), token(")"))(input),
+            || DerivationField::DynamicOutput(name.to_string()),
+        )?;
+    Ok((input, (name, outputs)))
+}
+
+/// Parses the outputs used from an input derivation.
+///
+/// This is either a plain list of output names, or, for dynamic derivations, a
+/// pair of a list of output names and a list of dynamic outputs. Like Nix, the
+/// pair is only accepted in the `DrvWithVersion("xp-dyn-drv",...)` format.
+/// `depth` is the number of dynamic outputs the outputs are nested in.
+fn parse_derivation_input_outputs(
+    version: DerivationVersion,
+    depth: usize,
+    input: &[u8],
+) -> ParserResult<'_, BorrowedDerivationInput<'_>> {
+    let mut plain_outputs = map(parse_output_names, |value| BorrowedDerivationInput {
+        value: value.into_iter().collect(),
+        dynamic_outputs: BTreeMap::new(),
+    });
+    if version == DerivationVersion::Traditional {
+        return plain_outputs(input);
+    }
+    if depth == MAX_DYNAMIC_OUTPUT_DEPTH && input.starts_with(b"(") {
+        return fail(input, DerivationParseErrorKind::TooDeep);
+    }
+    alt(
+        (
+            plain_outputs,
+            map(
+                delimited(
+                    token("("),
+                    separated_pair(
+                        parse_output_names,
+                        token(","),
+                        parse_list(|input| parse_dynamic_output(version, depth + 1, input)),
+                    ),
+                    token(")"),
+                ),
+                |(value, dynamic_outputs)| BorrowedDerivationInput {
+                    value: value.into_iter().collect(),
+                    dynamic_outputs: dynamic_outputs.into_iter().collect(),
+                },
             ),
-            |(key, value)| (PathBuf::from(key), DerivationInput { value }),
         ),
-        tag(")"),
-    )(
-        input,
-    )
+    )(input)
+}
+
+/// Parses a single `DerivationInput`.
+fn parse_derivation_input(
+    version: DerivationVersion,
+    input: &[u8],
+) -> ParserResult<'_, (StorePath, BorrowedDerivationInput<'_>)> {
+    let (input, path) = preceded(token("("), parse_store_path)(input)?;
+    let (input, outputs) =
+        add_field(
+            delimited(token(","), |input| parse_derivation_input_outputs(version, 0, input), token(")"))(input),
+            || DerivationField::InputDrv(path.clone()),
+        )?;
+    Ok((input, (path, outputs)))
 }
 
 /// Parses a list of source inputs.
+///
+/// This list can be empty.
 #[expect(clippy::single_call_fn, reason = "Parser functions are not inlined for readability.")]
-fn parse_source_inputs<'input, E>(input: &'input str) -> IResult<&'input str, Vec<PathBuf>, E>
-where
-    E: ParseError<&'input str> + FromExternalError<&'input str, ParseIntError> {
-    delimited(tag("["), separated_list0(tag(","), map(parse_string, PathBuf::from)), tag("]"))(input)
+fn parse_source_inputs(input: &[u8]) -> ParserResult<'_, BTreeSet<StorePath>> {
+    map(parse_list(parse_store_path), |paths| paths.into_iter().collect())(input)
 }
 
 /// Parses a system.
-#[expect(clippy::single_call_fn, reason = "Parser functions are not inlined for readability.")]
-fn parse_system<'input, E>(input: &'input str) -> IResult<&'input str, String, E>
-where
-    E: ParseError<&'input str> + FromExternalError<&'input str, ParseIntError> {
-    parse_string(input)
+fn parse_system(input: &[u8]) -> ParserResult<'_, Cow<'_, str>> {
+    parse_utf8_string(input)
 }
 
 /// Parses a builder.
-#[expect(clippy::single_call_fn, reason = "Parser functions are not inlined for readability.")]
-fn parse_builder<'input, E>(input: &'input str) -> IResult<&'input str, PathBuf, E>
-where
-    E: ParseError<&'input str> + FromExternalError<&'input str, ParseIntError> {
-    map(parse_string, PathBuf::from)(input)
+fn parse_builder(input: &[u8]) -> ParserResult<'_, Cow<'_, Path>> {
+    parse_path(input)
 }
 
 /// Parses a list of builder arguments.
 ///
 /// This list can be empty.
 #[expect(clippy::single_call_fn, reason = "Parser functions are not inlined for readability.")]
-fn parse_builder_args<'input, E>(input: &'input str) -> IResult<&'input str, Vec<String>, E>
-where
-    E: ParseError<&'input str> + FromExternalError<&'input str, ParseIntError> {
-    delimited(tag("["), separated_list0(tag(","), parse_string), tag("]"))(input)
+fn parse_builder_args(input: &[u8]) -> ParserResult<'_, Vec<Cow<'_, [u8]>>> {
+    parse_list(parse_byte_string)(input)
 }
 
 /// Parses a single environment variable.
-#[expect(clippy::single_call_fn, reason = "Parser functions are not inlined for readability.")]
-fn parse_environment_variable<'input, E>(input: &'input str) -> IResult<&'input str, (String, String), E>
-where
-    E: ParseError<&'input str> + FromExternalError<&'input str, ParseIntError> {
-    delimited(tag("("), separated_pair(parse_string, tag(","), parse_string), tag(")"))(input)
+fn parse_environment_variable(input: &[u8]) -> ParserResult<'_, BorrowedEnvironmentVariable<'_>> {
+    let (input, key) = preceded(token("("), parse_byte_string)(input)?;
+    let (input, value) =
+        add_field(
+            delimited(token(","), parse_byte_string, token(")"))(input),
+            || DerivationField::EnvVar(ByteString::from(&*key)),
+        )?;
+    Ok((input, (key, value)))
 }
 
 /// Parses a list of environment variables.
 ///
-/// This list can be empty.
+/// This list can be empty. Like Nix, the last value of a repeated key wins.
 #[expect(clippy::single_call_fn, reason = "Parser functions are not inlined for readability.")]
-fn parse_environment_variables<'input, E>(input: &'input str) -> IResult<&'input str, Vec<(String, String)>, E>
-where
-    E: ParseError<&'input str> + FromExternalError<&'input str, ParseIntError> {
-    delimited(tag("["), separated_list0(tag(","), parse_environment_variable), tag("]"))(input)
+fn parse_environment_variables(input: &[u8]) -> ParserResult<'_, BorrowedEnvironment<'_>> {
+    map(parse_list(parse_environment_variable), |variables| variables.into_iter().collect())(input)
 }
 
-/// Parses a `Derivation`.
-#[inline]
-pub fn parse_derivation(input: &str) -> IResult<&str, Derivation> {
+/// Parses the version named in a `DrvWithVersion(` header.
+#[expect(clippy::single_call_fn, reason = "Parser functions are not inlined for readability.")]
+fn parse_version_name(input: &[u8]) -> ParserResult<'_, DerivationVersion> {
+    let (remaining, version) = parse_utf8_string(input)?;
+    match &*version {
+        "xp-dyn-drv" => Ok((remaining, DerivationVersion::DynamicDerivations)),
+        _ => fail(input, DerivationParseErrorKind::UnknownVersion(version.into_owned())),
+    }
+}
+
+/// Parses the header of a derivation, which determines its `DerivationVersion`.
+fn parse_derivation_version(input: &[u8]) -> ParserResult<'_, DerivationVersion> {
+    alt(
+        (
+            value(DerivationVersion::Traditional, token("Derive(")),
+            preceded(token("DrvWithVersion("), terminated(parse_version_name, token(","))),
+        ),
+    )(input).map_err(|err| {
+        err.map(|error| {
+            // Neither header matched at all, so report that either was expected.
+            if error.input.len() == input.len() {
+                ParserError::new(input, DerivationParseErrorKind::ExpectedHeader)
+            } else {
+                error
+            }
+        })
+    })
+}
+
+/// Parses all the fields of a `Derivation`, stopping after its closing
+/// parenthesis.
+#[expect(clippy::single_call_fn, reason = "Parser functions are not inlined for readability.")]
+fn parse_derivation_fields(input: &[u8]) -> ParserResult<'_, BorrowedDerivation<'_>> {
+    // The version decides how input derivations are parsed.
+    let (input, version) = in_field(DerivationField::Header, parse_derivation_version)(input)?;
     map(
-        all_consuming(
-            delimited(
-                tag("Derive("),
-                tuple(
-                    (
-                        parse_derivation_outputs,
-                        preceded(tag(","), parse_derivation_inputs),
-                        preceded(tag(","), parse_source_inputs),
-                        preceded(tag(","), parse_system),
-                        preceded(tag(","), parse_builder),
-                        preceded(tag(","), parse_builder_args),
-                        preceded(tag(","), parse_environment_variables),
+        terminated(
+            tuple(
+                (
+                    in_field(DerivationField::Outputs, parse_derivation_outputs),
+                    in_field(
+                        DerivationField::InputDrvs,
+                        preceded(token(","), move |input| parse_derivation_inputs(version, input)),
                     ),
+                    in_field(DerivationField::InputSrcs, preceded(token(","), parse_source_inputs)),
+                    in_field(DerivationField::System, preceded(token(","), parse_system)),
+                    in_field(DerivationField::Builder, preceded(token(","), parse_builder)),
+                    in_field(DerivationField::Args, preceded(token(","), parse_builder_args)),
+                    in_field(DerivationField::Env, preceded(token(","), parse_environment_variables)),
                 ),
-                tag(")"),
             ),
+            token(")"),
         ),
-        |(outputs, input_drvs, input_srcs, system, builder, args, env)| Derivation {
+        move |(outputs, input_drvs, input_srcs, system, builder, args, env)| BorrowedDerivation {
+            version,
             outputs,
             input_drvs,
             input_srcs,
@@ -196,17 +533,279 @@ pub fn parse_derivation(input: &str) -> IResult<&str, Derivation> {
     )(input)
 }
 
+/// Parses a single `DerivationOutput`, copying it out of the input.
+#[cfg_attr(
+    not(test),
+    expect(clippy::single_call_fn, reason = "Parser functions are not inlined for readability.")
+)]
+fn parse_owned_derivation_output(input: &[u8]) -> ParserResult<'_, (String, DerivationOutput)> {
+    map(parse_derivation_output, |(name, output)| (name.into_owned(), output))(input)
+}
+
+/// Parses a single `DerivationInput`, copying it out of the input.
+#[cfg_attr(
+    not(test),
+    expect(clippy::single_call_fn, reason = "Parser functions are not inlined for readability.")
+)]
+fn parse_owned_derivation_input(
+    version: DerivationVersion,
+    input: &[u8],
+) -> ParserResult<'_, (StorePath, DerivationInput)> {
+    map(|input| parse_derivation_input(version, input), |(path, input)| (path, input.into_owned()))(input)
+}
+
+/// The smallest number of bytes read from the reader at a time.
+const READ_SIZE: usize = 8 * 1024;
+
+/// Parses a derivation from a reader one item at a time, e.g. a single output or
+/// environment variable, only keeping the item being parsed in memory.
+struct StreamParser<R> {
+    reader: R,
+    /// Bytes read from `reader` that have not been parsed yet.
+    buffer: Vec<u8>,
+    /// The position of the first byte of `buffer` in the whole input.
+    position: Position,
+    /// Whether `reader` has reached the end of its input.
+    eof: bool,
+}
+
+impl<R: Read> StreamParser<R> {
+    /// Reads more input into the buffer.
+    ///
+    /// At least as many bytes as are already buffered are read, so parsing a long
+    /// item again after every read stays linear in its length.
+    fn fill(&mut self) -> Result<(), ReadDerivationError> {
+        let target = self.buffer.len().max(READ_SIZE);
+        let read = (&mut self.reader).take(target as u64).read_to_end(&mut self.buffer)?;
+        self.eof = read < target;
+        Ok(())
+    }
+
+    /// Runs `parser` on the buffered input, reading more input for as long as it
+    /// runs out of it. Errors are reported as inside `field`, if any.
+    fn parse<O, F>(&mut self, field: Option<&DerivationField>, mut parser: F) -> Result<O, ReadDerivationError>
+    where
+        F: FnMut(&[u8]) -> ParserResult<'_, O> {
+        loop {
+            match parser(&self.buffer) {
+                Ok((remaining, output)) => {
+                    let parsed = self.buffer.len() - remaining.len();
+                    self.position = self.position.advance(self.buffer.get(..parsed).unwrap_or_default());
+                    self.buffer.drain(..parsed);
+                    return Ok(output);
+                },
+                Err(Err::Error(error) | Err::Failure(error))
+                    if error.kind != DerivationParseErrorKind::UnexpectedEnd || self.eof => {
+                    let error = match field {
+                        Some(field) => error.in_field(field.clone()),
+                        None => error,
+                    };
+                    return Err(error.into_parse_error(&self.buffer, self.position).into());
+                },
+                Err(_) => {},
+            }
+            self.fill()?;
+        }
+    }
+
+    /// Parses a list of items, where `start` parses the opening bracket.
+    fn parse_list<O, S, F>(
+        &mut self,
+        field: &DerivationField,
+        start: S,
+        mut item: F,
+    ) -> Result<Vec<O>, ReadDerivationError>
+    where
+        S: FnMut(&[u8]) -> ParserResult<'_, bool>,
+        F: FnMut(&[u8]) -> ParserResult<'_, O> {
+        let mut more = self.parse(Some(field), start)?;
+        let mut items = Vec::new();
+        while more {
+            items.push(self.parse(Some(field), &mut item)?);
+            more = self.parse(Some(field), parse_list_separator)?;
+        }
+        Ok(items)
+    }
+
+    /// Parses the rest of the input as a `Derivation`.
+    fn parse_derivation(&mut self) -> Result<Derivation, ReadDerivationError> {
+        let version = self.parse(Some(&DerivationField::Header), parse_derivation_version)?;
+        let outputs =
+            self.parse_list(&DerivationField::Outputs, parse_outputs_start, parse_owned_derivation_output)?;
+        self.parse(Some(&DerivationField::InputDrvs), |input| token(",")(input))?;
+        let input_drvs =
+            self.parse_list(&DerivationField::InputDrvs, parse_list_start, |input| {
+                parse_owned_derivation_input(version, input)
+            })?;
+        self.parse(Some(&DerivationField::InputSrcs), |input| token(",")(input))?;
+        let input_srcs =
+            self.parse_list(&DerivationField::InputSrcs, parse_list_start, parse_store_path)?;
+        let system =
+            self.parse(Some(&DerivationField::System), |input| {
+                preceded(token(","), map(parse_system, Cow::into_owned))(input)
+            })?;
+        let builder =
+            self.parse(Some(&DerivationField::Builder), |input| {
+                preceded(token(","), map(parse_builder, Cow::into_owned))(input)
+            })?;
+        self.parse(Some(&DerivationField::Args), |input| token(",")(input))?;
+        let args =
+            self.parse_list(&DerivationField::Args, parse_list_start, |input| {
+                map(parse_byte_string, |arg| ByteString::from(arg.into_owned()))(input)
+            })?;
+        self.parse(Some(&DerivationField::Env), |input| token(",")(input))?;
+        let env =
+            self.parse_list(&DerivationField::Env, parse_list_start, |input| {
+                map(parse_environment_variable, |(key, value)| {
+                    (ByteString::from(key.into_owned()), ByteString::from(value.into_owned()))
+                })(input)
+            })?;
+        self.parse(None, |input| token(")")(input))?;
+
+        // Like `parse_derivation_bytes`, the derivation must be the whole input.
+        while self.buffer.is_empty() && !self.eof {
+            self.fill()?;
+        }
+        if !self.buffer.is_empty() {
+            return Err(
+                ParserError::new(&self.buffer, DerivationParseErrorKind::TrailingData)
+                    .into_parse_error(&self.buffer, self.position)
+                    .into(),
+            );
+        }
+        Ok(Derivation {
+            version,
+            outputs: collect_outputs(outputs),
+            input_drvs: input_drvs.into_iter().collect(),
+            input_srcs: input_srcs.into_iter().collect(),
+            system,
+            builder,
+            args,
+            env: env.into_iter().collect(),
+        })
+    }
+}
+
+/// Reads a `Derivation` from `reader`, parsing it while it is being read.
+///
+/// Only the item currently being parsed is buffered, e.g. a single output or
+/// environment variable, so huge derivations such as ones with structured
+/// attributes in `__json` never have to be read into memory as a whole. Input is
+/// read in chunks, so `reader` does not need to be buffered. `reader` is read to
+/// its end, which must also be the end of the derivation.
+///
+/// # Errors
+///
+/// Returns a `ReadDerivationError` if reading from `reader` fails or if what is
+/// read is not exactly one derivation.
+#[inline]
+pub fn read_derivation<R: Read>(reader: R) -> Result<Derivation, ReadDerivationError> {
+    StreamParser {
+        reader,
+        buffer: Vec::new(),
+        position: Position::START,
+        eof: false,
+    }.parse_derivation()
+}
+
+/// Parses a `BorrowedDerivation` from raw bytes.
+///
+/// Strings without escapes are borrowed from `input` instead of being copied,
+/// which makes this the fastest way to look at many `.drv` files. Use
+/// `BorrowedDerivation::into_owned` to keep the result around after `input` is
+/// gone.
+///
+/// Both the traditional `Derive(...)` format and the dynamic derivations
+/// `DrvWithVersion("xp-dyn-drv",...)` format are accepted. Output names, hash
+/// algorithms, hashes and the system must be valid UTF-8; everything else may
+/// contain arbitrary bytes.
+///
+/// # Errors
+///
+/// Returns a `DerivationParseError` describing where and why parsing failed if
+/// `input` is not exactly one derivation.
+#[inline]
+pub fn parse_borrowed_derivation(input: &[u8]) -> Result<BorrowedDerivation<'_>, DerivationParseError> {
+    match parse_derivation_fields(input) {
+        Ok(([], derivation)) => Ok(derivation),
+        Ok((remaining, _)) => {
+            Err(
+                ParserError::new(remaining, DerivationParseErrorKind::TrailingData)
+                    .into_parse_error(input, Position::START),
+            )
+        },
+        Err(Err::Error(error) | Err::Failure(error)) => Err(error.into_parse_error(input, Position::START)),
+        Err(Err::Incomplete(_)) => {
+            Err(
+                ParserError::new(end_of(input), DerivationParseErrorKind::UnexpectedEnd)
+                    .into_parse_error(input, Position::START),
+            )
+        },
+    }
+}
+
+/// Parses a `Derivation` from raw bytes.
+///
+/// Both the traditional `Derive(...)` format and the dynamic derivations
+/// `DrvWithVersion("xp-dyn-drv",...)` format are accepted.
+///
+/// Nix does not require the contents of a `.drv` file to be valid UTF-8, so this
+/// is the parser to use for files read with `fs::read`. Output names, hash
+/// algorithms, hashes and the system must still be valid UTF-8.
+///
+/// # Errors
+///
+/// Returns a `DerivationParseError` describing where and why parsing failed if
+/// `input` is not exactly one derivation.
+#[inline]
+pub fn parse_derivation_bytes(input: &[u8]) -> Result<Derivation, DerivationParseError> {
+    parse_borrowed_derivation(input).map(BorrowedDerivation::into_owned)
+}
+
+/// Parses a `Derivation`.
+///
+/// This is a convenience wrapper around `parse_derivation_bytes` for input that
+/// is already known to be valid UTF-8.
+///
+/// # Errors
+///
+/// Returns a `DerivationParseError` describing where and why parsing failed if
+/// `input` is not exactly one derivation.
+#[inline]
+pub fn parse_derivation(input: &str) -> Result<Derivation, DerivationParseError> {
+    parse_derivation_bytes(input.as_bytes())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use nom::{
-        error::ErrorKind,
-        error_position,
-        Err,
+    use crate::derivations::errors::DerivationOutputError;
+    use crate::derivations::types::ContentAddressMethod;
+    use crate::hashes::errors::HashError;
+    use crate::hashes::types::{
+        Hash,
+        HashAlgo,
     };
+    use crate::store_paths::errors::StorePathError;
     use std::fs;
     use std::path::Path;
 
+    fn input_addressed(path: &str) -> DerivationOutput {
+        DerivationOutput::InputAddressed(StorePath::new(path).unwrap())
+    }
+
+    fn owned_outputs(input: &[u8]) -> ParserResult<'_, BTreeMap<String, DerivationOutput>> {
+        parse_derivation_outputs(input).map(|(remaining, outputs)| {
+            (remaining, outputs.into_iter().map(|(name, output)| (name.into_owned(), output)).collect())
+        })
+    }
+
+    fn owned_inputs(input: &[u8]) -> ParserResult<'_, BTreeMap<StorePath, DerivationInput>> {
+        parse_derivation_inputs(DerivationVersion::Traditional, input).map(|(remaining, inputs)| {
+            (remaining, inputs.into_iter().map(|(path, input)| (path, input.into_owned())).collect())
+        })
+    }
+
     #[test]
     fn release_packages() {
         let derivation_file_path =
@@ -242,40 +841,293 @@ mod tests {
         }
     }
 
+    #[test]
+    fn edge_case_derivations() {
+        let derivation_file_path =
+            Path::new(&std::env::var_os("CARGO_MANIFEST_DIR").unwrap()).join("src/derivations/edge_case_derivations");
+        let paths = fs::read_dir(derivation_file_path).unwrap();
+        for path in paths {
+            let drv_string = fs::read_to_string(path.expect("There should be files here!").path());
+            assert!(parse_derivation(&drv_string.unwrap()).is_ok())
+        }
+    }
+
+    #[test]
+    fn invalid_derivations() {
+        let derivation_file_path =
+            Path::new(&std::env::var_os("CARGO_MANIFEST_DIR").unwrap()).join("src/derivations/invalid_derivations");
+        let paths = fs::read_dir(derivation_file_path).unwrap();
+        for path in paths {
+            let path = path.expect("There should be files here!").path();
+            let drv_string = fs::read_to_string(&path);
+            assert!(parse_derivation(&drv_string.unwrap()).is_err(), "{} should not parse", path.display())
+        }
+    }
+
+    #[test]
+    fn non_utf8_derivation() {
+        let drv_bytes = [
+            br#"Derive("#.as_slice(),
+            br#"[("out","/nix/store/gwihsgkd13xmk8vwfn2k1nkdi9bys42x-shadow-4.14.6","","")],"#,
+            br#"[("/nix/store/wql9zbydwdr0nqxkm20crcbhn68wb4pc-stdenv-linux.drv",["out"])],"#,
+            br#"[],"x86_64-linux","/bin/sh",["-c","#,
+            b"\"\xff\"],[(\"\xfe\",\"\xff\\n\")])",
+        ].concat();
+        let derivation = parse_derivation_bytes(&drv_bytes).unwrap();
+        assert_eq!(derivation.args, vec![ByteString::from("-c"), ByteString::from(b"\xff".as_slice())]);
+        assert_eq!(
+            derivation.env,
+            BTreeMap::from([(ByteString::from(b"\xfe".as_slice()), ByteString::from(b"\xff\n".as_slice()))])
+        );
+    }
+
     #[test]
     fn derivation_output_all_empty() {
-        assert_eq!(parse_derivation_output(r#"("","","","")"#), Ok(("", ("".to_string(), DerivationOutput {
-            path: PathBuf::from(""),
-            hash_algo: "".to_string(),
-            hash: "".to_string(),
-        }))));
+        assert_eq!(
+            parse_owned_derivation_output(br#"("","","","")"#),
+            Ok((b"".as_slice(), ("".to_string(), DerivationOutput::Deferred)))
+        );
     }
 
     #[test]
     fn derivation_output_minimal() {
         assert_eq!(
-            parse_derivation_output(
-                r#"("out","/nix/store/l5x91w2x83z33alsm5pmgl1gslbaqiyy-nixos-system-massflash-24.05.20241009.d51c286","","")"#,
+            parse_owned_derivation_output(
+                br#"("out","/nix/store/l5x91w2x83z33alsm5pmgl1gslbaqiyy-nixos-system-massflash-24.05.20241009.d51c286","","")"#,
             ),
-            Ok(("", ("out".to_string(), DerivationOutput {
-                path: PathBuf::from(
+            Ok((b"".as_slice(), ("out".to_string(), DerivationOutput::InputAddressed(
+                StorePath::new(
                     "/nix/store/l5x91w2x83z33alsm5pmgl1gslbaqiyy-nixos-system-massflash-24.05.20241009.d51c286",
-                ),
-                hash_algo: "".to_string(),
-                hash: "".to_string(),
+                ).unwrap(),
+            ))))
+        );
+    }
+
+    #[test]
+    fn derivation_output_kinds() {
+        let path = StorePath::new("/nix/store/waqfyli5yfyx45x53bs9wk3kacz7hmls-bootstrap-tools").unwrap();
+        let hash = "7f41fcb8375371d995edf29be1d203d9c4758550050d73a14cdc347944b8e50f";
+        assert_eq!(
+            parse_owned_derivation_output(format!(r#"("out","{path}","r:sha256","{hash}")"#).as_bytes()),
+            Ok((b"".as_slice(), ("out".to_string(), DerivationOutput::CaFixed {
+                path,
+                method: ContentAddressMethod::Recursive,
+                hash: Hash::parse(hash, Some(HashAlgo::Sha256)).unwrap(),
+            })))
+        );
+        assert_eq!(
+            parse_owned_derivation_output(br#"("out","","text:sha256","")"#),
+            Ok((b"".as_slice(), ("out".to_string(), DerivationOutput::CaFloating {
+                method: ContentAddressMethod::Text,
+                hash_algo: HashAlgo::Sha256,
+            })))
+        );
+        assert_eq!(
+            parse_owned_derivation_output(br#"("out","","sha1","impure")"#),
+            Ok((b"".as_slice(), ("out".to_string(), DerivationOutput::Impure {
+                method: ContentAddressMethod::Flat,
+                hash_algo: HashAlgo::Sha1,
             })))
         );
     }
 
+    #[test]
+    fn derivation_output_invalid() {
+        assert_eq!(
+            parse_owned_derivation_output(br#"("out","","r:blake3","")"#),
+            Err(Err::Error(ParserError::new(
+                br#""r:blake3","")"#.as_slice(),
+                DerivationParseErrorKind::InvalidOutput(DerivationOutputError::InvalidHashAlgo(
+                    HashError::UnknownAlgo("blake3".to_string()),
+                )),
+            ).in_field(DerivationField::Output("out".to_string()))))
+        );
+        assert_eq!(
+            parse_owned_derivation_output(br#"("out","","","7f41fcb8")"#),
+            Err(Err::Error(ParserError::new(
+                br#""","7f41fcb8")"#.as_slice(),
+                DerivationParseErrorKind::InvalidOutput(DerivationOutputError::UnexpectedHash),
+            ).in_field(DerivationField::Output("out".to_string()))))
+        );
+        assert_eq!(
+            parse_owned_derivation_output(
+                br#"("out","/nix/store/waqfyli5yfyx45x53bs9wk3kacz7hmls-bootstrap-tools","sha256","7f41fcb8")"#,
+            ),
+            Err(Err::Error(ParserError::new(
+                br#""sha256","7f41fcb8")"#.as_slice(),
+                DerivationParseErrorKind::InvalidOutput(DerivationOutputError::InvalidHash(HashError::InvalidLength(
+                    HashAlgo::Sha256,
+                    8,
+                ))),
+            ).in_field(DerivationField::Output("out".to_string()))))
+        );
+        assert_eq!(
+            parse_owned_derivation_output(br#"("out","","sha256","7f41fcb8")"#),
+            Err(Err::Error(ParserError::new(
+                br#""sha256","7f41fcb8")"#.as_slice(),
+                DerivationParseErrorKind::InvalidOutput(DerivationOutputError::MissingPath),
+            ).in_field(DerivationField::Output("out".to_string()))))
+        );
+    }
+
+    #[test]
+    fn derivation_kinds() {
+        let read = |name: &str| {
+            let path = Path::new(&std::env::var_os("CARGO_MANIFEST_DIR").unwrap())
+                .join("src/derivations/edge_case_derivations")
+                .join(name);
+            parse_derivation_bytes(&fs::read(path).unwrap()).unwrap()
+        };
+        let fixed = read("13z54gdcxxfdiy5wrpghjshkr6235d93-bootstrap-tools.drv");
+        assert!(fixed.is_fixed_output() && fixed.is_content_addressed());
+        let floating = read("hnm5s456v5wky252cv71ni8cqaphf11i-hello.drv.drv");
+        assert!(!floating.is_fixed_output() && floating.is_content_addressed());
+        let deferred = read("ng1vcj72jjzc3a029xxvlb3xkrf4kl2h-use-hello.drv");
+        assert!(!deferred.is_fixed_output() && !deferred.is_content_addressed());
+        assert_eq!(deferred.outputs.get("out"), Some(&DerivationOutput::Deferred));
+    }
+
     #[test]
     fn derivation_outputs_empty() {
-        assert_eq!(parse_derivation_outputs(r#"[]"#), Err(Err::Error(error_position!("]", ErrorKind::Many1))));
+        assert_eq!(
+            owned_outputs(br#"[]"#),
+            Err(Err::Error(ParserError::new(b"]".as_slice(), DerivationParseErrorKind::ExpectedToken("("))))
+        );
+    }
+
+    #[test]
+    fn derivation_outputs_missing_comma() {
+        assert!(
+            owned_outputs(
+                concat!(
+                    r#"["#,
+                    r#"("dev","/nix/store/0fji8fg0z6gi3zyvsad7gxamx4ca2477-shadow-4.14.6-dev","","")"#,
+                    r#"("out","/nix/store/gwihsgkd13xmk8vwfn2k1nkdi9bys42x-shadow-4.14.6","","")"#,
+                    r#"]"#
+                ).as_bytes(),
+            ).is_err()
+        );
+    }
+
+    #[test]
+    fn derivation_inputs_empty() {
+        assert_eq!(owned_inputs(br#"[]"#), Ok((b"".as_slice(), BTreeMap::new())));
+    }
+
+    #[test]
+    fn derivation_input_no_outputs() {
+        assert_eq!(
+            parse_owned_derivation_input(
+                DerivationVersion::Traditional,
+                br#"("/nix/store/81l2lyg7hx6zwlb7yamncj1b2pbz5rj1-tcb-1.2.drv",[])"#,
+            ),
+            Ok(
+                (
+                    b"".as_slice(),
+                    (
+                        StorePath::new("/nix/store/81l2lyg7hx6zwlb7yamncj1b2pbz5rj1-tcb-1.2.drv").unwrap(),
+                        DerivationInput {
+                            value: BTreeSet::new(),
+                            dynamic_outputs: BTreeMap::new(),
+                        },
+                    ),
+                ),
+            )
+        );
+    }
+
+    #[test]
+    fn derivation_input_dynamic_outputs() {
+        assert_eq!(
+            parse_owned_derivation_input(
+                DerivationVersion::DynamicDerivations,
+                br#"("/nix/store/hnm5s456v5wky252cv71ni8cqaphf11i-hello.drv.drv",(["out"],[("out",(["dev"],[("lib",["out"])]))]))"#,
+            ),
+            Ok(
+                (
+                    b"".as_slice(),
+                    (
+                        StorePath::new("/nix/store/hnm5s456v5wky252cv71ni8cqaphf11i-hello.drv.drv").unwrap(),
+                        DerivationInput {
+                            value: BTreeSet::from(["out".to_string()]),
+                            dynamic_outputs: BTreeMap::from([("out".to_string(), DerivationInput {
+                                value: BTreeSet::from(["dev".to_string()]),
+                                dynamic_outputs: BTreeMap::from([("lib".to_string(), DerivationInput {
+                                    value: BTreeSet::from(["out".to_string()]),
+                                    dynamic_outputs: BTreeMap::new(),
+                                })]),
+                            })]),
+                        },
+                    ),
+                ),
+            )
+        );
+    }
+
+    #[test]
+    fn derivation_input_dynamic_outputs_too_deep() {
+        let nested = |depth| {
+            format!(
+                r#"DrvWithVersion("xp-dyn-drv",[("out","","","")],[("/nix/store/hnm5s456v5wky252cv71ni8cqaphf11i-hello.drv.drv",{}["out"]{})],[],"x86_64-linux","/bin/sh",[],[])"#,
+                r#"(["out"],[("out","#.repeat(depth),
+                ")])".repeat(depth),
+            )
+        };
+        let deepest = nested(MAX_DYNAMIC_OUTPUT_DEPTH);
+        assert_eq!(parse_derivation(&deepest).unwrap().to_string(), deepest);
+        let error = parse_derivation(&nested(200_000)).unwrap_err();
+        assert_eq!(error.kind, DerivationParseErrorKind::TooDeep);
+        // The error points at the first dynamic output that is nested too deeply.
+        assert_eq!(error.position.offset, deepest.rfind(r#"["out"]"#).unwrap());
+        let Err(ReadDerivationError::Parse(error)) = read_derivation(nested(200_000).as_bytes()) else {
+            panic!("deeply nested dynamic outputs should not parse");
+        };
+        assert_eq!(error.kind, DerivationParseErrorKind::TooDeep);
+    }
+
+    #[test]
+    fn derivation_version() {
+        let derivation =
+            parse_derivation(
+                r#"DrvWithVersion("xp-dyn-drv",[("out","","","")],[],[],"x86_64-linux","/bin/sh",[],[])"#,
+            ).unwrap();
+        assert_eq!(derivation.version, DerivationVersion::DynamicDerivations);
+        let derivation = parse_derivation(r#"Derive([("out","","","")],[],[],"x86_64-linux","/bin/sh",[],[])"#).unwrap();
+        assert_eq!(derivation.version, DerivationVersion::Traditional);
+    }
+
+    #[test]
+    fn derivation_input_dynamic_outputs_traditional() {
+        let error =
+            parse_derivation(
+                r#"Derive([("out","","","")],[("/nix/store/hnm5s456v5wky252cv71ni8cqaphf11i-hello.drv.drv",([],[("out",["out"])]))],[],"","",[],[])"#,
+            ).unwrap_err();
+        assert_eq!(error.kind, DerivationParseErrorKind::ExpectedToken("["));
+        assert_eq!(error.position.offset, 88);
+    }
+
+    #[test]
+    fn derivation_input_relative_path() {
+        assert_eq!(
+            parse_owned_derivation_input(DerivationVersion::Traditional, br#"("tcb-1.2.drv",["out"])"#),
+            Err(
+                Err::Error(
+                    ParserError::new(
+                        br#""tcb-1.2.drv",["out"])"#.as_slice(),
+                        DerivationParseErrorKind::InvalidStorePath(
+                            "tcb-1.2.drv".to_string(),
+                            StorePathError::NotInStore,
+                        ),
+                    ),
+                ),
+            )
+        );
     }
 
     #[test]
     fn derivation_output_shadow() {
         assert_eq!(
-            parse_derivation_outputs(
+            owned_outputs(
                 concat!(
                     r#"["#,
                     r#"("dev","/nix/store/0fji8fg0z6gi3zyvsad7gxamx4ca2477-shadow-4.14.6-dev","","")"#,
@@ -286,137 +1138,215 @@ mod tests {
                     r#","#,
                     r#"("su","/nix/store/w7lf813b5w0zrmh9sbrwm9xnnm1sh1d1-shadow-4.14.6-su","","")"#,
                     r#"]"#
-                ),
+                ).as_bytes(),
             ),
-            Ok(("", HashMap::from([("dev".to_string(), DerivationOutput {
-                path: PathBuf::from("/nix/store/0fji8fg0z6gi3zyvsad7gxamx4ca2477-shadow-4.14.6-dev"),
-                hash_algo: "".to_string(),
-                hash: "".to_string(),
-            }), ("man".to_string(), DerivationOutput {
-                path: PathBuf::from("/nix/store/9bzr2i2vvvjqfrbkrxm4j4zxq73im9nf-shadow-4.14.6-man"),
-                hash_algo: "".to_string(),
-                hash: "".to_string(),
-            }), ("out".to_string(), DerivationOutput {
-                path: PathBuf::from("/nix/store/gwihsgkd13xmk8vwfn2k1nkdi9bys42x-shadow-4.14.6"),
-                hash_algo: "".to_string(),
-                hash: "".to_string(),
-            }), ("su".to_string(), DerivationOutput {
-                path: PathBuf::from("/nix/store/w7lf813b5w0zrmh9sbrwm9xnnm1sh1d1-shadow-4.14.6-su"),
-                hash_algo: "".to_string(),
-                hash: "".to_string(),
-            })])))
+            Ok((b"".as_slice(), BTreeMap::from([
+                ("dev".to_string(), input_addressed("/nix/store/0fji8fg0z6gi3zyvsad7gxamx4ca2477-shadow-4.14.6-dev")),
+                ("man".to_string(), input_addressed("/nix/store/9bzr2i2vvvjqfrbkrxm4j4zxq73im9nf-shadow-4.14.6-man")),
+                ("out".to_string(), input_addressed("/nix/store/gwihsgkd13xmk8vwfn2k1nkdi9bys42x-shadow-4.14.6")),
+                ("su".to_string(), input_addressed("/nix/store/w7lf813b5w0zrmh9sbrwm9xnnm1sh1d1-shadow-4.14.6-su")),
+            ])))
         );
     }
 
     #[test]
     fn derivation_inputs_shadow() {
         assert_eq!(
-            parse_derivation_inputs(
+            owned_inputs(
                 concat!(
                     r#"["#,
                     r#"("/nix/store/2a4nqx30swmddxgd5f3y1h8gynwb1mp9-bison-3.8.2.drv",["out"])"#,
+                    r#","#,
                     r#"("/nix/store/81l2lyg7hx6zwlb7yamncj1b2pbz5rj1-tcb-1.2.drv",["dev"])"#,
+                    r#","#,
                     r#"("/nix/store/9jqhmw0ksi0gab01asfd8gfj3wv3ahg6-docbook-xsl-nons-1.79.2.drv",["out"])"#,
+                    r#","#,
                     r#"("/nix/store/9vyx2lhbiq2c6jg6xz68whkl29qy60j2-autoreconf-hook.drv",["out"])"#,
+                    r#","#,
                     r#"("/nix/store/b5gkdv8336qp2wx7qppmd54nl29y0zh4-libxcrypt-4.4.36.drv",["out"])"#,
+                    r#","#,
                     r#"("/nix/store/d4rparlxpipwi3y717ijj917h0lbmrbj-glibc-2.39-52.drv",["bin"])"#,
+                    r#","#,
                     r#"("/nix/store/dgj37ph9745jy0bnzfz2hl1x8yjhaawy-itstool-2.0.7.drv",["out"])"#,
+                    r#","#,
                     r#"("/nix/store/hl008qyglrzrsyg59pc499jxaf1rvgjz-source.drv",["out"])"#,
+                    r#","#,
                     r#"("/nix/store/hll9cxnh7mm2maiy06vbxl6zk2y65kvh-fix-implicit-getdef_bool.patch.drv",["out"])"#,
+                    r#","#,
                     r#"("/nix/store/icld2xsizd7xabkfr396chagxcv7qaal-libxslt-1.1.39.drv",["dev"])"#,
+                    r#","#,
                     r#"("/nix/store/kblxy5ggi81bli1vkz550vpvmy36wlbp-linux-pam-1.6.1.drv",["out"])"#,
+                    r#","#,
                     r#"("/nix/store/lzc3r3m5yp5xj9qnbz56zrkq94d5hhsy-flex-2.6.4.drv",["out"])"#,
+                    r#","#,
                     r#"("/nix/store/nz98jzc49vlkky3vpq5lwjxh94b207fh-pkg-config-wrapper-0.29.2.drv",["out"])"#,
+                    r#","#,
                     r#"("/nix/store/pfkmysygw53mz830rhwfkadnzdxv96yw-libxml2-2.12.7.drv",["dev"])"#,
+                    r#","#,
                     r#"("/nix/store/wkgn8l6fyq3avhcpw1caj2r1z9dsw4r0-docbook-xml-4.5.drv",["out"])"#,
+                    r#","#,
                     r#"("/nix/store/wql9zbydwdr0nqxkm20crcbhn68wb4pc-stdenv-linux.drv",["out"])"#,
+                    r#","#,
                     r#"("/nix/store/xzz7s4cc4bakhaavx3qyn10sl9w7x445-libbsd-0.11.8.drv",["dev"])"#,
+                    r#","#,
                     r#"("/nix/store/ysv6wz83jkvg7d65j0js4bml9k0yc4sv-bash-5.2p32.drv",["out"])"#,
                     r#"]"#
-                ),
+                ).as_bytes(),
             ),
             Ok(
                 (
-                    "",
-                    HashMap::from(
+                    b"".as_slice(),
+                    BTreeMap::from(
                         [
                             (
-                                PathBuf::from("/nix/store/2a4nqx30swmddxgd5f3y1h8gynwb1mp9-bison-3.8.2.drv"),
-                                DerivationInput { value: vec!["out".to_string()] },
+                                StorePath::new("/nix/store/2a4nqx30swmddxgd5f3y1h8gynwb1mp9-bison-3.8.2.drv").unwrap(),
+                                DerivationInput {
+                                    value: BTreeSet::from(["out".to_string()]),
+                                    dynamic_outputs: BTreeMap::new(),
+                                },
                             ),
                             (
-                                PathBuf::from("/nix/store/81l2lyg7hx6zwlb7yamncj1b2pbz5rj1-tcb-1.2.drv"),
-                                DerivationInput { value: vec!["dev".to_string()] },
+                                StorePath::new("/nix/store/81l2lyg7hx6zwlb7yamncj1b2pbz5rj1-tcb-1.2.drv").unwrap(),
+                                DerivationInput {
+                                    value: BTreeSet::from(["dev".to_string()]),
+                                    dynamic_outputs: BTreeMap::new(),
+                                },
                             ),
                             (
-                                PathBuf::from(
+                                StorePath::new(
                                     "/nix/store/9jqhmw0ksi0gab01asfd8gfj3wv3ahg6-docbook-xsl-nons-1.79.2.drv",
-                                ),
-                                DerivationInput { value: vec!["out".to_string()] },
+                                ).unwrap(),
+                                DerivationInput {
+                                    value: BTreeSet::from(["out".to_string()]),
+                                    dynamic_outputs: BTreeMap::new(),
+                                },
                             ),
                             (
-                                PathBuf::from("/nix/store/9vyx2lhbiq2c6jg6xz68whkl29qy60j2-autoreconf-hook.drv"),
-                                DerivationInput { value: vec!["out".to_string()] },
+                                StorePath::new(
+                                    "/nix/store/9vyx2lhbiq2c6jg6xz68whkl29qy60j2-autoreconf-hook.drv",
+                                ).unwrap(),
+                                DerivationInput {
+                                    value: BTreeSet::from(["out".to_string()]),
+                                    dynamic_outputs: BTreeMap::new(),
+                                },
                             ),
                             (
-                                PathBuf::from("/nix/store/b5gkdv8336qp2wx7qppmd54nl29y0zh4-libxcrypt-4.4.36.drv"),
-                                DerivationInput { value: vec!["out".to_string()] },
+                                StorePath::new(
+                                    "/nix/store/b5gkdv8336qp2wx7qppmd54nl29y0zh4-libxcrypt-4.4.36.drv",
+                                ).unwrap(),
+                                DerivationInput {
+                                    value: BTreeSet::from(["out".to_string()]),
+                                    dynamic_outputs: BTreeMap::new(),
+                                },
                             ),
                             (
-                                PathBuf::from("/nix/store/d4rparlxpipwi3y717ijj917h0lbmrbj-glibc-2.39-52.drv"),
-                                DerivationInput { value: vec!["bin".to_string()] },
+                                StorePath::new(
+                                    "/nix/store/d4rparlxpipwi3y717ijj917h0lbmrbj-glibc-2.39-52.drv",
+                                ).unwrap(),
+                                DerivationInput {
+                                    value: BTreeSet::from(["bin".to_string()]),
+                                    dynamic_outputs: BTreeMap::new(),
+                                },
                             ),
                             (
-                                PathBuf::from("/nix/store/dgj37ph9745jy0bnzfz2hl1x8yjhaawy-itstool-2.0.7.drv"),
-                                DerivationInput { value: vec!["out".to_string()] },
+                                StorePath::new(
+                                    "/nix/store/dgj37ph9745jy0bnzfz2hl1x8yjhaawy-itstool-2.0.7.drv",
+                                ).unwrap(),
+                                DerivationInput {
+                                    value: BTreeSet::from(["out".to_string()]),
+                                    dynamic_outputs: BTreeMap::new(),
+                                },
                             ),
                             (
-                                PathBuf::from("/nix/store/hl008qyglrzrsyg59pc499jxaf1rvgjz-source.drv"),
-                                DerivationInput { value: vec!["out".to_string()] },
+                                StorePath::new("/nix/store/hl008qyglrzrsyg59pc499jxaf1rvgjz-source.drv").unwrap(),
+                                DerivationInput {
+                                    value: BTreeSet::from(["out".to_string()]),
+                                    dynamic_outputs: BTreeMap::new(),
+                                },
                             ),
                             (
-                                PathBuf::from(
+                                StorePath::new(
                                     "/nix/store/hll9cxnh7mm2maiy06vbxl6zk2y65kvh-fix-implicit-getdef_bool.patch.drv",
-                                ),
-                                DerivationInput { value: vec!["out".to_string()] },
+                                ).unwrap(),
+                                DerivationInput {
+                                    value: BTreeSet::from(["out".to_string()]),
+                                    dynamic_outputs: BTreeMap::new(),
+                                },
                             ),
                             (
-                                PathBuf::from("/nix/store/icld2xsizd7xabkfr396chagxcv7qaal-libxslt-1.1.39.drv"),
-                                DerivationInput { value: vec!["dev".to_string()] },
+                                StorePath::new(
+                                    "/nix/store/icld2xsizd7xabkfr396chagxcv7qaal-libxslt-1.1.39.drv",
+                                ).unwrap(),
+                                DerivationInput {
+                                    value: BTreeSet::from(["dev".to_string()]),
+                                    dynamic_outputs: BTreeMap::new(),
+                                },
                             ),
                             (
-                                PathBuf::from("/nix/store/kblxy5ggi81bli1vkz550vpvmy36wlbp-linux-pam-1.6.1.drv"),
-                                DerivationInput { value: vec!["out".to_string()] },
+                                StorePath::new(
+                                    "/nix/store/kblxy5ggi81bli1vkz550vpvmy36wlbp-linux-pam-1.6.1.drv",
+                                ).unwrap(),
+                                DerivationInput {
+                                    value: BTreeSet::from(["out".to_string()]),
+                                    dynamic_outputs: BTreeMap::new(),
+                                },
                             ),
                             (
-                                PathBuf::from("/nix/store/lzc3r3m5yp5xj9qnbz56zrkq94d5hhsy-flex-2.6.4.drv"),
-                                DerivationInput { value: vec!["out".to_string()] },
+                                StorePath::new("/nix/store/lzc3r3m5yp5xj9qnbz56zrkq94d5hhsy-flex-2.6.4.drv").unwrap(),
+                                DerivationInput {
+                                    value: BTreeSet::from(["out".to_string()]),
+                                    dynamic_outputs: BTreeMap::new(),
+                                },
                             ),
                             (
-                                PathBuf::from(
+                                StorePath::new(
                                     "/nix/store/nz98jzc49vlkky3vpq5lwjxh94b207fh-pkg-config-wrapper-0.29.2.drv",
-                                ),
-                                DerivationInput { value: vec!["out".to_string()] },
+                                ).unwrap(),
+                                DerivationInput {
+                                    value: BTreeSet::from(["out".to_string()]),
+                                    dynamic_outputs: BTreeMap::new(),
+                                },
                             ),
                             (
-                                PathBuf::from("/nix/store/pfkmysygw53mz830rhwfkadnzdxv96yw-libxml2-2.12.7.drv"),
-                                DerivationInput { value: vec!["dev".to_string()] },
+                                StorePath::new(
+                                    "/nix/store/pfkmysygw53mz830rhwfkadnzdxv96yw-libxml2-2.12.7.drv",
+                                ).unwrap(),
+                                DerivationInput {
+                                    value: BTreeSet::from(["dev".to_string()]),
+                                    dynamic_outputs: BTreeMap::new(),
+                                },
                             ),
                             (
-                                PathBuf::from("/nix/store/wkgn8l6fyq3avhcpw1caj2r1z9dsw4r0-docbook-xml-4.5.drv"),
-                                DerivationInput { value: vec!["out".to_string()] },
+                                StorePath::new(
+                                    "/nix/store/wkgn8l6fyq3avhcpw1caj2r1z9dsw4r0-docbook-xml-4.5.drv",
+                                ).unwrap(),
+                                DerivationInput {
+                                    value: BTreeSet::from(["out".to_string()]),
+                                    dynamic_outputs: BTreeMap::new(),
+                                },
                             ),
                             (
-                                PathBuf::from("/nix/store/wql9zbydwdr0nqxkm20crcbhn68wb4pc-stdenv-linux.drv"),
-                                DerivationInput { value: vec!["out".to_string()] },
+                                StorePath::new("/nix/store/wql9zbydwdr0nqxkm20crcbhn68wb4pc-stdenv-linux.drv").unwrap(),
+                                DerivationInput {
+                                    value: BTreeSet::from(["out".to_string()]),
+                                    dynamic_outputs: BTreeMap::new(),
+                                },
                             ),
                             (
-                                PathBuf::from("/nix/store/xzz7s4cc4bakhaavx3qyn10sl9w7x445-libbsd-0.11.8.drv"),
-                                DerivationInput { value: vec!["dev".to_string()] },
+                                StorePath::new(
+                                    "/nix/store/xzz7s4cc4bakhaavx3qyn10sl9w7x445-libbsd-0.11.8.drv",
+                                ).unwrap(),
+                                DerivationInput {
+                                    value: BTreeSet::from(["dev".to_string()]),
+                                    dynamic_outputs: BTreeMap::new(),
+                                },
                             ),
                             (
-                                PathBuf::from("/nix/store/ysv6wz83jkvg7d65j0js4bml9k0yc4sv-bash-5.2p32.drv"),
-                                DerivationInput { value: vec!["out".to_string()] },
+                                StorePath::new("/nix/store/ysv6wz83jkvg7d65j0js4bml9k0yc4sv-bash-5.2p32.drv").unwrap(),
+                                DerivationInput {
+                                    value: BTreeSet::from(["out".to_string()]),
+                                    dynamic_outputs: BTreeMap::new(),
+                                },
                             ),
                         ],
                     ),
@@ -424,4 +1354,190 @@ mod tests {
             )
         )
     }
+
+    #[test]
+    fn error_in_environment_variable() {
+        let error =
+            parse_derivation(
+                concat!(
+                    r#"Derive([("out","/nix/store/gwihsgkd13xmk8vwfn2k1nkdi9bys42x-shadow-4.14.6","","")],[],[],"#,
+                    r#""x86_64-linux","/bin/sh",[],[("a","b"),("buildPhase" "c")])"#
+                ),
+            )
+                .unwrap_err();
+        assert_eq!(error.kind, DerivationParseErrorKind::ExpectedToken(","));
+        assert_eq!(error.field, vec![DerivationField::Env, DerivationField::EnvVar("buildPhase".into())]);
+        assert_eq!(error.position, Position {
+            offset: 141,
+            line: 1,
+            column: 142,
+        });
+    }
+
+    #[test]
+    fn error_in_output() {
+        let error =
+            parse_derivation_bytes(
+                b"Derive([(\"out\",\"/nix/store/gwihsgkd13xmk8vwfn2k1nkdi9bys42x-shadow-4.14.6\",\"\xff\",\"\")],[],[],\"\",\"\",[],[])",
+            ).unwrap_err();
+        assert_eq!(error.kind, DerivationParseErrorKind::InvalidUtf8);
+        assert_eq!(error.field, vec![DerivationField::Outputs, DerivationField::Output("out".to_string())]);
+        assert_eq!(error.position.offset, 75);
+    }
+
+    #[test]
+    fn error_in_input_derivation() {
+        let error =
+            parse_derivation(
+                r#"Derive([("out","","","")],[("/nix/store/gwihsgkd13xmk8vwfn2k1nkdi9bys42x-shadow.drv",["out",])],[],"","",[],[])"#,
+            ).unwrap_err();
+        assert_eq!(error.kind, DerivationParseErrorKind::ExpectedString);
+        assert_eq!(
+            error.field,
+            vec![
+                DerivationField::InputDrvs,
+                DerivationField::InputDrv(
+                    StorePath::new("/nix/store/gwihsgkd13xmk8vwfn2k1nkdi9bys42x-shadow.drv").unwrap(),
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn error_unexpected_end() {
+        let input = r#"Derive([("out","","","")],[],[],"x86_64-li"#;
+        let error = parse_derivation(input).unwrap_err();
+        assert_eq!(error.kind, DerivationParseErrorKind::UnexpectedEnd);
+        assert_eq!(error.field, vec![DerivationField::System]);
+        assert_eq!(error.position.offset, input.len());
+        let error = parse_derivation("Deri").unwrap_err();
+        assert_eq!(error.kind, DerivationParseErrorKind::UnexpectedEnd);
+        assert_eq!(error.field, vec![DerivationField::Header]);
+    }
+
+    #[test]
+    fn error_trailing_data() {
+        let error = parse_derivation("Derive([(\"out\",\"\",\"\",\"\")],[],[],\"\",\"\",[],[])\n").unwrap_err();
+        assert_eq!(error.kind, DerivationParseErrorKind::TrailingData);
+        assert!(error.field.is_empty());
+        assert_eq!(error.position, Position {
+            offset: 44,
+            line: 1,
+            column: 45,
+        });
+        assert_eq!(error.to_string(), "unexpected data after the end of the derivation at line 1, column 45 (byte 44)");
+    }
+
+    #[test]
+    fn error_unknown_header() {
+        let error =
+            parse_derivation(r#"DrvWithVersion("xp-future",[("out","","","")],[],[],"","",[],[])"#).unwrap_err();
+        assert_eq!(error.kind, DerivationParseErrorKind::UnknownVersion("xp-future".to_string()));
+        assert_eq!(error.position.offset, 15);
+        let error = parse_derivation(r#"Derivation([("out","","","")],[],[],"","",[],[])"#).unwrap_err();
+        assert_eq!(error.kind, DerivationParseErrorKind::ExpectedHeader);
+        assert_eq!(error.position.offset, 0);
+    }
+
+    #[test]
+    fn borrowed_derivation() {
+        let input =
+            concat!(
+                r#"Derive([("out","/nix/store/gwihsgkd13xmk8vwfn2k1nkdi9bys42x-shadow-4.14.6","","")],[],[],"x86_64-linux","/bin/sh","#,
+                r#"["-c","echo \"$out\""],[("out","/nix/store/gwihsgkd13xmk8vwfn2k1nkdi9bys42x-shadow-4.14.6")])"#
+            ).as_bytes();
+        let derivation = parse_borrowed_derivation(input).unwrap();
+        assert!(matches!(derivation.system, Cow::Borrowed("x86_64-linux")));
+        assert!(matches!(derivation.builder, Cow::Borrowed(_)));
+        assert!(matches!(derivation.args[0], Cow::Borrowed(b"-c")));
+        assert!(matches!(derivation.args[1], Cow::Owned(ref arg) if arg == b"echo \"$out\""));
+        assert_eq!(derivation.into_owned(), parse_derivation_bytes(input).unwrap());
+    }
+
+    /// A reader that returns at most `chunk` bytes per read, to exercise refilling
+    /// the buffer in the middle of every item.
+    struct ChunkedReader<'input> {
+        input: &'input [u8],
+        chunk: usize,
+    }
+
+    impl Read for ChunkedReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let length = self.chunk.min(buf.len()).min(self.input.len());
+            buf[..length].copy_from_slice(&self.input[..length]);
+            self.input = &self.input[length..];
+            Ok(length)
+        }
+    }
+
+    fn assert_reads_like_bytes(directory: &str) {
+        let derivation_file_path = Path::new(&std::env::var_os("CARGO_MANIFEST_DIR").unwrap()).join(directory);
+        for path in fs::read_dir(derivation_file_path).unwrap() {
+            let path = path.expect("There should be files here!").path();
+            let drv_bytes = fs::read(&path).unwrap();
+            for chunk in [1, 7, READ_SIZE] {
+                let read = read_derivation(ChunkedReader {
+                    input: &drv_bytes,
+                    chunk,
+                });
+                match (read, parse_derivation_bytes(&drv_bytes)) {
+                    (Ok(read), Ok(parsed)) => assert_eq!(read, parsed, "{}", path.display()),
+                    (Err(ReadDerivationError::Parse(read)), Err(parsed)) => {
+                        assert_eq!(read, parsed, "{}", path.display())
+                    },
+                    (read, parsed) => panic!("{}: read {read:?} but parsed {parsed:?}", path.display()),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn read_derivations() {
+        assert_reads_like_bytes("src/derivations/release_packages");
+        assert_reads_like_bytes("src/derivations/edge_case_derivations");
+    }
+
+    #[test]
+    fn read_invalid_derivations() {
+        assert_reads_like_bytes("src/derivations/invalid_derivations");
+    }
+
+    #[test]
+    fn read_trailing_data() {
+        let input = b"Derive([(\"out\",\"\",\"\",\"\")],[],[],\"\",\"\",[],[])\n";
+        let Err(ReadDerivationError::Parse(error)) = read_derivation(input.as_slice()) else {
+            panic!("trailing data should not parse");
+        };
+        assert_eq!(error, parse_derivation_bytes(input).unwrap_err());
+    }
+
+    #[test]
+    fn sorted_collections() {
+        let derivation =
+            parse_derivation(
+                concat!(
+                    r#"Derive([("out","","",""),("dev","","","")],[],"#,
+                    r#"["/nix/store/gwihsgkd13xmk8vwfn2k1nkdi9bys42x-b","/nix/store/gwihsgkd13xmk8vwfn2k1nkdi9bys42x-a"],"","",["z","a"],"#,
+                    r#"[("b","1"),("a","2"),("b","3")])"#
+                ),
+            ).unwrap();
+        assert_eq!(derivation.outputs.keys().collect::<Vec<_>>(), ["dev", "out"]);
+        assert_eq!(
+            derivation.input_srcs.iter().collect::<Vec<_>>(),
+            ["/nix/store/gwihsgkd13xmk8vwfn2k1nkdi9bys42x-a", "/nix/store/gwihsgkd13xmk8vwfn2k1nkdi9bys42x-b"]
+        );
+        assert_eq!(derivation.args, [ByteString::from("z"), ByteString::from("a")]);
+        assert_eq!(derivation.env, BTreeMap::from([("a".into(), "2".into()), ("b".into(), "3".into())]));
+    }
+
+    #[test]
+    fn derivations_as_keys() {
+        let first = parse_derivation(r#"Derive([("out","","","")],[],[],"aarch64-linux","",[],[])"#).unwrap();
+        let second = parse_derivation(r#"Derive([("out","","","")],[],[],"x86_64-linux","",[],[])"#).unwrap();
+        assert!(first < second);
+        let derivations = BTreeSet::from([second.clone(), first.clone(), second.clone()]);
+        assert_eq!(derivations.into_iter().collect::<Vec<_>>(), [first.clone(), second.clone()]);
+        let derivations = std::collections::HashSet::from([first.clone(), second, first]);
+        assert_eq!(derivations.len(), 2);
+    }
 }
@@ -0,0 +1,162 @@
+pub mod errors;
+
+use crate::derivations::paths::errors::DrvPathError;
+use crate::derivations::renderers::render_derivation;
+use crate::derivations::types::{
+    Derivation,
+    DerivationOutput,
+};
+use crate::hashes::types::Hash;
+use crate::store_paths::errors::StorePathError;
+use crate::store_paths::types::StorePath;
+
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Makes the path of a text file that refers to `references`, such as a `.drv`
+/// file, following `makeTextPath` in Nix.
+///
+/// # Errors
+///
+/// Returns a `StorePathError` if the path would not be a valid store path.
+#[inline]
+pub fn make_text_path<'path>(
+    store_dir: &Path,
+    hash: &Hash,
+    references: impl IntoIterator<Item = &'path StorePath>,
+    name: &str,
+) -> Result<StorePath, StorePathError> {
+    let mut references = references.into_iter().collect::<Vec<_>>();
+    references.sort();
+    references.dedup();
+    let kind = references.into_iter().fold(String::from("text"), |kind, reference| format!("{kind}:{reference}"));
+    StorePath::make(store_dir, &kind, hash, name)
+}
+
+impl Derivation {
+    /// Returns the store paths the `.drv` file of the derivation refers to: its
+    /// input derivations and input sources.
+    #[inline]
+    #[must_use]
+    pub fn references(&self) -> BTreeSet<&StorePath> {
+        self.input_drvs.keys().chain(&self.input_srcs).collect()
+    }
+
+    /// Returns the store directory of the paths the derivation refers to, taken
+    /// from its outputs or else from its references. A derivation without
+    /// either has none.
+    #[inline]
+    #[must_use]
+    pub fn store_dir(&self) -> Option<&Path> {
+        let output = self.outputs.values().find_map(DerivationOutput::path);
+        output.or_else(|| self.references().into_iter().next()).map(StorePath::store_dir)
+    }
+
+    /// Computes the path Nix stores the derivation at when it is named `name`,
+    /// e.g. `/nix/store/...-hello-2.12.1.drv` for `hello-2.12.1`.
+    ///
+    /// The path is a text path of the rendered derivation, so a modified
+    /// derivation gets a new path.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StorePathError` if the path would not be a valid store path.
+    #[inline]
+    pub fn drv_path(&self, store_dir: &Path, name: &str) -> Result<StorePath, StorePathError> {
+        let hash = Hash::sha256(&render_derivation(self));
+        make_text_path(store_dir, &hash, self.references(), &format!("{name}.drv"))
+    }
+
+    /// Checks that `path` is the path Nix would store the derivation at, i.e.
+    /// that its hash part matches the contents of the derivation.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `DrvPathError` if `path` is not a `.drv` path or is not the
+    /// path of the derivation.
+    #[inline]
+    pub fn check_drv_path(&self, path: &StorePath) -> Result<(), DrvPathError> {
+        let name = path.name().strip_suffix(".drv").ok_or_else(|| DrvPathError::NotDerivation(path.clone()))?;
+        let computed = self.drv_path(path.store_dir(), name).map_err(DrvPathError::InvalidPath)?;
+        if computed != *path {
+            return Err(DrvPathError::Mismatch {
+                path: path.clone(),
+                computed,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::derivations::parsers::parse_derivation_bytes;
+    use crate::store_paths::types::DEFAULT_STORE_DIR;
+    use std::fs;
+
+    fn assert_paths_match(directory: &str) {
+        let directory = Path::new(&std::env::var_os("CARGO_MANIFEST_DIR").unwrap()).join(directory);
+        for entry in fs::read_dir(directory).unwrap() {
+            let file = entry.unwrap().path();
+            let derivation = parse_derivation_bytes(&fs::read(&file).unwrap()).unwrap();
+            let path = Path::new(DEFAULT_STORE_DIR).join(file.file_name().unwrap());
+            let path = StorePath::new(path.to_str().unwrap()).unwrap();
+            assert_eq!(derivation.check_drv_path(&path), Ok(()), "{}", file.display());
+        }
+    }
+
+    #[test]
+    fn release_packages() {
+        assert_paths_match("src/derivations/release_packages");
+    }
+
+    #[test]
+    fn release_packages_ca() {
+        assert_paths_match("src/derivations/release_packages_ca");
+    }
+
+    #[test]
+    fn misc_derivations() {
+        assert_paths_match("src/derivations/misc_derivations");
+    }
+
+    #[test]
+    fn edge_case_derivations() {
+        assert_paths_match("src/derivations/edge_case_derivations");
+    }
+
+    #[test]
+    fn modified_derivation() {
+        let mut derivation = parse_derivation_bytes(br#"Derive([("out","","","")],[],[],"x86_64-linux","/bin/sh",[],[])"#).unwrap();
+        let store_dir = Path::new(DEFAULT_STORE_DIR);
+        let path = derivation.drv_path(store_dir, "empty").unwrap();
+        assert_eq!(derivation.check_drv_path(&path), Ok(()));
+
+        derivation.system = "aarch64-linux".to_string();
+        let computed = derivation.drv_path(store_dir, "empty").unwrap();
+        assert_ne!(computed, path);
+        assert_eq!(derivation.check_drv_path(&path), Err(DrvPathError::Mismatch {
+            path: path.clone(),
+            computed,
+        }));
+        let output = StorePath::new("/nix/store/yj9wanf1s3wdvk65nv3p1yhyyzw29knh-empty").unwrap();
+        assert_eq!(derivation.check_drv_path(&output), Err(DrvPathError::NotDerivation(output)));
+    }
+
+    #[test]
+    fn text_path_references() {
+        let hash = Hash::sha256(b"");
+        let first = StorePath::new("/nix/store/gwihsgkd13xmk8vwfn2k1nkdi9bys42x-a").unwrap();
+        let second = StorePath::new("/nix/store/0fji8fg0z6gi3zyvsad7gxamx4ca2477-b").unwrap();
+        let store_dir = Path::new(DEFAULT_STORE_DIR);
+        assert_eq!(
+            make_text_path(store_dir, &hash, [&first, &second], "refs"),
+            make_text_path(store_dir, &hash, [&second, &first, &second], "refs")
+        );
+        assert_eq!(
+            make_text_path(store_dir, &hash, [&first], "refs"),
+            StorePath::make(store_dir, &format!("text:{first}"), &hash, "refs")
+        );
+    }
+}
@@ -0,0 +1,40 @@
+use crate::store_paths::errors::StorePathError;
+use crate::store_paths::types::StorePath;
+
+use core::fmt;
+use std::error::Error;
+
+/// An error produced when a `.drv` path does not belong to a derivation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DrvPathError {
+    /// The path does not end in `.drv`.
+    NotDerivation(StorePath),
+    /// The path of the derivation would not be a valid store path.
+    InvalidPath(StorePathError),
+    /// The path is not the one Nix computes from the contents of the derivation.
+    Mismatch { path: StorePath, computed: StorePath },
+}
+
+impl fmt::Display for DrvPathError {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::NotDerivation(ref path) => write!(formatter, "`{path}` is not a `.drv` path"),
+            Self::InvalidPath(ref err) => write!(formatter, "invalid derivation path: {err}"),
+            Self::Mismatch { ref path, ref computed } => {
+                write!(formatter, "`{path}` does not match its contents, which belong at `{computed}`")
+            },
+        }
+    }
+}
+
+impl Error for DrvPathError {
+    #[inline]
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            Self::InvalidPath(ref err) => Some(err),
+            Self::NotDerivation(_) | Self::Mismatch { .. } => None,
+        }
+    }
+}
@@ -0,0 +1,582 @@
+use crate::derivations::diffs::{
+    pair_by_name,
+    Change,
+    DerivationDiff,
+};
+use crate::derivations::graphs::errors::CycleError;
+use crate::derivations::graphs::DerivationGraph;
+use crate::derivations::types::{
+    Derivation,
+    DerivationOutput,
+};
+use crate::nix32;
+use crate::store_paths::types::{
+    StorePath,
+    HASH_PART_LENGTH,
+};
+use crate::strings::types::ByteString;
+
+use core::fmt;
+use core::str;
+use std::collections::{
+    BTreeMap,
+    BTreeSet,
+};
+use std::ffi::OsString;
+use std::os::unix::ffi::{
+    OsStrExt,
+    OsStringExt,
+};
+use std::path::PathBuf;
+
+/// The old store paths to rewrite by their hash parts, each with the new path
+/// it is replaced by.
+type HashParts<'path> = BTreeMap<&'path str, (&'path StorePath, &'path StorePath)>;
+
+/// Replaces every hash part in `text` that is a key of `hash_parts` with the
+/// hash part of the new path, and the name following it with the new name, so
+/// versions are rewritten too. Hash parts are found as runs of exactly
+/// `HASH_PART_LENGTH` base-32 characters, so they are replaced wherever they
+/// occur, not only in full paths.
+fn rewrite_hash_parts(text: &[u8], hash_parts: &HashParts<'_>) -> Vec<u8> {
+    let mut rewritten = Vec::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(&first) = rest.first() {
+        let run = rest.iter().take_while(|&&byte| nix32::is_valid_char(char::from(byte))).count();
+        if run == 0 {
+            rewritten.push(first);
+            rest = &rest[1..];
+            continue;
+        }
+        let (word, after) = rest.split_at(run);
+        rest = after;
+        let replacement =
+            str::from_utf8(word).ok().filter(|_| run == HASH_PART_LENGTH).and_then(|word| hash_parts.get(word));
+        let Some(&(old_path, new_path)) = replacement else {
+            rewritten.extend_from_slice(word);
+            continue;
+        };
+        rewritten.extend_from_slice(new_path.hash_part().as_bytes());
+        let old_name = after.strip_prefix(b"-").and_then(|name| name.strip_prefix(old_path.name().as_bytes()));
+        if let Some(after_name) = old_name {
+            rewritten.push(b'-');
+            rewritten.extend_from_slice(new_path.name().as_bytes());
+            rest = after_name;
+        }
+    }
+    rewritten
+}
+
+/// Rewrites the hash part and name of `path` with `hash_parts`.
+fn rewrite_path(path: &StorePath, hash_parts: &HashParts<'_>) -> StorePath {
+    let rewritten = String::from_utf8_lossy(&rewrite_hash_parts(path.as_str().as_bytes(), hash_parts)).into_owned();
+    StorePath::new(rewritten).unwrap_or_else(|_| path.clone())
+}
+
+/// Rewrites every store path in `derivation` with `hash_parts`.
+fn rewrite_derivation(derivation: &Derivation, hash_parts: &HashParts<'_>) -> Derivation {
+    let path = |path: &StorePath| rewrite_path(path, hash_parts);
+    let bytes = |bytes: &ByteString| ByteString::from(rewrite_hash_parts(bytes, hash_parts));
+    let outputs = derivation.outputs.iter().map(|(name, output)| {
+        let output = match *output {
+            DerivationOutput::InputAddressed(ref output_path) => DerivationOutput::InputAddressed(path(output_path)),
+            DerivationOutput::CaFixed { path: ref output_path, method, ref hash } => DerivationOutput::CaFixed {
+                path: path(output_path),
+                method,
+                hash: hash.clone(),
+            },
+            _ => output.clone(),
+        };
+        (name.clone(), output)
+    });
+    let builder = rewrite_hash_parts(derivation.builder.as_os_str().as_bytes(), hash_parts);
+    Derivation {
+        version: derivation.version,
+        outputs: outputs.collect(),
+        input_drvs: derivation.input_drvs.iter().map(|(input, outputs)| (path(input), outputs.clone())).collect(),
+        input_srcs: derivation.input_srcs.iter().map(path).collect(),
+        system: derivation.system.clone(),
+        builder: PathBuf::from(OsString::from_vec(builder)),
+        args: derivation.args.iter().map(bytes).collect(),
+        env: derivation.env.iter().map(|(key, value)| (bytes(key), bytes(value))).collect(),
+    }
+}
+
+/// Records that the derivation at `old_path` in `old` became the one at
+/// `new_path` in `new`, so its path and the paths of its outputs are rewritten.
+fn add_hash_parts<'graph>(
+    hash_parts: &mut HashParts<'graph>,
+    (old, old_path): (&'graph DerivationGraph, &'graph StorePath),
+    (new, new_path): (&'graph DerivationGraph, &'graph StorePath),
+) {
+    hash_parts.insert(old_path.hash_part(), (old_path, new_path));
+    let (Some(old_derivation), Some(new_derivation)) = (old.get(old_path), new.get(new_path)) else {
+        return;
+    };
+    for (name, old_output) in &old_derivation.outputs {
+        let new_output = new_derivation.outputs.get(name).and_then(DerivationOutput::path);
+        if let (Some(old_output), Some(new_output)) = (old_output.path(), new_output) {
+            hash_parts.insert(old_output.hash_part(), (old_output, new_output));
+        }
+    }
+}
+
+/// Returns the environment of `derivation` without the variables named after
+/// its outputs, whose paths change whenever anything else does.
+fn env_without_outputs(derivation: &Derivation) -> Vec<(&ByteString, &ByteString)> {
+    let is_output = |key: &ByteString| key.to_str().is_ok_and(|key| derivation.outputs.contains_key(key));
+    derivation.env.iter().filter(|&(key, _)| !is_output(key)).collect()
+}
+
+/// Returns the name of the derivation at `path` without its version, and what
+/// follows the last digit of the version, such as the `.tar.gz` of a source
+/// archive. Like `parseDrvName` in Nix, the version starts after the first `-`
+/// that is not followed by a letter. `openssl-3.0.1.drv` and `openssl-3.0.2.drv`
+/// both give `("openssl", "")`, and `openssl-3.0.1.tar.gz.drv` gives
+/// `("openssl", ".tar.gz")`.
+fn versionless_name(path: &StorePath) -> (&str, &str) {
+    let name = path.name().strip_suffix(".drv").unwrap_or(path.name());
+    let version_start = name.match_indices('-').map(|(index, _)| index + 1).find(|&start| {
+        name.get(start..).and_then(|version| version.chars().next()).is_some_and(|first| !first.is_ascii_alphabetic())
+    });
+    let Some(start) = version_start else {
+        return (name, "");
+    };
+    let version = name.get(start..).unwrap_or_default();
+    let suffix_start = version.rfind(|character: char| character.is_ascii_digit()).map_or(0, |last| last + 1);
+    let suffix = version.get(suffix_start..).unwrap_or_default();
+    (name.get(..start - 1).unwrap_or_default(), suffix)
+}
+
+/// Old derivations rewritten with the hash parts known at the time, each with
+/// the derivations it depends on. A rewrite is out of date once one of those is
+/// paired, since only their hash parts can occur in it.
+type Rewrites<'graph> = BTreeMap<&'graph StorePath, (Derivation, BTreeSet<&'graph StorePath>)>;
+
+/// Picks which of the old derivations at `candidates` `new_derivation` is the
+/// new version of, returning its index. Once rewritten with `hash_parts`, the
+/// candidate with the same inputs and environment is picked, or else one with
+/// the same inputs, or else one with the same environment. Rewrites are kept in
+/// `rewrites`, so each candidate is only rewritten again once it is out of date.
+fn match_candidate<'graph>(
+    old: &'graph DerivationGraph,
+    candidates: &[&'graph StorePath],
+    new_derivation: &Derivation,
+    hash_parts: &HashParts<'_>,
+    rewrites: &mut Rewrites<'graph>,
+) -> Option<usize> {
+    for &path in candidates {
+        if let (false, Some(derivation)) = (rewrites.contains_key(path), old.get(path)) {
+            rewrites.insert(path, (rewrite_derivation(derivation, hash_parts), old.requisites(path)));
+        }
+    }
+    let same_inputs = |derivation: &Derivation| {
+        derivation.input_drvs == new_derivation.input_drvs && derivation.input_srcs == new_derivation.input_srcs
+    };
+    let new_env = env_without_outputs(new_derivation);
+    let same_env = |derivation: &Derivation| env_without_outputs(derivation) == new_env;
+    let position = |matches: &dyn Fn(&Derivation) -> bool| {
+        candidates.iter().position(|path| rewrites.get(path).is_some_and(|(derivation, _)| matches(derivation)))
+    };
+    position(&|derivation| same_inputs(derivation) && same_env(derivation))
+        .or_else(|| position(&same_inputs))
+        .or_else(|| position(&same_env))
+}
+
+/// Counts the derivations in `changed` that depend on the one at `path`,
+/// directly or indirectly, where `referrers` maps each derivation to the ones
+/// using it.
+#[expect(clippy::single_call_fn, reason = "Counting is not inlined for readability.")]
+fn count_changed_referrers(
+    referrers: &BTreeMap<&StorePath, Vec<&StorePath>>,
+    path: &StorePath,
+    changed: &BTreeSet<&StorePath>,
+) -> usize {
+    let mut visited = BTreeSet::new();
+    let mut queue = referrers.get(path).cloned().unwrap_or_default();
+    while let Some(referrer) = queue.pop() {
+        if visited.insert(referrer) {
+            queue.extend(referrers.get(referrer).into_iter().flatten().copied());
+        }
+    }
+    visited.intersection(changed).filter(|&&referrer| referrer != path).count()
+}
+
+/// A derivation that changed by itself, rather than only because its inputs
+/// changed.
+#[expect(clippy::exhaustive_structs, reason = "Derivation format is very stable.")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RebuildOrigin {
+    pub old: StorePath,
+    pub new: StorePath,
+    /// The changes to the derivation itself, with the paths of changed inputs
+    /// and outputs already replaced by their new paths.
+    pub diff: DerivationDiff,
+    /// The number of changed derivations in the new closure that depend on
+    /// this one.
+    pub rebuilds: usize,
+}
+
+/// Why the derivations of a closure changed: the origins, which changed by
+/// themselves, and everything that only changed because they did.
+///
+/// Derivations are paired up by name, like in `DerivationDiff`. Derivations
+/// that share a name, like the many `source.drv` in a closure, or whose version
+/// changed, like `openssl-3.0.1.drv` and `openssl-3.0.2.drv`, are paired as
+/// described in `RebuildReport::new`.
+#[expect(clippy::exhaustive_structs, reason = "Derivation format is very stable.")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RebuildReport {
+    /// The derivations that changed by themselves, in the order the new
+    /// derivations are built in.
+    pub origins: Vec<RebuildOrigin>,
+    /// Every pair of old and new derivations with the same name that differ.
+    pub changed: Vec<(StorePath, StorePath)>,
+    /// The derivations only in the new closure.
+    pub added: Vec<StorePath>,
+    /// The derivations only in the old closure.
+    pub removed: Vec<StorePath>,
+}
+
+impl RebuildReport {
+    /// Compares the closures `old` and `new` to find the origins of the changes.
+    ///
+    /// A changed derivation is an origin if it still differs from its new version
+    /// once the paths of the changed derivations and their outputs are replaced
+    /// by their new paths everywhere in it.
+    ///
+    /// Derivations with a name that is not unique are paired in the order they
+    /// are built in: each new one is paired with the old one that has the same
+    /// inputs and environment once rewritten, or else the same inputs, or else
+    /// the same environment. Like in `nix-diff`, derivations left over are then
+    /// paired by their names without versions, directly if exactly one old and
+    /// one new derivation share that name, or else in the same way. Those still
+    /// left over count as added or removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CycleError` if the derivations of `new` depend on each other in
+    /// a cycle.
+    #[inline]
+    pub fn new(old: &DerivationGraph, new: &DerivationGraph) -> Result<Self, CycleError> {
+        let old_paths = old.nodes().keys().collect();
+        let new_paths = new.nodes().keys().collect();
+        let mut pairs = Vec::new();
+        let mut added = Vec::new();
+        // The old derivations that could not be paired by name alone.
+        let mut removed = Vec::new();
+        for change in pair_by_name(&old_paths, &new_paths) {
+            match change {
+                Change::Changed(old_path, new_path) => pairs.push((old_path, new_path)),
+                Change::Added(path) => added.push(path),
+                Change::Removed(path) => removed.push(path),
+            }
+        }
+        // How many derivations on each side share a name without the version.
+        let mut versionless_counts = BTreeMap::<_, (usize, usize)>::new();
+        for &path in &removed {
+            versionless_counts.entry(versionless_name(path)).or_default().0 += 1;
+        }
+        for &path in &added {
+            versionless_counts.entry(versionless_name(path)).or_default().1 += 1;
+        }
+        let mut hash_parts = HashParts::new();
+        for &(old_path, new_path) in &pairs {
+            add_hash_parts(&mut hash_parts, (old, old_path), (new, new_path));
+        }
+
+        // Inputs come first, so their hash parts are known by the time the
+        // derivations using them are compared.
+        let order = new.topological_order()?;
+        let unpaired = added.iter().copied().collect::<BTreeSet<_>>();
+        let mut rewrites = Rewrites::new();
+        for &new_path in &order {
+            let (true, Some(new_derivation)) = (unpaired.contains(new_path), new.get(new_path)) else {
+                continue;
+            };
+            let versionless = versionless_name(new_path);
+            let same_name = removed.iter().copied().filter(|path| path.name() == new_path.name()).collect::<Vec<_>>();
+            let same_versionless =
+                removed.iter().copied().filter(|&path| versionless_name(path) == versionless).collect::<Vec<_>>();
+            let old_path = match_candidate(old, &same_name, new_derivation, &hash_parts, &mut rewrites)
+                .map(|index| same_name[index])
+                .or_else(|| match (versionless_counts.get(&versionless), same_versionless.as_slice()) {
+                    (Some(&(1, 1)), &[old_path]) => Some(old_path),
+                    _ => {
+                        match_candidate(old, &same_versionless, new_derivation, &hash_parts, &mut rewrites)
+                            .map(|index| same_versionless[index])
+                    },
+                });
+            if let Some(old_path) = old_path {
+                removed.retain(|&path| path != old_path);
+                rewrites.retain(|_, (_, requisites)| !requisites.contains(old_path));
+                add_hash_parts(&mut hash_parts, (old, old_path), (new, new_path));
+                pairs.push((old_path, new_path));
+            }
+        }
+        pairs.sort_by_key(|&(old_path, new_path)| (new_path.name(), old_path, new_path));
+        let paired = pairs.iter().map(|&(_, new_path)| new_path).collect::<BTreeSet<_>>();
+
+        let mut report = Self {
+            added: added.into_iter().filter(|path| !paired.contains(path)).cloned().collect(),
+            removed: removed.into_iter().cloned().collect(),
+            ..Self::default()
+        };
+        let positions =
+            order.into_iter().enumerate().map(|(position, path)| (path, position)).collect::<BTreeMap<_, _>>();
+        let changed = paired.iter().copied().chain(&report.added).collect::<BTreeSet<_>>();
+        let mut referrers = BTreeMap::<_, Vec<_>>::new();
+        for edge in new.edges() {
+            referrers.entry(edge.to).or_default().push(edge.from);
+        }
+        for &(old_path, new_path) in &pairs {
+            let (Some(old_derivation), Some(new_derivation)) = (old.get(old_path), new.get(new_path)) else {
+                continue;
+            };
+            let diff = DerivationDiff::new(&rewrite_derivation(old_derivation, &hash_parts), new_derivation);
+            if !diff.is_empty() {
+                report.origins.push(RebuildOrigin {
+                    old: old_path.clone(),
+                    new: new_path.clone(),
+                    diff,
+                    rebuilds: count_changed_referrers(&referrers, new_path, &changed),
+                });
+            }
+        }
+        report.origins.sort_by_key(|origin| positions.get(&origin.new).copied());
+        report.changed = pairs.into_iter().map(|(old_path, new_path)| (old_path.clone(), new_path.clone())).collect();
+        Ok(report)
+    }
+}
+
+/// Formats a summary followed by each origin with its changes, and the
+/// derivations that were added or removed.
+impl fmt::Display for RebuildReport {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            formatter,
+            "{} derivations changed, {} by themselves; {} added, {} removed",
+            self.changed.len(),
+            self.origins.len(),
+            self.added.len(),
+            self.removed.len(),
+        )?;
+        for origin in &self.origins {
+            writeln!(formatter, "`{}` (was `{}`), rebuilding {} more:", origin.new, origin.old, origin.rebuilds)?;
+            for line in origin.diff.to_string().lines() {
+                writeln!(formatter, "  {line}")?;
+            }
+        }
+        for path in &self.added {
+            writeln!(formatter, "added `{path}`")?;
+        }
+        for path in &self.removed {
+            writeln!(formatter, "removed `{path}`")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::derivations::errors::ReadDerivationError;
+    use crate::derivations::hashing::{
+        DerivationHasher,
+        DerivationResolver,
+        FileResolver,
+    };
+    use crate::derivations::types::{
+        DerivationInput,
+        DerivationVersion,
+    };
+    use std::env;
+    use std::path::Path;
+
+    fn edge_case_resolver() -> FileResolver {
+        let manifest_directory = env::var_os("CARGO_MANIFEST_DIR").unwrap();
+        FileResolver::in_directory(Path::new(&manifest_directory).join("src/derivations/edge_case_derivations"))
+    }
+
+    fn store_path(base_name: &str) -> StorePath {
+        StorePath::new(format!("/nix/store/{base_name}")).unwrap()
+    }
+
+    /// Recomputes the output paths of `derivation` after it was modified, and
+    /// returns its new path.
+    fn rebuild(derivation: &mut Derivation, name: &str, patched: &BTreeMap<StorePath, Derivation>) -> StorePath {
+        let resolver = |path: &StorePath| -> Result<Derivation, ReadDerivationError> {
+            patched.get(path).map_or_else(|| edge_case_resolver().resolve(path), |derivation| Ok(derivation.clone()))
+        };
+        let store_dir = Path::new("/nix/store");
+        let paths = DerivationHasher::new(resolver).output_paths(derivation, name, store_dir).unwrap();
+        for (output, path) in paths {
+            derivation.outputs.insert(output.clone(), DerivationOutput::InputAddressed(path.clone()));
+            if let Some(value) = derivation.env.get_mut(output.as_bytes()) {
+                *value = path.as_str().into();
+            }
+        }
+        derivation.drv_path(store_dir, name).unwrap()
+    }
+
+    #[test]
+    fn rewrite() {
+        let old_path = store_path("5xg9ljjhn60bwkv20dhpdhl7fxr06akb-hello-2.12.1");
+        let new_path = store_path("11111111111111111111111111111111-hello-2.12.2");
+        let hash_parts = HashParts::from([(old_path.hash_part(), (&old_path, &new_path))]);
+        assert_eq!(
+            rewrite_hash_parts(b"--prefix=/nix/store/5xg9ljjhn60bwkv20dhpdhl7fxr06akb-hello-2.12.1/bin", &hash_parts),
+            b"--prefix=/nix/store/11111111111111111111111111111111-hello-2.12.2/bin",
+        );
+        let hash_part = b"5xg9ljjhn60bwkv20dhpdhl7fxr06akb";
+        assert_eq!(rewrite_hash_parts(hash_part, &hash_parts), b"11111111111111111111111111111111");
+        let longer = b"x5xg9ljjhn60bwkv20dhpdhl7fxr06akb";
+        assert_eq!(rewrite_hash_parts(longer, &hash_parts), longer);
+        assert_eq!(rewrite_hash_parts(b"", &hash_parts), b"");
+    }
+
+    #[test]
+    fn origins() {
+        let hello_path = store_path("qhh9g08r8f6an2hly0p4rjh7q73myz93-hello-2.12.1.drv");
+        let empty_env_path = store_path("nkqgqq2l1fpyifm974rf8yhxc1gzmihx-empty-env.drv");
+        let old = DerivationGraph::load(&mut edge_case_resolver(), [empty_env_path.clone()]).unwrap();
+
+        let mut patched = BTreeMap::new();
+        let mut hello = old.get(&hello_path).unwrap().clone();
+        let build_command = hello.env.get_mut(b"buildCommand".as_slice()).unwrap();
+        *build_command = build_command.to_str_lossy().replace("make install", "make -j4 install").into();
+        let new_hello_path = rebuild(&mut hello, "hello-2.12.1", &patched);
+        patched.insert(new_hello_path.clone(), hello);
+        let mut empty_env = old.get(&empty_env_path).unwrap().clone();
+        let input = empty_env.input_drvs.remove(&hello_path).unwrap();
+        empty_env.input_drvs.insert(new_hello_path.clone(), input);
+        let new_empty_env_path = rebuild(&mut empty_env, "empty-env", &patched);
+        patched.insert(new_empty_env_path.clone(), empty_env);
+
+        let mut resolver = |path: &StorePath| {
+            patched.get(path).map_or_else(|| edge_case_resolver().resolve(path), |derivation| Ok(derivation.clone()))
+        };
+        let new = DerivationGraph::load(&mut resolver, [new_empty_env_path.clone()]).unwrap();
+        let report = RebuildReport::new(&old, &new).unwrap();
+
+        assert_eq!(report.changed, [
+            (empty_env_path, new_empty_env_path),
+            (hello_path.clone(), new_hello_path.clone()),
+        ]);
+        assert!(report.added.is_empty());
+        assert!(report.removed.is_empty());
+        let [ref origin] = *report.origins.as_slice() else {
+            panic!("expected one origin, got {:?}", report.origins);
+        };
+        assert_eq!((&origin.old, &origin.new, origin.rebuilds), (&hello_path, &new_hello_path, 1));
+        assert_eq!(origin.diff.env.keys().collect::<Vec<_>>(), ["buildCommand"]);
+        assert!(report.to_string().starts_with("2 derivations changed, 1 by themselves; 0 added, 0 removed\n"));
+    }
+
+    /// Adds a derivation named `name` with a `url` to `derivations`, returning
+    /// its path.
+    fn add(
+        derivations: &mut BTreeMap<StorePath, Derivation>,
+        name: &str,
+        url: &str,
+        inputs: &[&StorePath],
+    ) -> StorePath {
+        let input = DerivationInput {
+            value: BTreeSet::from(["out".to_string()]),
+            dynamic_outputs: BTreeMap::new(),
+        };
+        // The output path is computed by `rebuild`.
+        let output = DerivationOutput::InputAddressed(store_path(&format!("{}-{name}", "0".repeat(32))));
+        let mut derivation = Derivation {
+            version: DerivationVersion::Traditional,
+            outputs: BTreeMap::from([("out".to_string(), output)]),
+            input_drvs: inputs.iter().map(|&path| (path.clone(), input.clone())).collect(),
+            input_srcs: BTreeSet::new(),
+            system: "x86_64-linux".to_string(),
+            builder: PathBuf::from("/bin/sh"),
+            args: Vec::new(),
+            env: BTreeMap::from([("name".into(), name.into()), ("out".into(), "".into()), ("url".into(), url.into())]),
+        };
+        let path = rebuild(&mut derivation, name, derivations);
+        derivations.insert(path.clone(), derivation);
+        path
+    }
+
+    #[test]
+    fn same_names() {
+        let mut derivations = BTreeMap::new();
+        let old_patch = add(&mut derivations, "patch", "1", &[]);
+        let old_a = add(&mut derivations, "source", "a", &[&old_patch]);
+        let old_b = add(&mut derivations, "source", "b", &[&old_patch]);
+        let old_root = add(&mut derivations, "root", "", &[&old_a, &old_b]);
+        let new_patch = add(&mut derivations, "patch", "2", &[]);
+        let new_a = add(&mut derivations, "source", "a", &[&new_patch]);
+        let new_b = add(&mut derivations, "source", "b", &[&new_patch]);
+        let new_root = add(&mut derivations, "root", "", &[&new_a, &new_b]);
+
+        let mut resolver = |path: &StorePath| derivations.get(path).cloned().ok_or(());
+        let old = DerivationGraph::load(&mut resolver, [old_root.clone()]).unwrap();
+        let new = DerivationGraph::load(&mut resolver, [new_root.clone()]).unwrap();
+        let report = RebuildReport::new(&old, &new).unwrap();
+
+        let mut sources = vec![(old_a, new_a), (old_b, new_b)];
+        sources.sort();
+        let pairs = vec![(old_patch.clone(), new_patch.clone()), (old_root, new_root)];
+        assert_eq!(report.changed, [pairs, sources].concat());
+        let [ref origin] = *report.origins.as_slice() else {
+            panic!("expected one origin, got {:?}", report.origins);
+        };
+        assert_eq!((&origin.old, &origin.new, origin.rebuilds), (&old_patch, &new_patch, 3));
+        assert!(report.added.is_empty());
+        assert!(report.removed.is_empty());
+    }
+
+    #[test]
+    fn version_bump() {
+        let mut derivations = BTreeMap::new();
+        let old_source = add(&mut derivations, "openssl-3.0.1.tar.gz", "3.0.1", &[]);
+        let old_openssl = add(&mut derivations, "openssl-3.0.1", "", &[&old_source]);
+        let old_curl = add(&mut derivations, "curl-8.0", "", &[&old_openssl]);
+        let new_source = add(&mut derivations, "openssl-3.0.2.tar.gz", "3.0.2", &[]);
+        let new_openssl = add(&mut derivations, "openssl-3.0.2", "", &[&new_source]);
+        let new_curl = add(&mut derivations, "curl-8.0", "", &[&new_openssl]);
+
+        let mut resolver = |path: &StorePath| derivations.get(path).cloned().ok_or(());
+        let old = DerivationGraph::load(&mut resolver, [old_curl.clone()]).unwrap();
+        let new = DerivationGraph::load(&mut resolver, [new_curl.clone()]).unwrap();
+        let report = RebuildReport::new(&old, &new).unwrap();
+
+        assert_eq!(report.changed, [
+            (old_curl, new_curl),
+            (old_openssl.clone(), new_openssl.clone()),
+            (old_source.clone(), new_source.clone()),
+        ]);
+        assert!(report.added.is_empty());
+        assert!(report.removed.is_empty());
+        // `curl` only changed because `openssl` did.
+        let origins =
+            report.origins.iter().map(|origin| (&origin.old, &origin.new, origin.rebuilds)).collect::<Vec<_>>();
+        assert_eq!(origins, [(&old_source, &new_source, 2), (&old_openssl, &new_openssl, 1)]);
+    }
+
+    #[test]
+    fn versionless_names() {
+        for (name, expected) in [
+            ("openssl-3.0.1.drv", ("openssl", "")),
+            ("openssl-3.0.1.tar.gz.drv", ("openssl", ".tar.gz")),
+            ("bash-5.2p37.drv", ("bash", "")),
+            ("hook-setup-hook.drv", ("hook-setup-hook", "")),
+            ("source.drv", ("source", "")),
+        ] {
+            assert_eq!(versionless_name(&store_path(&format!("{}-{name}", "0".repeat(32)))), expected);
+        }
+    }
+
+    #[test]
+    fn unchanged() {
+        let root = store_path("nkqgqq2l1fpyifm974rf8yhxc1gzmihx-empty-env.drv");
+        let graph = DerivationGraph::load(&mut edge_case_resolver(), [root]).unwrap();
+        assert_eq!(RebuildReport::new(&graph, &graph).unwrap(), RebuildReport::default());
+    }
+}
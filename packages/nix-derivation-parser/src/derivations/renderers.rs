@@ -0,0 +1,346 @@
+use crate::derivations::types::{
+    Derivation,
+    DerivationInput,
+    DerivationOutput,
+};
+use crate::store_paths::types::StorePath;
+use crate::strings::renderers::write_string;
+
+use core::fmt;
+use core::str;
+use std::collections::BTreeMap;
+use std::io::{
+    self,
+    Write,
+};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+/// Renders a list of items surrounded by square brackets and separated by
+/// commas, using `write_item` to render each item.
+fn write_list<W, T, F>(writer: &mut W, items: impl IntoIterator<Item = T>, mut write_item: F) -> io::Result<()>
+where
+    W: Write,
+    F: FnMut(&mut W, T) -> io::Result<()> {
+    writer.write_all(b"[")?;
+    for (index, item) in items.into_iter().enumerate() {
+        if index > 0 {
+            writer.write_all(b",")?;
+        }
+        write_item(writer, item)?;
+    }
+    writer.write_all(b"]")
+}
+
+/// Renders a path as a string.
+fn write_path<W: Write>(writer: &mut W, path: &Path) -> io::Result<()> {
+    write_string(writer, path.as_os_str().as_bytes())
+}
+
+/// Renders a single `DerivationOutput` with its name. If `mask_outputs`, the
+/// path of an input-addressed output is left empty.
+#[expect(clippy::single_call_fn, reason = "Renderer functions are not inlined for readability.")]
+fn write_derivation_output<W: Write>(
+    writer: &mut W,
+    name: &str,
+    output: &DerivationOutput,
+    mask_outputs: bool,
+) -> io::Result<()> {
+    let path = match *output {
+        DerivationOutput::InputAddressed(_) if mask_outputs => None,
+        _ => output.path(),
+    };
+    writer.write_all(b"(")?;
+    write_string(writer, name.as_bytes())?;
+    writer.write_all(b",")?;
+    write_string(writer, path.map_or("", StorePath::as_str).as_bytes())?;
+    writer.write_all(b",")?;
+    write_string(writer, output.aterm_hash_algo().as_bytes())?;
+    writer.write_all(b",")?;
+    write_string(writer, output.aterm_hash().as_bytes())?;
+    writer.write_all(b")")
+}
+
+/// Renders the outputs used from an input derivation.
+///
+/// Like Nix, the outputs are written as a plain list unless there are dynamic
+/// outputs. Nested dynamic outputs are rendered with a stack of the entries
+/// left at each level rather than recursively, so no nesting overflows the
+/// call stack.
+fn write_derivation_input_outputs<W: Write>(writer: &mut W, input: &DerivationInput) -> io::Result<()> {
+    let mut levels = Vec::new();
+    let mut next = Some(input);
+    loop {
+        if let Some(input) = next.take() {
+            if input.dynamic_outputs.is_empty() {
+                write_list(writer, &input.value, |writer, output| write_string(writer, output.as_bytes()))?;
+            } else {
+                writer.write_all(b"(")?;
+                write_list(writer, &input.value, |writer, output| write_string(writer, output.as_bytes()))?;
+                writer.write_all(b",[")?;
+                levels.push((input.dynamic_outputs.iter(), false));
+            }
+        }
+        let Some(&mut (ref mut entries, ref mut is_started)) = levels.last_mut() else {
+            return Ok(());
+        };
+        if *is_started {
+            writer.write_all(b")")?;
+        }
+        if let Some((name, outputs)) = entries.next() {
+            if *is_started {
+                writer.write_all(b",")?;
+            }
+            *is_started = true;
+            writer.write_all(b"(")?;
+            write_string(writer, name.as_bytes())?;
+            writer.write_all(b",")?;
+            next = Some(outputs);
+        } else {
+            writer.write_all(b"])")?;
+            levels.pop();
+        }
+    }
+}
+
+/// Renders a single `DerivationInput` with its derivation path, or the hash
+/// that stands in for it.
+#[expect(clippy::single_call_fn, reason = "Renderer functions are not inlined for readability.")]
+fn write_derivation_input<W: Write>(writer: &mut W, path: &str, input: &DerivationInput) -> io::Result<()> {
+    writer.write_all(b"(")?;
+    write_string(writer, path.as_bytes())?;
+    writer.write_all(b",")?;
+    write_derivation_input_outputs(writer, input)?;
+    writer.write_all(b")")
+}
+
+/// Renders a single environment variable.
+#[expect(clippy::single_call_fn, reason = "Renderer functions are not inlined for readability.")]
+fn write_environment_variable<W: Write>(writer: &mut W, key: &[u8], value: &[u8]) -> io::Result<()> {
+    writer.write_all(b"(")?;
+    write_string(writer, key)?;
+    writer.write_all(b",")?;
+    write_string(writer, value)?;
+    writer.write_all(b")")
+}
+
+/// Renders a `Derivation` with the given input derivations, which are sorted by
+/// their keys. If `mask_outputs`, the paths of input-addressed outputs and the
+/// environment variables named after outputs are left empty.
+fn write_derivation_with<'derivation, W, I>(
+    writer: &mut W,
+    derivation: &Derivation,
+    input_drvs: I,
+    mask_outputs: bool,
+) -> io::Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = (&'derivation str, &'derivation DerivationInput)> {
+    // Like Nix, the header follows from the inputs rather than from the version
+    // the derivation was parsed in, so dynamic outputs are never written in the
+    // traditional format.
+    if derivation.has_dynamic_inputs() {
+        writer.write_all(b"DrvWithVersion(\"xp-dyn-drv\",")?;
+    } else {
+        writer.write_all(b"Derive(")?;
+    }
+    write_list(writer, &derivation.outputs, |writer, (name, output)| {
+        write_derivation_output(writer, name, output, mask_outputs)
+    })?;
+    writer.write_all(b",")?;
+    write_list(writer, input_drvs, |writer, (path, input)| write_derivation_input(writer, path, input))?;
+    writer.write_all(b",")?;
+    write_list(writer, &derivation.input_srcs, |writer, path| write_string(writer, path.as_str().as_bytes()))?;
+    writer.write_all(b",")?;
+    write_string(writer, derivation.system.as_bytes())?;
+    writer.write_all(b",")?;
+    write_path(writer, &derivation.builder)?;
+    writer.write_all(b",")?;
+    write_list(writer, &derivation.args, |writer, arg| write_string(writer, arg))?;
+    writer.write_all(b",")?;
+    write_list(writer, &derivation.env, |writer, (key, value)| {
+        let is_output = str::from_utf8(key).is_ok_and(|name| derivation.outputs.contains_key(name));
+        write_environment_variable(writer, key, if mask_outputs && is_output { b"" } else { value })
+    })?;
+    writer.write_all(b")")
+}
+
+/// Renders a `Derivation` in the ATerm format Nix uses for `.drv` files.
+///
+/// Everything is written in the same order Nix writes it, so parsing a `.drv`
+/// file written by Nix and rendering the result reproduces the original file
+/// byte for byte. The header is `DrvWithVersion("xp-dyn-drv",` if and only if an
+/// input derivation has dynamic outputs, whatever `Derivation::version` says.
+#[inline]
+pub fn write_derivation<W: Write>(writer: &mut W, derivation: &Derivation) -> io::Result<()> {
+    let input_drvs = derivation.input_drvs.iter().map(|(path, input)| (path.as_str(), input));
+    write_derivation_with(writer, derivation, input_drvs, false)
+}
+
+/// Renders a `Derivation` the way Nix does before hashing it in
+/// `hashDerivationModulo`: each input derivation is replaced by the base-16
+/// hash in `input_drvs`, and output paths are masked if `mask_outputs`.
+#[inline]
+#[must_use]
+pub fn render_derivation_modulo(
+    derivation: &Derivation,
+    input_drvs: &BTreeMap<String, DerivationInput>,
+    mask_outputs: bool,
+) -> Vec<u8> {
+    let mut rendered = Vec::new();
+    let input_drvs = input_drvs.iter().map(|(hash, input)| (hash.as_str(), input));
+    write_derivation_with(&mut rendered, derivation, input_drvs, mask_outputs)
+        .expect("Writing to a `Vec` never fails.");
+    rendered
+}
+
+/// Renders a `Derivation` into a new byte vector.
+#[inline]
+#[must_use]
+pub fn render_derivation(derivation: &Derivation) -> Vec<u8> {
+    let mut rendered = Vec::new();
+    write_derivation(&mut rendered, derivation).expect("Writing to a `Vec` never fails.");
+    rendered
+}
+
+/// Formats the rendered `Derivation`, replacing any invalid UTF-8 sequences with
+/// `U+FFFD REPLACEMENT CHARACTER`. Use `render_derivation` or
+/// `write_derivation` to get the exact bytes.
+impl fmt::Display for Derivation {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(&String::from_utf8_lossy(&render_derivation(self)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::derivations::parsers::parse_derivation_bytes;
+    use crate::derivations::types::DerivationVersion;
+    use std::collections::BTreeSet;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn input_addressed(path: &str) -> DerivationOutput {
+        DerivationOutput::InputAddressed(StorePath::new(path).unwrap())
+    }
+
+    fn assert_round_trips(directory: &str) {
+        let derivation_file_path = Path::new(&std::env::var_os("CARGO_MANIFEST_DIR").unwrap()).join(directory);
+        let paths = fs::read_dir(derivation_file_path).unwrap();
+        for path in paths {
+            let path = path.expect("There should be files here!").path();
+            let drv_bytes = fs::read(&path).unwrap();
+            let derivation = parse_derivation_bytes(&drv_bytes).unwrap();
+            assert!(render_derivation(&derivation) == drv_bytes, "{} did not round trip", path.display());
+        }
+    }
+
+    #[test]
+    fn release_packages() {
+        assert_round_trips("src/derivations/release_packages");
+    }
+
+    #[test]
+    fn release_packages_ca() {
+        assert_round_trips("src/derivations/release_packages_ca");
+    }
+
+    #[test]
+    fn misc_derivations() {
+        assert_round_trips("src/derivations/misc_derivations");
+    }
+
+    #[test]
+    fn edge_case_derivations() {
+        assert_round_trips("src/derivations/edge_case_derivations");
+    }
+
+    #[test]
+    fn header_follows_inputs() {
+        let mut derivation =
+            parse_derivation_bytes(
+                br#"DrvWithVersion("xp-dyn-drv",[("out","","","")],[],[],"x86_64-linux","/bin/sh",[],[])"#,
+            ).unwrap();
+        assert_eq!(derivation.version, DerivationVersion::DynamicDerivations);
+        assert_eq!(derivation.to_string(), r#"Derive([("out","","","")],[],[],"x86_64-linux","/bin/sh",[],[])"#);
+
+        derivation.version = DerivationVersion::Traditional;
+        derivation.input_drvs.insert(
+            StorePath::new("/nix/store/hnm5s456v5wky252cv71ni8cqaphf11i-hello.drv.drv").unwrap(),
+            DerivationInput {
+                value: BTreeSet::new(),
+                dynamic_outputs: BTreeMap::from([("out".to_string(), DerivationInput {
+                    value: BTreeSet::from(["out".to_string()]),
+                    dynamic_outputs: BTreeMap::new(),
+                })]),
+            },
+        );
+        assert_eq!(
+            derivation.to_string(),
+            concat!(
+                r#"DrvWithVersion("xp-dyn-drv",[("out","","","")],"#,
+                r#"[("/nix/store/hnm5s456v5wky252cv71ni8cqaphf11i-hello.drv.drv",([],[("out",["out"])]))],"#,
+                r#"[],"x86_64-linux","/bin/sh",[],[])"#
+            )
+        );
+    }
+
+    #[test]
+    fn nested_dynamic_outputs() {
+        let text = concat!(
+            r#"DrvWithVersion("xp-dyn-drv",[("out","","","")],"#,
+            r#"[("/nix/store/hnm5s456v5wky252cv71ni8cqaphf11i-hello.drv.drv","#,
+            r#"(["dev"],[("a",(["out"],[("b",["x"]),("c",[])])),("d",["out"])]))],"#,
+            r#"[],"x86_64-linux","/bin/sh",[],[])"#,
+        );
+        assert_eq!(parse_derivation_bytes(text.as_bytes()).unwrap().to_string(), text);
+    }
+
+    #[test]
+    fn sorted_outputs_and_inputs() {
+        let derivation = Derivation {
+            version: DerivationVersion::Traditional,
+            outputs: BTreeMap::from([
+                ("out".to_string(), input_addressed("/nix/store/gwihsgkd13xmk8vwfn2k1nkdi9bys42x-shadow-4.14.6")),
+                ("dev".to_string(), input_addressed("/nix/store/0fji8fg0z6gi3zyvsad7gxamx4ca2477-shadow-4.14.6-dev")),
+            ]),
+            input_drvs: BTreeMap::from(
+                [
+                    (
+                        StorePath::new("/nix/store/wql9zbydwdr0nqxkm20crcbhn68wb4pc-stdenv-linux.drv").unwrap(),
+                        DerivationInput {
+                            value: BTreeSet::from(["out".to_string()]),
+                            dynamic_outputs: BTreeMap::new(),
+                        },
+                    ),
+                    (
+                        StorePath::new("/nix/store/81l2lyg7hx6zwlb7yamncj1b2pbz5rj1-tcb-1.2.drv").unwrap(),
+                        DerivationInput {
+                            value: BTreeSet::from(["dev".to_string(), "out".to_string()]),
+                            dynamic_outputs: BTreeMap::new(),
+                        },
+                    ),
+                ],
+            ),
+            input_srcs: BTreeSet::new(),
+            system: "x86_64-linux".to_string(),
+            builder: PathBuf::from("/bin/sh"),
+            args: vec!["-c".into(), "echo \"$out\"\n".into()],
+            env: BTreeMap::from([("out".into(), "/nix/store/gwihsgkd13xmk8vwfn2k1nkdi9bys42x-shadow-4.14.6".into())]),
+        };
+        assert_eq!(
+            derivation.to_string(),
+            concat!(
+                r#"Derive("#,
+                r#"[("dev","/nix/store/0fji8fg0z6gi3zyvsad7gxamx4ca2477-shadow-4.14.6-dev","",""),"#,
+                r#"("out","/nix/store/gwihsgkd13xmk8vwfn2k1nkdi9bys42x-shadow-4.14.6","","")],"#,
+                r#"[("/nix/store/81l2lyg7hx6zwlb7yamncj1b2pbz5rj1-tcb-1.2.drv",["dev","out"]),"#,
+                r#"("/nix/store/wql9zbydwdr0nqxkm20crcbhn68wb4pc-stdenv-linux.drv",["out"])],"#,
+                r#"[],"x86_64-linux","/bin/sh",["-c","echo \"$out\"\n"],"#,
+                r#"[("out","/nix/store/gwihsgkd13xmk8vwfn2k1nkdi9bys42x-shadow-4.14.6")])"#
+            )
+        );
+    }
+}
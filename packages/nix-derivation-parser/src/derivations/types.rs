@@ -1,28 +1,479 @@
-use std::collections::HashMap;
-use std::path::PathBuf;
+use crate::derivations::errors::DerivationOutputError;
+use crate::hashes::errors::HashError;
+use crate::hashes::types::{
+    Hash,
+    HashAlgo,
+    HashEncoding,
+};
+use crate::store_paths::types::StorePath;
+use crate::strings::types::ByteString;
 
-#[expect(clippy::exhaustive_structs, reason = "Derivation format is very stable.")]
-#[derive(Debug, Eq, Hash, PartialEq)]
-pub struct DerivationOutput {
-    pub path: PathBuf,
-    pub hash_algo: String,
-    pub hash: String,
+extern crate alloc;
+
+use alloc::borrow::Cow;
+use core::fmt;
+use std::collections::{
+    BTreeMap,
+    BTreeSet,
+};
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+/// How the contents of a content-addressed output are hashed.
+///
+/// In a `.drv` file the method is written as a prefix of the hash algorithm,
+/// e.g. `r:sha256`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum ContentAddressMethod {
+    /// The hash of a single file's contents. Written without a prefix.
+    Flat,
+    /// The hash of the NAR serialisation of a file system object. Written as
+    /// `r:`.
+    Recursive,
+    /// The hash of a text file, such as a derivation, which may refer to other
+    /// store paths. Written as `text:`.
+    Text,
+    /// The hash of a Git tree or blob. Written as `git:`.
+    Git,
+}
+
+impl ContentAddressMethod {
+    /// Returns the prefix the method adds to the hash algorithm in a `.drv`
+    /// file.
+    #[inline]
+    #[must_use]
+    pub const fn prefix(self) -> &'static str {
+        match self {
+            Self::Flat => "",
+            Self::Recursive => "r:",
+            Self::Text => "text:",
+            Self::Git => "git:",
+        }
+    }
+
+    /// Returns the name Nix uses for the method in JSON, e.g. `nar`.
+    #[inline]
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Flat => "flat",
+            Self::Recursive => "nar",
+            Self::Text => "text",
+            Self::Git => "git",
+        }
+    }
+
+    /// Finds the method with the name Nix uses for it in JSON, e.g. `nar`.
+    #[inline]
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::Flat, Self::Recursive, Self::Text, Self::Git].into_iter().find(|method| method.name() == name)
+    }
+
+    /// Splits a hash algorithm as written in a `.drv` file, e.g. `r:sha256`, into
+    /// its method and algorithm.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `HashError` if the algorithm is not one Nix supports.
+    #[inline]
+    pub fn parse_with_algo(hash_algo: &str) -> Result<(Self, HashAlgo), HashError> {
+        let (method, algo) = [Self::Recursive, Self::Text, Self::Git]
+            .into_iter()
+            .find_map(|method| hash_algo.strip_prefix(method.prefix()).map(|algo| (method, algo)))
+            .unwrap_or((Self::Flat, hash_algo));
+        Ok((method, algo.parse()?))
+    }
+}
+
+impl fmt::Display for ContentAddressMethod {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(self.name())
+    }
 }
 
+/// An output of a derivation.
+///
+/// In a `.drv` file an output is written as a path, a hash algorithm and a
+/// hash, any of which can be empty. Which of them are empty decides the kind of
+/// output, following `parseDerivationOutput` in Nix's `derivations.cc`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum DerivationOutput {
+    /// The path is computed from the derivation itself. Written as
+    /// `("out","/nix/store/...","","")`.
+    InputAddressed(StorePath),
+    /// The contents are known in advance, e.g. for a fetched source. Written as
+    /// `("out","/nix/store/...","r:sha256","...")`.
+    CaFixed {
+        path: StorePath,
+        method: ContentAddressMethod,
+        hash: Hash,
+    },
+    /// The path is computed from the contents once the output is built. Written
+    /// as `("out","","r:sha256","")`.
+    CaFloating {
+        method: ContentAddressMethod,
+        hash_algo: HashAlgo,
+    },
+    /// Input-addressed, but the path can not be computed yet because an input is
+    /// content-addressed. Written as `("out","","","")`.
+    Deferred,
+    /// The output of an impure derivation, which is content-addressed but never
+    /// reused. Written as `("out","","r:sha256","impure")`.
+    Impure {
+        method: ContentAddressMethod,
+        hash_algo: HashAlgo,
+    },
+}
+
+impl DerivationOutput {
+    /// Builds an output from its path, hash algorithm and hash as written in a
+    /// `.drv` file.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `DerivationOutputError` if the hash algorithm or hash are invalid
+    /// or the fields do not describe any kind of output.
+    #[inline]
+    pub fn from_aterm_fields(
+        path: Option<StorePath>,
+        hash_algo: &str,
+        hash: &str,
+    ) -> Result<Self, DerivationOutputError> {
+        if hash_algo.is_empty() {
+            return match (path, hash.is_empty()) {
+                (Some(path), true) => Ok(Self::InputAddressed(path)),
+                (None, true) => Ok(Self::Deferred),
+                (_, false) => Err(DerivationOutputError::UnexpectedHash),
+            };
+        }
+
+        let (method, hash_algo) = ContentAddressMethod::parse_with_algo(hash_algo)?;
+        match (path, hash) {
+            (None, "impure") => Ok(Self::Impure { method, hash_algo }),
+            (None, "") => Ok(Self::CaFloating { method, hash_algo }),
+            (None, _) => Err(DerivationOutputError::MissingPath),
+            (Some(_), "impure" | "") => Err(DerivationOutputError::UnexpectedPath),
+            (Some(path), hash) => Ok(Self::CaFixed {
+                path,
+                method,
+                hash: Hash::parse_unprefixed(hash, hash_algo).map_err(DerivationOutputError::InvalidHash)?,
+            }),
+        }
+    }
+
+    /// Returns the path of the output if it is known in advance.
+    #[inline]
+    #[must_use]
+    pub const fn path(&self) -> Option<&StorePath> {
+        match *self {
+            Self::InputAddressed(ref path) | Self::CaFixed { ref path, .. } => Some(path),
+            Self::CaFloating { .. } | Self::Deferred | Self::Impure { .. } => None,
+        }
+    }
+
+    /// Returns the hash algorithm of the output as written in a `.drv` file, e.g.
+    /// `r:sha256`, or an empty string for input-addressed outputs.
+    #[inline]
+    #[must_use]
+    pub fn aterm_hash_algo(&self) -> String {
+        match *self {
+            Self::InputAddressed(_) | Self::Deferred => String::new(),
+            Self::CaFixed { method, ref hash, .. } => format!("{}{}", method.prefix(), hash.algo()),
+            Self::CaFloating { method, hash_algo } | Self::Impure { method, hash_algo } => {
+                format!("{}{hash_algo}", method.prefix())
+            },
+        }
+    }
+
+    /// Returns the hash of the output as written in a `.drv` file, which is
+    /// empty unless the output is fixed or impure. Like Nix, fixed hashes are
+    /// written in base-16.
+    #[inline]
+    #[must_use]
+    pub fn aterm_hash(&self) -> String {
+        match *self {
+            Self::CaFixed { ref hash, .. } => hash.encode(HashEncoding::Base16),
+            Self::Impure { .. } => "impure".to_string(),
+            Self::InputAddressed(_) | Self::CaFloating { .. } | Self::Deferred => String::new(),
+        }
+    }
+
+    /// Returns whether the contents of the output are known in advance.
+    #[inline]
+    #[must_use]
+    pub const fn is_fixed_output(&self) -> bool {
+        matches!(*self, Self::CaFixed { .. })
+    }
+
+    /// Returns whether the path of the output is computed from its contents.
+    /// Like in Nix, this includes impure outputs.
+    #[inline]
+    #[must_use]
+    pub const fn is_content_addressed(&self) -> bool {
+        matches!(*self, Self::CaFixed { .. } | Self::CaFloating { .. } | Self::Impure { .. })
+    }
+}
+
+/// The outputs of an input derivation that a derivation depends on.
+///
+/// With the `dynamic-derivations` experimental feature, an output of an input
+/// derivation can itself be a derivation. `dynamic_outputs` maps the names of
+/// such outputs to the outputs of the derivation they produce.
+///
+/// Like in Nix, output names are kept sorted.
 #[expect(clippy::exhaustive_structs, reason = "Derivation format is very stable.")]
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct DerivationInput {
-    pub value: Vec<String>,
+    pub value: BTreeSet<String>,
+    pub dynamic_outputs: BTreeMap<String, DerivationInput>,
 }
 
+impl DerivationInput {
+    /// Returns the names of the used outputs. Outputs of the derivations that
+    /// dynamic outputs produce are written like `out^bin`, as in Nix.
+    #[inline]
+    #[must_use]
+    pub fn output_names(&self) -> Vec<String> {
+        let dynamic_outputs = self.dynamic_outputs.iter().flat_map(|(name, outputs)| {
+            outputs.output_names().into_iter().map(move |output| format!("{name}^{output}"))
+        });
+        self.value.iter().cloned().chain(dynamic_outputs).collect()
+    }
+}
+
+/// The version of the ATerm format a derivation is written in.
+///
+/// Like Nix, derivations are always rendered in the version their inputs need,
+/// see `Derivation::has_dynamic_inputs`, so this only records what was parsed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum DerivationVersion {
+    /// The original format, written as `Derive(...)`.
+    #[default]
+    Traditional,
+    /// The format used by the `dynamic-derivations` experimental feature, written
+    /// as `DrvWithVersion("xp-dyn-drv",...)`.
+    DynamicDerivations,
+}
+
+/// A derivation, as read from a `.drv` file.
+///
+/// Like in Nix, outputs, input derivations, input sources and environment
+/// variables are kept sorted, so iterating over them is deterministic. Builder
+/// arguments keep their order.
+///
+/// The builder is a plain path, since it does not have to be in the store, e.g.
+/// `/bin/sh` or `builtin:fetchurl`.
 #[expect(clippy::exhaustive_structs, reason = "Derivation format is very stable.")]
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Derivation {
-    pub outputs: HashMap<String, DerivationOutput>,
-    pub input_drvs: HashMap<PathBuf, DerivationInput>,
-    pub input_srcs: Vec<PathBuf>,
+    pub version: DerivationVersion,
+    pub outputs: BTreeMap<String, DerivationOutput>,
+    pub input_drvs: BTreeMap<StorePath, DerivationInput>,
+    pub input_srcs: BTreeSet<StorePath>,
     pub system: String,
     pub builder: PathBuf,
-    pub args: Vec<String>,
-    pub env: Vec<(String, String)>,
+    pub args: Vec<ByteString>,
+    pub env: BTreeMap<ByteString, ByteString>,
+}
+
+impl Derivation {
+    /// Returns whether the derivation is a fixed-output derivation, i.e. all of
+    /// its outputs have contents known in advance.
+    #[inline]
+    #[must_use]
+    pub fn is_fixed_output(&self) -> bool {
+        !self.outputs.is_empty() && self.outputs.values().all(DerivationOutput::is_fixed_output)
+    }
+
+    /// Returns whether the outputs of the derivation are content-addressed,
+    /// either fixed, floating or impure.
+    #[inline]
+    #[must_use]
+    pub fn is_content_addressed(&self) -> bool {
+        !self.outputs.is_empty() && self.outputs.values().all(DerivationOutput::is_content_addressed)
+    }
+
+    /// Returns the `name` environment variable, which Nix names the derivation
+    /// and its outputs after, if it is valid UTF-8.
+    #[inline]
+    #[must_use]
+    pub fn name(&self) -> Option<&str> {
+        self.env.get(b"name".as_slice()).and_then(|name| name.to_str().ok())
+    }
+
+    /// Returns whether the derivation is impure.
+    #[inline]
+    #[must_use]
+    pub fn is_impure(&self) -> bool {
+        self.outputs.values().any(|output| matches!(*output, DerivationOutput::Impure { .. }))
+    }
+
+    /// Returns whether any input derivation has dynamic outputs, which can only
+    /// be written in the `DrvWithVersion("xp-dyn-drv",...)` format. This is
+    /// `hasDynamicDrvDep` in Nix.
+    #[inline]
+    #[must_use]
+    pub fn has_dynamic_inputs(&self) -> bool {
+        self.input_drvs.values().any(|input| !input.dynamic_outputs.is_empty())
+    }
+}
+
+/// Whether the hashes in a `DerivationHash` are final.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum DerivationHashKind {
+    /// The hashes can be used to compute output paths.
+    Regular,
+    /// The derivation has floating content-addressed or impure outputs, or one
+    /// of its inputs is deferred or uses dynamic outputs, so the hashes only
+    /// become final once those are built. Like in Nix, deferred input-addressed
+    /// outputs alone leave the hashes regular.
+    Deferred,
+}
+
+/// The result of Nix's `hashDerivationModulo`: a hash for each output of a
+/// derivation.
+///
+/// The outputs of a fixed-output derivation each get a hash of their content
+/// hash and path, so changing how a fixed output is fetched does not change
+/// anything that depends on it. The outputs of an impure derivation all get
+/// the same constant hash. All other outputs share the hash of the derivation
+/// with its input derivations replaced by their own hashes.
+#[expect(clippy::exhaustive_structs, reason = "Derivation format is very stable.")]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DerivationHash {
+    pub hashes: BTreeMap<String, Hash>,
+    pub kind: DerivationHashKind,
+}
+
+/// A difference between an output of a derivation and what Nix would compute
+/// for it.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum OutputMismatch {
+    /// The recorded path of the output is not the one Nix computes.
+    Path {
+        output: String,
+        recorded: StorePath,
+        computed: StorePath,
+    },
+    /// The environment variable named after the output does not hold its path.
+    Env {
+        output: String,
+        path: StorePath,
+        value: Option<ByteString>,
+    },
+}
+
+impl fmt::Display for OutputMismatch {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Path { ref output, ref recorded, ref computed } => {
+                write!(formatter, "output `{output}` has path `{recorded}`, but Nix computes `{computed}`")
+            },
+            Self::Env { ref output, ref path, value: Some(ref value) } => {
+                write!(formatter, "environment variable `{output}` is `{value}`, but should be `{path}`")
+            },
+            Self::Env { ref output, ref path, value: None } => {
+                write!(formatter, "environment variable `{output}` is missing, but should be `{path}`")
+            },
+        }
+    }
+}
+
+/// A `DerivationInput` that borrows from the input it was parsed from.
+#[expect(clippy::exhaustive_structs, reason = "Derivation format is very stable.")]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BorrowedDerivationInput<'input> {
+    pub value: BTreeSet<Cow<'input, str>>,
+    pub dynamic_outputs: BTreeMap<Cow<'input, str>, BorrowedDerivationInput<'input>>,
+}
+
+impl BorrowedDerivationInput<'_> {
+    /// Converts into an owned `DerivationInput`, copying any borrowed strings.
+    #[inline]
+    #[must_use]
+    pub fn into_owned(self) -> DerivationInput {
+        DerivationInput {
+            value: self.value.into_iter().map(Cow::into_owned).collect(),
+            dynamic_outputs: self
+                .dynamic_outputs
+                .into_iter()
+                .map(|(name, input)| (name.into_owned(), input.into_owned()))
+                .collect(),
+        }
+    }
+}
+
+/// An environment variable key and value that borrow from the input they were
+/// parsed from.
+pub type BorrowedEnvironmentVariable<'input> = (Cow<'input, [u8]>, Cow<'input, [u8]>);
+
+/// Environment variables that borrow from the input they were parsed from.
+pub type BorrowedEnvironment<'input> = BTreeMap<Cow<'input, [u8]>, Cow<'input, [u8]>>;
+
+/// A `Derivation` that borrows from the input it was parsed from.
+///
+/// Most strings in a `.drv` file contain no escapes, so they can be used
+/// straight from the input without allocating. Only strings with escapes are
+/// `Cow::Owned`. Store paths are short, so they are always copied into
+/// `StorePath`s. Use `into_owned` to get a `Derivation` that outlives the input.
+#[expect(clippy::exhaustive_structs, reason = "Derivation format is very stable.")]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BorrowedDerivation<'input> {
+    pub version: DerivationVersion,
+    pub outputs: BTreeMap<Cow<'input, str>, DerivationOutput>,
+    pub input_drvs: BTreeMap<StorePath, BorrowedDerivationInput<'input>>,
+    pub input_srcs: BTreeSet<StorePath>,
+    pub system: Cow<'input, str>,
+    pub builder: Cow<'input, Path>,
+    pub args: Vec<Cow<'input, [u8]>>,
+    pub env: BorrowedEnvironment<'input>,
+}
+
+impl BorrowedDerivation<'_> {
+    /// Converts into an owned `Derivation`, copying any borrowed strings.
+    #[inline]
+    #[must_use]
+    pub fn into_owned(self) -> Derivation {
+        Derivation {
+            version: self.version,
+            outputs: self.outputs.into_iter().map(|(name, output)| (name.into_owned(), output)).collect(),
+            input_drvs: self.input_drvs.into_iter().map(|(path, input)| (path, input.into_owned())).collect(),
+            input_srcs: self.input_srcs,
+            system: self.system.into_owned(),
+            builder: self.builder.into_owned(),
+            args: self.args.into_iter().map(|arg| ByteString::from(arg.into_owned())).collect(),
+            env: self
+                .env
+                .into_iter()
+                .map(|(key, value)| (ByteString::from(key.into_owned()), ByteString::from(value.into_owned())))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dynamic_outputs() {
+        let input = DerivationInput {
+            value: BTreeSet::from(["dev".to_string()]),
+            dynamic_outputs: BTreeMap::from([("out".to_string(), DerivationInput {
+                value: BTreeSet::from(["bin".to_string(), "out".to_string()]),
+                dynamic_outputs: BTreeMap::new(),
+            })]),
+        };
+        assert_eq!(input.output_names(), ["dev", "out^bin", "out^out"]);
+    }
 }
@@ -0,0 +1,3 @@
+pub mod encodings;
+pub mod errors;
+pub mod types;
@@ -0,0 +1,142 @@
+use crate::hashes::errors::HashError;
+
+/// The characters of base-16 encoding, as Nix writes them.
+const BASE16_ALPHABET: &[u8] = b"0123456789abcdef";
+
+/// The characters of standard base-64 encoding.
+const BASE64_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Finds the value of `character` in `alphabet`.
+fn digit(alphabet: &[u8], character: u8) -> Result<u8, HashError> {
+    alphabet
+        .iter()
+        .position(|&byte| byte == character)
+        .and_then(|position| u8::try_from(position).ok())
+        .ok_or(HashError::InvalidCharacter(char::from(character)))
+}
+
+/// Returns the length of `size` bytes encoded in base-16.
+#[inline]
+#[must_use]
+pub const fn base16_len(size: usize) -> usize {
+    size * 2
+}
+
+/// Encodes bytes in lowercase base-16.
+#[inline]
+#[must_use]
+pub fn encode_base16(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .flat_map(|&byte| [BASE16_ALPHABET[usize::from(byte >> 4)], BASE16_ALPHABET[usize::from(byte & 0xf)]])
+        .map(char::from)
+        .collect()
+}
+
+/// Decodes base-16. Like Nix, both lowercase and uppercase digits are accepted.
+///
+/// # Errors
+///
+/// Returns a `HashError` if `input` has an odd length or a character that is not
+/// a base-16 digit.
+#[inline]
+pub fn decode_base16(input: &str) -> Result<Vec<u8>, HashError> {
+    let input = input.as_bytes();
+    if !input.len().is_multiple_of(2) {
+        return Err(HashError::InvalidEncoding);
+    }
+    input
+        .chunks_exact(2)
+        .map(|pair| {
+            let [high, low] = [pair[0], pair[1]].map(|character| {
+                digit(BASE16_ALPHABET, character.to_ascii_lowercase())
+                    .map_err(|_| HashError::InvalidCharacter(char::from(character)))
+            });
+            Ok((high? << 4) | low?)
+        })
+        .collect()
+}
+
+/// Returns the length of `size` bytes encoded in padded base-64.
+#[inline]
+#[must_use]
+pub const fn base64_len(size: usize) -> usize {
+    size.div_ceil(3) * 4
+}
+
+/// Encodes bytes in standard, padded base-64.
+#[inline]
+#[must_use]
+pub fn encode_base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(base64_len(bytes.len()));
+    for chunk in bytes.chunks(3) {
+        let mut group = 0_u32;
+        for (index, &byte) in chunk.iter().enumerate() {
+            group |= u32::from(byte) << (16 - index * 8);
+        }
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(char::from(BASE64_ALPHABET[(group >> (18 - index * 6)) as usize & 0x3f]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decodes standard, padded base-64.
+///
+/// # Errors
+///
+/// Returns a `HashError` if `input` is not padded to a multiple of 4 characters,
+/// has padding anywhere but the end, or has a character that is not in the
+/// base-64 alphabet.
+#[inline]
+pub fn decode_base64(input: &str) -> Result<Vec<u8>, HashError> {
+    let input = input.as_bytes();
+    if !input.len().is_multiple_of(4) {
+        return Err(HashError::InvalidEncoding);
+    }
+    let unpadded = input.strip_suffix(b"==").or_else(|| input.strip_suffix(b"=")).unwrap_or(input);
+    let mut bytes = Vec::with_capacity(unpadded.len() * 3 / 4);
+    for chunk in unpadded.chunks(4) {
+        let mut group = 0_u32;
+        for (index, &character) in chunk.iter().enumerate() {
+            group |= u32::from(digit(BASE64_ALPHABET, character)?) << (18 - index * 6);
+        }
+        let [_, first, second, third] = group.to_be_bytes();
+        let decoded = [first, second, third];
+        let (kept, dropped) = decoded.split_at(chunk.len() - 1);
+        if chunk.len() < 2 || dropped.iter().any(|&byte| byte != 0) {
+            return Err(HashError::InvalidEncoding);
+        }
+        bytes.extend_from_slice(if chunk.len() == 4 { &decoded } else { kept });
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base16() {
+        assert_eq!(encode_base16(&[0x00, 0x7f, 0xff]), "007fff");
+        assert_eq!(decode_base16("007FfF"), Ok(vec![0x00, 0x7f, 0xff]));
+        assert_eq!(decode_base16("0g"), Err(HashError::InvalidCharacter('g')));
+        assert_eq!(decode_base16("abc"), Err(HashError::InvalidEncoding));
+    }
+
+    #[test]
+    fn base64() {
+        let cases = [(b"".as_slice(), ""), (b"f", "Zg=="), (b"fo", "Zm8="), (b"foo", "Zm9v"), (b"foob", "Zm9vYg==")];
+        for (bytes, encoded) in cases {
+            assert_eq!(encode_base64(bytes), encoded);
+            assert_eq!(decode_base64(encoded).as_deref(), Ok(bytes));
+        }
+        assert_eq!(decode_base64("Zg="), Err(HashError::InvalidEncoding));
+        assert_eq!(decode_base64("Zh=="), Err(HashError::InvalidEncoding));
+        assert_eq!(decode_base64("Z=g="), Err(HashError::InvalidCharacter('=')));
+    }
+}
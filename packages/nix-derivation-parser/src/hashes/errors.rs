@@ -0,0 +1,57 @@
+use crate::hashes::types::HashAlgo;
+use crate::nix32::errors::Nix32Error;
+
+use core::fmt;
+use std::error::Error;
+
+/// An error produced when a hash or hash algorithm can not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HashError {
+    /// The hash algorithm is not one Nix supports.
+    UnknownAlgo(String),
+    /// The hash encoding is not one Nix supports.
+    UnknownEncoding(String),
+    /// The hash has no `sha256:` or `sha256-` prefix, and no algorithm was given.
+    MissingAlgo,
+    /// The hash was expected to be made with the first algorithm, but its prefix
+    /// names the second.
+    WrongAlgo(HashAlgo, HashAlgo),
+    /// The length of the encoded hash does not match any encoding of a digest
+    /// made with the algorithm.
+    InvalidLength(HashAlgo, usize),
+    /// The digest has the wrong number of bytes for the algorithm.
+    WrongDigestSize(HashAlgo, usize),
+    /// The encoded hash contains a character its encoding does not use.
+    InvalidCharacter(char),
+    /// The encoded hash is malformed, e.g. it is wrongly padded.
+    InvalidEncoding,
+}
+
+impl fmt::Display for HashError {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::UnknownAlgo(ref algo) => write!(formatter, "unknown hash algorithm `{algo}`"),
+            Self::UnknownEncoding(ref encoding) => write!(formatter, "unknown hash encoding `{encoding}`"),
+            Self::WrongAlgo(expected, found) => write!(formatter, "expected a {expected} hash, found a {found} hash"),
+            Self::MissingAlgo => formatter.write_str("hash has no algorithm"),
+            Self::InvalidLength(algo, length) => write!(formatter, "a {algo} hash can not be {length} characters long"),
+            Self::WrongDigestSize(algo, size) => write!(formatter, "a {algo} digest is not {size} bytes long"),
+            Self::InvalidCharacter(character) => write!(formatter, "invalid character `{character}` in hash"),
+            Self::InvalidEncoding => formatter.write_str("invalid hash encoding"),
+        }
+    }
+}
+
+impl Error for HashError {}
+
+impl From<Nix32Error> for HashError {
+    #[inline]
+    fn from(err: Nix32Error) -> Self {
+        match err {
+            Nix32Error::InvalidCharacter(character) => Self::InvalidCharacter(character),
+            Nix32Error::NonZeroPadding => Self::InvalidEncoding,
+        }
+    }
+}
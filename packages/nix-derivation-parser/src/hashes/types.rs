@@ -0,0 +1,279 @@
+use crate::hashes::encodings::{
+    base16_len,
+    base64_len,
+    decode_base16,
+    decode_base64,
+    encode_base16,
+    encode_base64,
+};
+use crate::hashes::errors::HashError;
+use crate::nix32;
+
+use core::fmt;
+use core::str::FromStr;
+use sha2::{
+    Digest,
+    Sha256,
+};
+
+/// A hash algorithm supported by Nix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum HashAlgo {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl HashAlgo {
+    /// Returns the name Nix uses for the algorithm, e.g. `sha256`.
+    #[inline]
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Md5 => "md5",
+            Self::Sha1 => "sha1",
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+        }
+    }
+
+    /// Returns the size of a digest made with the algorithm, in bytes.
+    #[inline]
+    #[must_use]
+    pub const fn digest_size(self) -> usize {
+        match self {
+            Self::Md5 => 16,
+            Self::Sha1 => 20,
+            Self::Sha256 => 32,
+            Self::Sha512 => 64,
+        }
+    }
+}
+
+impl fmt::Display for HashAlgo {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(self.name())
+    }
+}
+
+impl FromStr for HashAlgo {
+    type Err = HashError;
+
+    #[inline]
+    fn from_str(name: &str) -> Result<Self, HashError> {
+        match name {
+            "md5" => Ok(Self::Md5),
+            "sha1" => Ok(Self::Sha1),
+            "sha256" => Ok(Self::Sha256),
+            "sha512" => Ok(Self::Sha512),
+            _ => Err(HashError::UnknownAlgo(name.to_string())),
+        }
+    }
+}
+
+/// A way of writing a digest as text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum HashEncoding {
+    /// Lowercase hexadecimal, as used in `.drv` files.
+    Base16,
+    /// Nix's own base-32, as used in store paths and `narinfo` files.
+    Nix32,
+    /// Standard, padded base-64.
+    Base64,
+    /// Subresource Integrity, i.e. the algorithm, a `-` and base-64, as used in
+    /// nixpkgs, e.g. `sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=`.
+    Sri,
+}
+
+impl FromStr for HashEncoding {
+    type Err = HashError;
+
+    #[inline]
+    fn from_str(name: &str) -> Result<Self, HashError> {
+        match name {
+            "base16" => Ok(Self::Base16),
+            "nix32" | "base32" => Ok(Self::Nix32),
+            "base64" => Ok(Self::Base64),
+            "sri" => Ok(Self::Sri),
+            _ => Err(HashError::UnknownEncoding(name.to_string())),
+        }
+    }
+}
+
+/// A digest together with the algorithm that made it.
+///
+/// Hashes can be parsed from any encoding Nix accepts and written in any other,
+/// e.g. to compare the base-16 hash of a fixed output with an SRI hash from
+/// nixpkgs. They are displayed in SRI form.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Hash {
+    algo: HashAlgo,
+    digest: Vec<u8>,
+}
+
+impl Hash {
+    /// Creates a hash from a digest.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `HashError` if the digest has the wrong size for `algo`.
+    #[inline]
+    pub fn new(algo: HashAlgo, digest: impl Into<Vec<u8>>) -> Result<Self, HashError> {
+        let digest = digest.into();
+        if digest.len() != algo.digest_size() {
+            return Err(HashError::WrongDigestSize(algo, digest.len()));
+        }
+        Ok(Self { algo, digest })
+    }
+
+    /// Hashes `data` with SHA-256.
+    #[inline]
+    #[must_use]
+    pub fn sha256(data: &[u8]) -> Self {
+        Self {
+            algo: HashAlgo::Sha256,
+            digest: Sha256::digest(data).to_vec(),
+        }
+    }
+
+    /// Parses a hash without an algorithm prefix, such as the hash of a fixed
+    /// output in a `.drv` file.
+    ///
+    /// Like `Hash::parseNonSRIUnprefixed` in Nix, the encoding is told apart by
+    /// the length of `input`, and may be base-16, Nix's base-32 or base-64.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `HashError` if `input` is not a valid encoding of an `algo`
+    /// digest.
+    #[inline]
+    pub fn parse_unprefixed(input: &str, algo: HashAlgo) -> Result<Self, HashError> {
+        let size = algo.digest_size();
+        let digest = match input.len() {
+            length if length == base16_len(size) => decode_base16(input)?,
+            length if length == nix32::encoded_len(size) => nix32::decode(input)?,
+            length if length == base64_len(size) => decode_base64(input)?,
+            length => return Err(HashError::InvalidLength(algo, length)),
+        };
+        Self::new(algo, digest)
+    }
+
+    /// Parses a hash with an algorithm prefix: either SRI, e.g. `sha256-...`, or
+    /// Nix's `sha256:...` with any encoding. If `input` has no prefix, it is
+    /// parsed as an `algo` hash.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `HashError` if the prefix is unknown or disagrees with `algo`,
+    /// if there is neither a prefix nor `algo`, or if the digest is invalid.
+    #[inline]
+    pub fn parse(input: &str, algo: Option<HashAlgo>) -> Result<Self, HashError> {
+        let (prefix, is_sri, rest) = match (input.split_once(':'), input.split_once('-')) {
+            (Some((prefix, rest)), _) => (Some(prefix), false, rest),
+            (None, Some((prefix, rest))) => (Some(prefix), true, rest),
+            (None, None) => (None, false, input),
+        };
+        let algo = match (prefix.map(str::parse::<HashAlgo>).transpose()?, algo) {
+            (Some(found), Some(expected)) if found != expected => return Err(HashError::WrongAlgo(expected, found)),
+            (Some(algo), _) | (None, Some(algo)) => algo,
+            (None, None) => return Err(HashError::MissingAlgo),
+        };
+        if is_sri {
+            return Self::new(algo, decode_base64(rest)?);
+        }
+        Self::parse_unprefixed(rest, algo)
+    }
+
+    /// Returns the algorithm that made the digest.
+    #[inline]
+    #[must_use]
+    pub const fn algo(&self) -> HashAlgo {
+        self.algo
+    }
+
+    /// Returns the digest.
+    #[inline]
+    #[must_use]
+    pub fn digest(&self) -> &[u8] {
+        &self.digest
+    }
+
+    /// Writes the digest in the given encoding. Only SRI includes the algorithm.
+    #[inline]
+    #[must_use]
+    pub fn encode(&self, encoding: HashEncoding) -> String {
+        match encoding {
+            HashEncoding::Base16 => encode_base16(&self.digest),
+            HashEncoding::Nix32 => nix32::encode(&self.digest),
+            HashEncoding::Base64 => encode_base64(&self.digest),
+            HashEncoding::Sri => format!("{}-{}", self.algo, encode_base64(&self.digest)),
+        }
+    }
+}
+
+impl fmt::Display for Hash {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(&self.encode(HashEncoding::Sri))
+    }
+}
+
+/// Parses a hash with an algorithm prefix, as `Hash::parse` does.
+impl FromStr for Hash {
+    type Err = HashError;
+
+    #[inline]
+    fn from_str(input: &str) -> Result<Self, HashError> {
+        Self::parse(input, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The sha256 of the empty string.
+    const EMPTY_BASE16: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+    const EMPTY_NIX32: &str = "0mdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c73";
+    const EMPTY_SRI: &str = "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=";
+
+    #[test]
+    fn names_round_trip() {
+        for algo in [HashAlgo::Md5, HashAlgo::Sha1, HashAlgo::Sha256, HashAlgo::Sha512] {
+            assert_eq!(algo.to_string().parse(), Ok(algo));
+        }
+        assert_eq!("blake3".parse::<HashAlgo>(), Err(HashError::UnknownAlgo("blake3".to_string())));
+    }
+
+    #[test]
+    fn parse_any_encoding() {
+        let hash = Hash::parse_unprefixed(EMPTY_BASE16, HashAlgo::Sha256).unwrap();
+        assert_eq!(Hash::parse_unprefixed(EMPTY_NIX32, HashAlgo::Sha256), Ok(hash.clone()));
+        assert_eq!(EMPTY_SRI.parse(), Ok(hash.clone()));
+        assert_eq!(format!("sha256:{EMPTY_NIX32}").parse(), Ok(hash.clone()));
+        assert_eq!(Hash::parse(EMPTY_BASE16, Some(HashAlgo::Sha256)), Ok(hash.clone()));
+        assert_eq!(hash.encode(HashEncoding::Base16), EMPTY_BASE16);
+        assert_eq!(hash.encode(HashEncoding::Nix32), EMPTY_NIX32);
+        assert_eq!(hash.to_string(), EMPTY_SRI);
+    }
+
+    #[test]
+    fn sha256() {
+        assert_eq!(Hash::sha256(b"").encode(HashEncoding::Base16), EMPTY_BASE16);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(EMPTY_BASE16.parse::<Hash>(), Err(HashError::MissingAlgo));
+        assert_eq!(Hash::parse_unprefixed("abcd", HashAlgo::Sha1), Err(HashError::InvalidLength(HashAlgo::Sha1, 4)));
+        assert_eq!(
+            Hash::parse(EMPTY_SRI, Some(HashAlgo::Sha512)),
+            Err(HashError::WrongAlgo(HashAlgo::Sha512, HashAlgo::Sha256))
+        );
+        assert_eq!("sha256-Zg==".parse::<Hash>(), Err(HashError::WrongDigestSize(HashAlgo::Sha256, 1)));
+    }
+}
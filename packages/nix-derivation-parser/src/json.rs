@@ -0,0 +1,4 @@
+pub mod errors;
+pub mod parsers;
+pub mod renderers;
+pub mod types;
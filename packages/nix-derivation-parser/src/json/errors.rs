@@ -0,0 +1,67 @@
+use crate::positions::Position;
+
+use core::fmt;
+use std::error::Error;
+
+/// What went wrong while parsing JSON.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum JsonParseErrorKind {
+    /// Expected a value, such as a string or an object.
+    ExpectedValue,
+    /// Expected the given token, such as `:`.
+    ExpectedToken(&'static str),
+    /// Expected a string as the key of an object entry.
+    ExpectedKey,
+    /// Expected a `,` before the next element of an array or a `]` ending it.
+    ExpectedArraySeparator,
+    /// Expected a `,` before the next entry of an object or a `}` ending it.
+    ExpectedObjectSeparator,
+    /// A backslash in a string is not followed by a valid escape.
+    InvalidEscape,
+    /// A string contains a control character, which must be escaped.
+    UnescapedControlCharacter,
+    /// The input ended before the value was complete.
+    UnexpectedEnd,
+    /// There is more input after the end of the value.
+    TrailingData,
+    /// Arrays and objects are nested deeper than `MAX_DEPTH`.
+    TooDeep,
+}
+
+impl fmt::Display for JsonParseErrorKind {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::ExpectedValue => formatter.write_str("expected a value"),
+            Self::ExpectedToken(token) => write!(formatter, "expected `{token}`"),
+            Self::ExpectedKey => formatter.write_str("expected a string key"),
+            Self::ExpectedArraySeparator => formatter.write_str("expected `,` or `]`"),
+            Self::ExpectedObjectSeparator => formatter.write_str("expected `,` or `}`"),
+            Self::InvalidEscape => formatter.write_str("invalid escape sequence"),
+            Self::UnescapedControlCharacter => formatter.write_str("unescaped control character in a string"),
+            Self::UnexpectedEnd => formatter.write_str("unexpected end of input"),
+            Self::TrailingData => formatter.write_str("unexpected data after the end of the value"),
+            Self::TooDeep => formatter.write_str("arrays and objects are nested too deeply"),
+        }
+    }
+}
+
+/// An error produced when JSON can not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct JsonParseError {
+    /// What went wrong.
+    pub kind: JsonParseErrorKind,
+    /// Where in the input the error occurred.
+    pub position: Position,
+}
+
+impl fmt::Display for JsonParseError {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "{} at {}", self.kind, self.position)
+    }
+}
+
+impl Error for JsonParseError {}
@@ -0,0 +1,321 @@
+use crate::json::errors::{
+    JsonParseError,
+    JsonParseErrorKind,
+};
+use crate::json::types::JsonValue;
+use crate::positions::Position;
+
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{
+        char,
+        digit0,
+        digit1,
+        one_of,
+    },
+    combinator::{
+        map,
+        opt,
+        recognize,
+        value,
+    },
+    error::{
+        ErrorKind,
+        ParseError,
+    },
+    sequence::{
+        pair,
+        preceded,
+        tuple,
+    },
+    Err,
+    IResult,
+};
+use std::collections::BTreeMap;
+
+/// The number of arrays and objects a value can be nested in, as in
+/// `serde_json`. Deeper values are rejected rather than overflowing the stack.
+pub const MAX_DEPTH: usize = 128;
+
+/// The error used while parsing. It only borrows the input, and is turned into a
+/// `JsonParseError` once parsing has failed.
+#[derive(Debug, PartialEq)]
+struct ParserError<'input> {
+    /// The remaining input where the error occurred.
+    input: &'input str,
+    kind: JsonParseErrorKind,
+}
+
+impl<'input> ParserError<'input> {
+    /// Creates an error at `input`. Running out of input is always reported as
+    /// an unexpected end, no matter what was expected.
+    fn new(input: &'input str, kind: JsonParseErrorKind) -> Self {
+        Self {
+            input,
+            kind: if input.is_empty() { JsonParseErrorKind::UnexpectedEnd } else { kind },
+        }
+    }
+}
+
+impl<'input> ParseError<&'input str> for ParserError<'input> {
+    // The nom parsers used here only fail at the start of a value.
+    #[inline]
+    fn from_error_kind(input: &'input str, _kind: ErrorKind) -> Self {
+        Self::new(input, JsonParseErrorKind::ExpectedValue)
+    }
+
+    #[inline]
+    fn append(_input: &'input str, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+
+    // Report the alternative that got the furthest.
+    #[inline]
+    fn or(self, other: Self) -> Self {
+        if other.input.len() < self.input.len() {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+type ParserResult<'input, O> = IResult<&'input str, O, ParserError<'input>>;
+
+/// Fails with an error of the given kind at `input`.
+fn fail<O>(input: &str, kind: JsonParseErrorKind) -> ParserResult<'_, O> {
+    Err(Err::Error(ParserError::new(input, kind)))
+}
+
+/// Skips the whitespace JSON allows between tokens.
+fn skip_whitespace(input: &str) -> &str {
+    input.trim_start_matches([' ', '\t', '\n', '\r'])
+}
+
+/// Parses `token` after any whitespace, failing with `kind` if it is not there.
+fn parse_token<'input>(input: &'input str, token: &str, kind: JsonParseErrorKind) -> ParserResult<'input, ()> {
+    let input = skip_whitespace(input);
+    match input.strip_prefix(token) {
+        Some(rest) => Ok((rest, ())),
+        None => fail(input, kind),
+    }
+}
+
+/// Parses the four hexadecimal digits of a `\u` escape.
+fn parse_hex_digits(input: &str) -> ParserResult<'_, u32> {
+    let digits = input.get(..4).filter(|digits| digits.bytes().all(|byte| byte.is_ascii_hexdigit()));
+    match digits.and_then(|digits| u32::from_str_radix(digits, 16).ok()) {
+        Some(code) => Ok((input.get(4..).unwrap_or_default(), code)),
+        None => fail(input, JsonParseErrorKind::InvalidEscape),
+    }
+}
+
+/// Parses an escape sequence after its backslash. Characters outside the basic
+/// multilingual plane are escaped as a pair of UTF-16 surrogates.
+#[expect(clippy::single_call_fn, reason = "Parser functions are not inlined for readability.")]
+fn parse_escape(input: &str) -> ParserResult<'_, char> {
+    let mut chars = input.chars();
+    let escaped = match chars.next() {
+        Some(character @ ('"' | '\\' | '/')) => character,
+        Some('b') => '\u{8}',
+        Some('f') => '\u{c}',
+        Some('n') => '\n',
+        Some('r') => '\r',
+        Some('t') => '\t',
+        Some('u') => {
+            let (rest, code) = parse_hex_digits(chars.as_str())?;
+            let (rest, code) = if (0xD800..0xDC00).contains(&code) {
+                let (rest, low) = preceded(tag("\\u"), parse_hex_digits)(rest)?;
+                if !(0xDC00..0xE000).contains(&low) {
+                    return fail(input, JsonParseErrorKind::InvalidEscape);
+                }
+                (rest, 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00))
+            } else {
+                (rest, code)
+            };
+            return match char::from_u32(code) {
+                Some(character) => Ok((rest, character)),
+                None => fail(input, JsonParseErrorKind::InvalidEscape),
+            };
+        },
+        _ => return fail(input, JsonParseErrorKind::InvalidEscape),
+    };
+    Ok((chars.as_str(), escaped))
+}
+
+/// Parses a string, surrounded by double quotes.
+fn parse_string(input: &str) -> ParserResult<'_, String> {
+    let Some(mut rest) = input.strip_prefix('"') else {
+        return fail(input, JsonParseErrorKind::ExpectedValue);
+    };
+    let mut string = String::new();
+    loop {
+        let literal_end = rest.find(|character| matches!(character, '"' | '\\' | '\0'..='\u{1f}'));
+        let (literal, after_literal) = rest.split_at(literal_end.unwrap_or(rest.len()));
+        string.push_str(literal);
+        let mut chars = after_literal.chars();
+        match chars.next() {
+            Some('"') => return Ok((chars.as_str(), string)),
+            Some('\\') => {
+                let (after_escape, character) = parse_escape(chars.as_str())?;
+                string.push(character);
+                rest = after_escape;
+            },
+            Some(_) => return fail(after_literal, JsonParseErrorKind::UnescapedControlCharacter),
+            None => return fail(after_literal, JsonParseErrorKind::UnexpectedEnd),
+        }
+    }
+}
+
+/// Parses a number, which is kept as written.
+#[expect(clippy::single_call_fn, reason = "Parser functions are not inlined for readability.")]
+fn parse_number(input: &str) -> ParserResult<'_, String> {
+    let integer = alt((tag("0"), recognize(pair(one_of("123456789"), digit0))));
+    let fraction = pair(char('.'), digit1);
+    let exponent = tuple((one_of("eE"), opt(one_of("+-")), digit1));
+    map(recognize(tuple((opt(char('-')), integer, opt(fraction), opt(exponent)))), str::to_owned)(input)
+}
+
+/// Parses the elements of an array after its `[`, at `depth`.
+#[expect(clippy::single_call_fn, reason = "Parser functions are not inlined for readability.")]
+fn parse_array_elements(input: &str, depth: usize) -> ParserResult<'_, Vec<JsonValue>> {
+    let mut values = Vec::new();
+    if let Ok((rest, ())) = parse_token(input, "]", JsonParseErrorKind::ExpectedValue) {
+        return Ok((rest, values));
+    }
+    let mut rest = input;
+    loop {
+        let (after_value, value) = parse_value(rest, depth)?;
+        values.push(value);
+        if let Ok((after_end, ())) = parse_token(after_value, "]", JsonParseErrorKind::ExpectedArraySeparator) {
+            return Ok((after_end, values));
+        }
+        (rest, ()) = parse_token(after_value, ",", JsonParseErrorKind::ExpectedArraySeparator)?;
+    }
+}
+
+/// Parses the entries of an object after its `{`, at `depth`. Like in
+/// `nlohmann::json`, a key that is repeated takes its last value.
+#[expect(clippy::single_call_fn, reason = "Parser functions are not inlined for readability.")]
+fn parse_object_entries(input: &str, depth: usize) -> ParserResult<'_, BTreeMap<String, JsonValue>> {
+    let mut entries = BTreeMap::new();
+    if let Ok((rest, ())) = parse_token(input, "}", JsonParseErrorKind::ExpectedKey) {
+        return Ok((rest, entries));
+    }
+    let mut rest = input;
+    loop {
+        let after_whitespace = skip_whitespace(rest);
+        let (after_key, key) = parse_string(after_whitespace).map_err(|err| {
+            err.map(|err| {
+                if err.input == after_whitespace {
+                    ParserError::new(after_whitespace, JsonParseErrorKind::ExpectedKey)
+                } else {
+                    err
+                }
+            })
+        })?;
+        let (after_colon, ()) = parse_token(after_key, ":", JsonParseErrorKind::ExpectedToken(":"))?;
+        let (after_value, value) = parse_value(after_colon, depth)?;
+        entries.insert(key, value);
+        if let Ok((after_end, ())) = parse_token(after_value, "}", JsonParseErrorKind::ExpectedObjectSeparator) {
+            return Ok((after_end, entries));
+        }
+        (rest, ()) = parse_token(after_value, ",", JsonParseErrorKind::ExpectedObjectSeparator)?;
+    }
+}
+
+/// Parses a value after any whitespace. `depth` is the number of arrays and
+/// objects the value is in.
+fn parse_value(input: &str, depth: usize) -> ParserResult<'_, JsonValue> {
+    let input = skip_whitespace(input);
+    if depth == MAX_DEPTH && input.starts_with(['[', '{']) {
+        return fail(input, JsonParseErrorKind::TooDeep);
+    }
+    alt((
+        value(JsonValue::Null, tag("null")),
+        value(JsonValue::Bool(true), tag("true")),
+        value(JsonValue::Bool(false), tag("false")),
+        map(parse_number, JsonValue::Number),
+        map(parse_string, JsonValue::String),
+        map(preceded(char('['), |rest| parse_array_elements(rest, depth + 1)), JsonValue::Array),
+        map(preceded(char('{'), |rest| parse_object_entries(rest, depth + 1)), JsonValue::Object),
+    ))(input)
+}
+
+/// Parses a JSON document: a value, surrounded by any whitespace.
+///
+/// # Errors
+///
+/// Returns a `JsonParseError` if `input` is not valid JSON, or if it nests
+/// arrays and objects deeper than `MAX_DEPTH`.
+#[inline]
+pub fn parse_json(input: &str) -> Result<JsonValue, JsonParseError> {
+    let into_parse_error = |err: ParserError<'_>| JsonParseError {
+        kind: err.kind,
+        position: Position::new(input.as_bytes(), input.len() - err.input.len()),
+    };
+    match parse_value(input, 0) {
+        Ok((rest, value)) => {
+            let rest = skip_whitespace(rest);
+            if rest.is_empty() {
+                Ok(value)
+            } else {
+                Err(into_parse_error(ParserError::new(rest, JsonParseErrorKind::TrailingData)))
+            }
+        },
+        Err(Err::Error(err) | Err::Failure(err)) => Err(into_parse_error(err)),
+        Err(Err::Incomplete(_)) => Err(into_parse_error(ParserError::new("", JsonParseErrorKind::UnexpectedEnd))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::renderers::render_compact_json;
+
+    fn parse(input: &str) -> String {
+        render_compact_json(&parse_json(input).unwrap())
+    }
+
+    fn parse_error(input: &str) -> String {
+        parse_json(input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(parse(" null "), "null");
+        assert_eq!(parse("[true, false, 0, -12.5e+3, \"\"]"), r#"[true,false,0,-12.5e+3,""]"#);
+        assert_eq!(parse("{ \"b\": {}, \"a\" : [ ], \"b\": 1 }"), r#"{"a":[],"b":1}"#);
+    }
+
+    #[test]
+    fn escapes() {
+        let value = parse_json(r#""\"\\\/\b\f\n\r\té😀""#).unwrap();
+        assert_eq!(value, JsonValue::String("\"\\/\u{8}\u{c}\n\r\té😀".to_owned()));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse_error(""), "unexpected end of input at line 1, column 1 (byte 0)");
+        assert_eq!(parse_error("[1 2]"), "expected `,` or `]` at line 1, column 4 (byte 3)");
+        assert_eq!(parse_error("{\n  1: 2}"), "expected a string key at line 2, column 3 (byte 4)");
+        assert_eq!(parse_error(r#"{"a" 1}"#), "expected `:` at line 1, column 6 (byte 5)");
+        assert_eq!(parse_error(r#"{"a": [nul]}"#), "expected a value at line 1, column 8 (byte 7)");
+        assert_eq!(parse_error(r#""\x""#), "invalid escape sequence at line 1, column 3 (byte 2)");
+        assert_eq!(parse_error("\"a\nb\""), "unescaped control character in a string at line 1, column 3 (byte 2)");
+        assert_eq!(parse_error("01"), "unexpected data after the end of the value at line 1, column 2 (byte 1)");
+        assert_eq!(parse_error("[1,"), "unexpected end of input at line 1, column 4 (byte 3)");
+    }
+
+    #[test]
+    fn depth() {
+        let nested = |depth| format!("{}0{}", "[{\"a\":".repeat(depth / 2), "}]".repeat(depth / 2));
+        assert!(parse_json(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(parse_json(&nested(MAX_DEPTH + 2)).unwrap_err().kind, JsonParseErrorKind::TooDeep);
+        assert_eq!(
+            parse_error(&"[".repeat(200_000)),
+            "arrays and objects are nested too deeply at line 1, column 129 (byte 128)",
+        );
+    }
+}
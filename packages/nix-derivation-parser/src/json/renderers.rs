@@ -0,0 +1,134 @@
+use crate::json::types::JsonValue;
+
+use std::io::{
+    self,
+    Write,
+};
+
+/// Renders a string, surrounded by double quotes. Like `nlohmann::json`, only
+/// quotes, backslashes and control characters are escaped.
+fn write_json_string<W: Write>(writer: &mut W, string: &str) -> io::Result<()> {
+    writer.write_all(b"\"")?;
+    for character in string.chars() {
+        match character {
+            '"' => writer.write_all(b"\\\"")?,
+            '\\' => writer.write_all(b"\\\\")?,
+            '\u{8}' => writer.write_all(b"\\b")?,
+            '\u{c}' => writer.write_all(b"\\f")?,
+            '\n' => writer.write_all(b"\\n")?,
+            '\r' => writer.write_all(b"\\r")?,
+            '\t' => writer.write_all(b"\\t")?,
+            '\0'..='\u{1f}' => write!(writer, "\\u{:04x}", u32::from(character))?,
+            _ => writer.write_all(character.encode_utf8(&mut [0; 4]).as_bytes())?,
+        }
+    }
+    writer.write_all(b"\"")
+}
+
+/// Renders a value. If `depth` is given, arrays and objects are spread over
+/// lines indented by two spaces per level, starting at `depth`; otherwise the
+/// value is written without any whitespace.
+fn write_json_value<W: Write>(writer: &mut W, value: &JsonValue, depth: Option<usize>) -> io::Result<()> {
+    // What comes before each element and the closing bracket or brace, and
+    // between a key and its value.
+    let (separator, end, colon) = match depth {
+        Some(depth) => (format!("\n{}", "  ".repeat(depth + 1)), format!("\n{}", "  ".repeat(depth)), ": "),
+        None => (String::new(), String::new(), ":"),
+    };
+    let inner_depth = depth.map(|depth| depth + 1);
+    match *value {
+        JsonValue::Null => writer.write_all(b"null"),
+        JsonValue::Bool(bool) => write!(writer, "{bool}"),
+        JsonValue::Number(ref number) => writer.write_all(number.as_bytes()),
+        JsonValue::String(ref string) => write_json_string(writer, string),
+        JsonValue::Array(ref values) if values.is_empty() => writer.write_all(b"[]"),
+        JsonValue::Array(ref values) => {
+            writer.write_all(b"[")?;
+            for (index, value) in values.iter().enumerate() {
+                if index > 0 {
+                    writer.write_all(b",")?;
+                }
+                writer.write_all(separator.as_bytes())?;
+                write_json_value(writer, value, inner_depth)?;
+            }
+            write!(writer, "{end}]")
+        },
+        JsonValue::Object(ref entries) if entries.is_empty() => writer.write_all(b"{}"),
+        JsonValue::Object(ref entries) => {
+            writer.write_all(b"{")?;
+            for (index, (key, value)) in entries.iter().enumerate() {
+                if index > 0 {
+                    writer.write_all(b",")?;
+                }
+                writer.write_all(separator.as_bytes())?;
+                write_json_string(writer, key)?;
+                writer.write_all(colon.as_bytes())?;
+                write_json_value(writer, value, inner_depth)?;
+            }
+            write!(writer, "{end}}}")
+        },
+    }
+}
+
+/// Renders a value indented by two spaces, like `nix derivation show`.
+#[inline]
+pub fn write_json<W: Write>(writer: &mut W, value: &JsonValue) -> io::Result<()> {
+    write_json_value(writer, value, Some(0))
+}
+
+/// Renders a value without any whitespace, like Nix writes the structured
+/// attributes of a derivation into its `__json` environment variable.
+#[inline]
+pub fn write_compact_json<W: Write>(writer: &mut W, value: &JsonValue) -> io::Result<()> {
+    write_json_value(writer, value, None)
+}
+
+/// Renders a value indented by two spaces into a new string.
+#[inline]
+#[must_use]
+pub fn render_json(value: &JsonValue) -> String {
+    let mut rendered = Vec::new();
+    write_json(&mut rendered, value).expect("Writing to a `Vec` never fails.");
+    String::from_utf8(rendered).expect("Rendered JSON is valid UTF-8.")
+}
+
+/// Renders a value without any whitespace into a new string.
+#[inline]
+#[must_use]
+pub fn render_compact_json(value: &JsonValue) -> String {
+    let mut rendered = Vec::new();
+    write_compact_json(&mut rendered, value).expect("Writing to a `Vec` never fails.");
+    String::from_utf8(rendered).expect("Rendered JSON is valid UTF-8.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> JsonValue {
+        JsonValue::object([
+            ("array", JsonValue::strings(["a\"b", "\u{1}\n", "é"])),
+            ("empty", JsonValue::object::<String>([])),
+            (
+                "values",
+                JsonValue::Array(vec![JsonValue::Bool(true), JsonValue::Null, JsonValue::Number("-1.5e3".to_owned())]),
+            ),
+        ])
+    }
+
+    #[test]
+    fn pretty() {
+        assert_eq!(
+            render_json(&example()),
+            "{\n  \"array\": [\n    \"a\\\"b\",\n    \"\\u0001\\n\",\n    \"é\"\n  ],\n  \"empty\": {},\n  \"values\": [\n    true,\n    null,\n    -1.5e3\n  ]\n}",
+        );
+    }
+
+    #[test]
+    fn compact() {
+        assert_eq!(
+            render_compact_json(&example()),
+            r#"{"array":["a\"b","\u0001\n","é"],"empty":{},"values":[true,null,-1.5e3]}"#,
+        );
+    }
+}
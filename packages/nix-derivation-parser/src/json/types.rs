@@ -0,0 +1,68 @@
+use std::collections::BTreeMap;
+
+/// A JSON value.
+///
+/// Like in Nix, which uses `nlohmann::json`, object keys are kept sorted.
+/// Numbers are kept as written, since derivations only use them for format
+/// versions and in structured attributes, which are passed on unchanged.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(BTreeMap<String, JsonValue>),
+}
+
+impl JsonValue {
+    /// Builds an object from its keys and values.
+    #[inline]
+    pub fn object<K: Into<String>>(entries: impl IntoIterator<Item = (K, Self)>) -> Self {
+        Self::Object(entries.into_iter().map(|(key, value)| (key.into(), value)).collect())
+    }
+
+    /// Builds an array of strings.
+    #[inline]
+    pub fn strings<S: Into<String>>(strings: impl IntoIterator<Item = S>) -> Self {
+        Self::Array(strings.into_iter().map(|string| Self::String(string.into())).collect())
+    }
+
+    /// Returns the contents if the value is a string.
+    #[inline]
+    #[must_use]
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Self::String(ref string) => Some(string),
+            Self::Null | Self::Bool(_) | Self::Number(_) | Self::Array(_) | Self::Object(_) => None,
+        }
+    }
+
+    /// Returns the elements if the value is an array.
+    #[inline]
+    #[must_use]
+    pub fn as_array(&self) -> Option<&[Self]> {
+        match *self {
+            Self::Array(ref values) => Some(values),
+            Self::Null | Self::Bool(_) | Self::Number(_) | Self::String(_) | Self::Object(_) => None,
+        }
+    }
+
+    /// Returns the entries if the value is an object.
+    #[inline]
+    #[must_use]
+    pub const fn as_object(&self) -> Option<&BTreeMap<String, Self>> {
+        match *self {
+            Self::Object(ref entries) => Some(entries),
+            Self::Null | Self::Bool(_) | Self::Number(_) | Self::String(_) | Self::Array(_) => None,
+        }
+    }
+
+    /// Returns the value of `key` if the value is an object that has it.
+    #[inline]
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&Self> {
+        self.as_object()?.get(key)
+    }
+}
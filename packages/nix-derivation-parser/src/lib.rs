@@ -1,4 +1,4 @@
-//! A module for parsing derivations from `.drv` files.
+//! A module for parsing and rendering derivations from `.drv` files.
 #![allow(clippy::implicit_return, reason = "clippy will ping pong on this forever.")]
 #![allow(
     clippy::self_named_module_files,
@@ -7,4 +7,9 @@
 )]
 
 pub mod derivations;
+pub mod hashes;
+pub mod json;
+pub mod nix32;
+pub mod positions;
+pub mod store_paths;
 pub mod strings;
@@ -1,18 +1,684 @@
-use nix_derivation_parser::derivations::parsers::parse_derivation;
-use std::fs;
-
-fn main() {
-    let input =
-        fs::read_to_string(
-            "./src/vlv5v250k5daq2dnhj3bzn7p5dnsrg2f-nixos-system-massflash-24.05.20241009.d51c286.drv",
-        ).unwrap();
-    match parse_derivation(&input) {
-        Ok((remaining, parsed)) => {
-            println!("Parsed: {parsed:#?}");
-            println!("Remaining: {remaining:#?}");
+use nix_derivation_parser::derivations::diffs::DerivationDiff;
+use nix_derivation_parser::derivations::errors::ReadDerivationError;
+use nix_derivation_parser::derivations::graphs::exporters::{
+    self,
+    matches_glob,
+    ExportOptions,
+};
+use nix_derivation_parser::derivations::graphs::DerivationGraph;
+use nix_derivation_parser::derivations::hashing::{
+    DerivationHasher,
+    FileResolver,
+};
+use nix_derivation_parser::derivations::indexes::OutputIndex;
+use nix_derivation_parser::derivations::json::{
+    derivation_from_json,
+    derivations_from_json,
+    derivations_to_json,
+    JsonFormat,
+};
+use nix_derivation_parser::derivations::parsers::read_derivation;
+use nix_derivation_parser::derivations::rebuilds::RebuildReport;
+use nix_derivation_parser::derivations::renderers::write_derivation;
+use nix_derivation_parser::derivations::types::{
+    Derivation,
+    DerivationOutput,
+};
+use nix_derivation_parser::json::parsers::parse_json;
+use nix_derivation_parser::json::renderers::write_json;
+use nix_derivation_parser::store_paths::types::{
+    StorePath,
+    DEFAULT_STORE_DIR,
+    HASH_PART_LENGTH,
+};
+
+use std::collections::{
+    BTreeMap,
+    BTreeSet,
+};
+use std::env;
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fs::{
+    self,
+    File,
+};
+use std::io::{
+    self,
+    Write as _,
+};
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+usage: nix-derivation-parser COMMAND [ARGUMENTS...]
+
+Commands:
+  parse [FILES...]                   print the parsed derivations
+  show [FILES...]                    print the derivations in a readable form
+  render [FILES...]                  render the derivations as `.drv` files
+  json [--format FORMAT] [FILES...]  print the derivations as JSON, like `nix derivation show`, in
+                                     the format `unversioned` (the default), `3` or `4`
+  from-json [FILES...]               render derivations from JSON, like `nix derivation show`
+                                     prints, as `.drv` files
+  check FILES...                     check the paths of the derivations and their outputs
+  closure FILES...                   print the closure of the derivations
+  graph [--depth N] [--pattern GLOB] FORMAT FILES...
+                                     print the closure as a `dot`, `graphml` or `mermaid` graph, only
+                                     with the derivations at most N inputs away from the given ones
+                                     or with names matching GLOB, e.g. `hello-*`
+  diff [--recursive] OLD NEW         print the differences between two derivations
+  rebuilds OLD NEW                   print the changes that cause the rebuilds between two closures
+  why-depends [--all] ROOT TARGET    print the chains of input derivations from ROOT to TARGET
+  deriver DIRECTORY QUERIES...       print the derivations producing output paths or hash prefixes
+
+FILES are `.drv` files or globs of them, e.g. `/nix/store/*-hello-*.drv`, except for
+`from-json`, which reads JSON files. `parse`, `show`, `render`, `json` and `from-json`
+read standard input for `-` or if no files are given.
+
+Exits with 0 on success, 1 if a derivation is invalid or differs, and 2 if the
+arguments are invalid.
+";
+
+/// The exit code for invalid arguments, like most command-line tools.
+const USAGE_ERROR: u8 = 2;
+
+/// Prints `message` and the usage, and returns the exit code for invalid
+/// arguments.
+fn usage_error(message: &str) -> ExitCode {
+    eprintln!("{message}");
+    eprint!("\n{USAGE}");
+    ExitCode::from(USAGE_ERROR)
+}
+
+/// Returns the directory containing the file at `path`, which is the current
+/// directory for a bare file name.
+fn parent_directory(path: &Path) -> &Path {
+    path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."))
+}
+
+/// Expands the globs in `arguments`, e.g. `/nix/store/*-hello-*.drv`, into the
+/// paths they match, printing any error. Like in a shell, only file names can
+/// have wildcards, and hidden files only match patterns starting with `.`.
+fn expand_globs(arguments: &[PathBuf]) -> Option<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for argument in arguments {
+        let Some(pattern) =
+            argument.file_name().and_then(OsStr::to_str).filter(|file_name| file_name.contains(['*', '?']))
+        else {
+            paths.push(argument.clone());
+            continue;
+        };
+        let directory = parent_directory(argument);
+        let entries = match fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(err) => {
+                eprintln!("{}: {err}", directory.display());
+                return None;
+            },
+        };
+        let mut matches = entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name().and_then(OsStr::to_str).is_some_and(|file_name| {
+                    (!file_name.starts_with('.') || pattern.starts_with('.')) && matches_glob(pattern, file_name)
+                })
+            })
+            .collect::<Vec<_>>();
+        if matches.is_empty() {
+            eprintln!("{}: no files match", argument.display());
+            return None;
+        }
+        matches.sort();
+        paths.extend(matches);
+    }
+    Some(paths)
+}
+
+/// Reads the derivation in the file at `path`, or from standard input if
+/// `path` is `-`.
+fn read_input(path: &Path) -> Result<Derivation, ReadDerivationError> {
+    if path.as_os_str() == "-" {
+        read_derivation(io::stdin().lock())
+    } else {
+        File::open(path).map_err(Into::into).and_then(read_derivation)
+    }
+}
+
+/// Reads the derivations at `paths` and calls `visit` with each, printing an
+/// error for each that can not be read or visited. Fails if any can not.
+fn for_each_derivation(paths: &[PathBuf], mut visit: impl FnMut(&Path, Derivation) -> io::Result<()>) -> ExitCode {
+    let mut exit_code = ExitCode::SUCCESS;
+    for path in paths {
+        match read_input(path) {
+            Ok(derivation) => {
+                if let Err(err) = visit(path, derivation) {
+                    eprintln!("{}: {err}", path.display());
+                    exit_code = ExitCode::FAILURE;
+                }
+            },
+            Err(err) => {
+                eprintln!("{}: {err}", path.display());
+                exit_code = ExitCode::FAILURE;
+            },
+        }
+    }
+    exit_code
+}
+
+/// Returns the name of the derivation in the `.drv` file at `path`, e.g.
+/// `hello-2.12.1` for `dwkzl7flwsi6rjginyhv9driwvyqf1s4-hello-2.12.1.drv`.
+fn derivation_name(path: &Path) -> Option<&str> {
+    path.file_name()?.to_str()?.get(HASH_PART_LENGTH + 1..)?.strip_suffix(".drv")
+}
+
+/// Returns the store path of `derivation`, read from the file at `path`.
+///
+/// The file may be a copy taken out of the store, so the store directory is
+/// taken from the paths the derivation refers to.
+fn drv_store_path(path: &Path, derivation: &Derivation) -> Option<StorePath> {
+    let store_dir = derivation.store_dir().unwrap_or(Path::new(DEFAULT_STORE_DIR));
+    StorePath::new(store_dir.join(path.file_name()?).to_str()?).ok()
+}
+
+/// Returns the store path of `derivation`, read from the file at `path`, or
+/// computes it from its contents and name if `path` is not a `.drv` file name,
+/// e.g. for standard input.
+fn input_drv_path(path: &Path, derivation: &Derivation) -> Option<StorePath> {
+    drv_store_path(path, derivation).or_else(|| {
+        let store_dir = derivation.store_dir().unwrap_or(Path::new(DEFAULT_STORE_DIR));
+        derivation.drv_path(store_dir, derivation.name()?).ok()
+    })
+}
+
+/// Prints the derivations at `paths` as parsed.
+fn parse(paths: &[PathBuf]) -> ExitCode {
+    for_each_derivation(paths, |_, derivation| writeln!(io::stdout().lock(), "{derivation:#?}"))
+}
+
+/// Describes an output as its path, if it is known, and its kind.
+fn describe_output(output: &DerivationOutput) -> String {
+    match *output {
+        DerivationOutput::InputAddressed(ref path) => path.to_string(),
+        DerivationOutput::CaFixed { ref path, .. } => {
+            format!("{path} (fixed, {}:{})", output.aterm_hash_algo(), output.aterm_hash())
         },
+        DerivationOutput::CaFloating { .. } => format!("(floating, {})", output.aterm_hash_algo()),
+        DerivationOutput::Deferred => "(deferred)".to_owned(),
+        DerivationOutput::Impure { .. } => format!("(impure, {})", output.aterm_hash_algo()),
+        _ => format!("{output:?}"),
+    }
+}
+
+/// Prints the derivations at `paths` in a readable form.
+fn show(paths: &[PathBuf]) -> ExitCode {
+    for_each_derivation(paths, |path, derivation| {
+        let mut stdout = io::stdout().lock();
+        match input_drv_path(path, &derivation) {
+            Some(drv_path) => writeln!(stdout, "{drv_path}")?,
+            None => writeln!(stdout, "{}", path.display())?,
+        }
+        writeln!(stdout, "  system: {}", derivation.system)?;
+        writeln!(stdout, "  builder: {}", derivation.builder.display())?;
+        writeln!(stdout, "  args:")?;
+        for arg in &derivation.args {
+            writeln!(stdout, "    {arg:?}")?;
+        }
+        writeln!(stdout, "  outputs:")?;
+        for (name, output) in &derivation.outputs {
+            writeln!(stdout, "    {name}: {}", describe_output(output))?;
+        }
+        writeln!(stdout, "  input derivations:")?;
+        for (input_path, input) in &derivation.input_drvs {
+            writeln!(stdout, "    {input_path} [{}]", input.output_names().join(", "))?;
+        }
+        writeln!(stdout, "  input sources:")?;
+        for input_path in &derivation.input_srcs {
+            writeln!(stdout, "    {input_path}")?;
+        }
+        writeln!(stdout, "  environment:")?;
+        for (key, value) in &derivation.env {
+            writeln!(stdout, "    {key} = {value:?}")?;
+        }
+        Ok(())
+    })
+}
+
+/// Renders the derivations at `paths` as they are written in `.drv` files,
+/// separated by newlines.
+fn render(paths: &[PathBuf]) -> ExitCode {
+    let mut is_first = true;
+    for_each_derivation(paths, |_, derivation| {
+        let mut stdout = io::stdout().lock();
+        if !is_first {
+            writeln!(stdout)?;
+        }
+        is_first = false;
+        write_derivation(&mut stdout, &derivation)?;
+        stdout.flush()
+    })
+}
+
+/// Prints the derivations at `paths` as JSON keyed by their paths, like
+/// `nix derivation show`, in `format`.
+fn json(paths: &[PathBuf], format: JsonFormat) -> ExitCode {
+    let mut derivations = BTreeMap::new();
+    let exit_code = for_each_derivation(paths, |path, derivation| {
+        let drv_path = input_drv_path(path, &derivation)
+            .ok_or_else(|| io::Error::other("can not tell the path of a derivation without a name"))?;
+        derivations.insert(drv_path, derivation);
+        Ok(())
+    });
+    let mut stdout = io::stdout().lock();
+    let json = derivations_to_json(&derivations, format);
+    if let Err(err) = write_json(&mut stdout, &json).and_then(|()| writeln!(stdout)) {
+        eprintln!("{err}");
+        return ExitCode::FAILURE;
+    }
+    exit_code
+}
+
+/// Reads the JSON derivations in the file at `path`, or from standard input if
+/// `path` is `-`. The JSON can be a single derivation, or an object of them
+/// keyed by their paths, like `nix derivation show` prints.
+fn read_json_derivations(path: &Path) -> Result<Vec<Derivation>, Box<dyn Error>> {
+    let input = if path.as_os_str() == "-" {
+        io::read_to_string(io::stdin().lock())?
+    } else {
+        fs::read_to_string(path)?
+    };
+    let value = parse_json(&input)?;
+    let store_dir = Path::new(DEFAULT_STORE_DIR);
+    if value.get("outputs").is_some() {
+        Ok(vec![derivation_from_json(&value, store_dir)?])
+    } else {
+        Ok(derivations_from_json(&value, store_dir)?.into_values().collect())
+    }
+}
+
+/// Renders the JSON derivations at `paths` as they are written in `.drv`
+/// files, separated by newlines.
+fn from_json(paths: &[PathBuf]) -> ExitCode {
+    let mut exit_code = ExitCode::SUCCESS;
+    let mut is_first = true;
+    for path in paths {
+        let derivations = match read_json_derivations(path) {
+            Ok(derivations) => derivations,
+            Err(err) => {
+                eprintln!("{}: {err}", path.display());
+                exit_code = ExitCode::FAILURE;
+                continue;
+            },
+        };
+        let mut stdout = io::stdout().lock();
+        for derivation in derivations {
+            let written = if is_first { Ok(()) } else { writeln!(stdout) }
+                .and_then(|()| write_derivation(&mut stdout, &derivation))
+                .and_then(|()| stdout.flush());
+            if let Err(err) = written {
+                eprintln!("{err}");
+                return ExitCode::FAILURE;
+            }
+            is_first = false;
+        }
+    }
+    exit_code
+}
+
+/// Checks that the name of each `.drv` file matches its contents and that the
+/// output paths recorded in it are the ones Nix computes. Input derivations are
+/// read from the directory of the file that uses them.
+fn check(paths: &[PathBuf]) -> ExitCode {
+    let mut hashers = BTreeMap::new();
+    let mut exit_code = ExitCode::SUCCESS;
+    for path in paths {
+        let Some(name) = derivation_name(path) else {
+            eprintln!("{}: not a `.drv` file", path.display());
+            exit_code = ExitCode::FAILURE;
+            continue;
+        };
+        let derivation = match read_input(path) {
+            Ok(derivation) => derivation,
+            Err(err) => {
+                eprintln!("{}: {err}", path.display());
+                exit_code = ExitCode::FAILURE;
+                continue;
+            },
+        };
+        let mut is_valid = true;
+        match drv_store_path(path, &derivation).map(|store_path| derivation.check_drv_path(&store_path)) {
+            Some(Ok(())) => {},
+            Some(Err(err)) => {
+                eprintln!("{}: {err}", path.display());
+                is_valid = false;
+            },
+            None => {
+                eprintln!("{}: not a valid `.drv` file name", path.display());
+                is_valid = false;
+            },
+        }
+        let directory = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        let hasher = hashers
+            .entry(directory.clone())
+            .or_insert_with(|| DerivationHasher::new(FileResolver::in_directory(directory)));
+        match hasher.verify_outputs(&derivation, name) {
+            Ok(mismatches) => {
+                for mismatch in &mismatches {
+                    eprintln!("{}: {mismatch}", path.display());
+                }
+                is_valid &= mismatches.is_empty();
+            },
+            Err(err) => {
+                eprintln!("{}: {err}", path.display());
+                is_valid = false;
+            },
+        }
+        if is_valid {
+            println!("{}: ok", path.display());
+        } else {
+            exit_code = ExitCode::FAILURE;
+        }
+    }
+    exit_code
+}
+
+/// Loads the closure of the `.drv` files at `paths`, which must all be in the
+/// same directory, printing any error.
+fn load_closure(paths: &[PathBuf]) -> Option<DerivationGraph> {
+    let directory = paths.first().map_or(Path::new("."), |path| parent_directory(path));
+    let canonical_directory = fs::canonicalize(directory).ok();
+    let mut roots = Vec::new();
+    for path in paths {
+        if fs::canonicalize(parent_directory(path)).ok() != canonical_directory {
+            eprintln!("{}: not in the same directory as `{}`", path.display(), directory.display());
+            return None;
+        }
+        let root = File::open(path)
+            .map_err(Into::into)
+            .and_then(read_derivation)
+            .map(|derivation| drv_store_path(path, &derivation));
+        match root {
+            Ok(Some(root)) => roots.push(root),
+            Ok(None) => {
+                eprintln!("{}: not a valid `.drv` file name", path.display());
+                return None;
+            },
+            Err(err) => {
+                eprintln!("{}: {err}", path.display());
+                return None;
+            },
+        }
+    }
+    DerivationGraph::load_from_directory(directory, roots).map_err(|err| eprintln!("{err}")).ok()
+}
+
+/// Prints the closure of the `.drv` files at `paths` with the outputs each
+/// derivation uses from its inputs.
+fn closure(paths: &[PathBuf]) -> ExitCode {
+    let Some(graph) = load_closure(paths) else {
+        return ExitCode::FAILURE;
+    };
+    for path in graph.nodes().keys() {
+        println!("{path}");
+        for edge in graph.dependencies(path) {
+            let outputs = edge.input.value.iter().map(String::as_str).collect::<Vec<_>>();
+            println!("  {} [{}]", edge.to, outputs.join(", "));
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// Prints the closure of the `.drv` files at `paths` as a `dot`, `graphml` or
+/// `mermaid` graph, with the derivations that `options` select.
+fn graph(format: &Path, paths: &[PathBuf], options: &ExportOptions) -> ExitCode {
+    let write = match format.to_str() {
+        Some("dot") => exporters::write_dot,
+        Some("graphml") => exporters::write_graphml,
+        Some("mermaid") => exporters::write_mermaid,
+        _ => {
+            return usage_error(&format!(
+                "unknown graph format `{}`, expected `dot`, `graphml` or `mermaid`",
+                format.display(),
+            ));
+        },
+    };
+    let Some(graph) = load_closure(paths) else {
+        return ExitCode::FAILURE;
+    };
+    match write(&mut io::stdout().lock(), &graph, options) {
+        Ok(()) => ExitCode::SUCCESS,
         Err(err) => {
-            eprintln!("Error parsing DerivationOutput: {err:?}");
+            eprintln!("{err}");
+            ExitCode::FAILURE
         },
+    }
+}
+
+/// Prints the differences between the `.drv` files at `old` and `new`, and
+/// between their input derivations if `recursive`. Like `diff`, fails if there
+/// are differences.
+fn diff(old: &Path, new: &Path, recursive: bool) -> ExitCode {
+    let (old_derivation, new_derivation) = match (read_input(old), read_input(new)) {
+        (Ok(old_derivation), Ok(new_derivation)) => (old_derivation, new_derivation),
+        (Err(err), _) => {
+            eprintln!("{}: {err}", old.display());
+            return ExitCode::FAILURE;
+        },
+        (_, Err(err)) => {
+            eprintln!("{}: {err}", new.display());
+            return ExitCode::FAILURE;
+        },
+    };
+    let diff = if recursive {
+        let resolver = |path: &Path| FileResolver::in_directory(path.parent().unwrap_or(Path::new(".")));
+        match DerivationDiff::recursive(&old_derivation, &new_derivation, &mut resolver(old), &mut resolver(new)) {
+            Ok(diff) => diff,
+            Err(err) => {
+                eprintln!("{err}");
+                return ExitCode::FAILURE;
+            },
+        }
+    } else {
+        DerivationDiff::new(&old_derivation, &new_derivation)
+    };
+    print!("{diff}");
+    if diff.is_empty() { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
+
+/// Prints the derivations that changed by themselves between the closures of
+/// the `.drv` files at `old` and `new`, causing everything else to rebuild.
+fn rebuilds(old: &Path, new: &Path) -> ExitCode {
+    let (Some(old_graph), Some(new_graph)) = (load_closure(&[old.to_path_buf()]), load_closure(&[new.to_path_buf()]))
+    else {
+        return ExitCode::FAILURE;
+    };
+    match RebuildReport::new(&old_graph, &new_graph) {
+        Ok(report) => {
+            print!("{report}");
+            ExitCode::SUCCESS
+        },
+        Err(err) => {
+            eprintln!("{}: {err}", new.display());
+            ExitCode::FAILURE
+        },
+    }
+}
+
+/// Prints the shortest chains of input derivations from the `.drv` file at
+/// `root` to the derivations with the path or name `target`, and the outputs
+/// used at each hop. With `all`, every shortest chain is printed as one tree
+/// in which each derivation is expanded once, like `nix why-depends --all`.
+fn why_depends(root: &Path, target: &str, all: bool) -> ExitCode {
+    let Some(graph) = load_closure(&[root.to_path_buf()]) else {
+        return ExitCode::FAILURE;
+    };
+    let Some(root_path) = graph.roots().into_iter().next() else {
+        return ExitCode::FAILURE;
     };
+    let is_target = |path: &StorePath| {
+        path.as_str() == target || path.name() == target || path.name().strip_suffix(".drv") == Some(target)
+    };
+    let Some(edges) = graph.why_depends(root_path, is_target, all) else {
+        eprintln!("`{root_path}` does not depend on `{target}`");
+        return ExitCode::FAILURE;
+    };
+    let mut dependencies = BTreeMap::<_, Vec<_>>::new();
+    for edge in &edges {
+        dependencies.entry(edge.from).or_default().push(edge);
+    }
+    println!("{root_path}");
+    let mut expanded = BTreeSet::from([root_path]);
+    // The edges left to print, last first, with the prefix of their line.
+    let mut pending = Vec::new();
+    let push_dependencies = |pending: &mut Vec<_>, path, prefix: &str| {
+        let edges = dependencies.get(path).map(Vec::as_slice).unwrap_or_default();
+        for (index, edge) in edges.iter().enumerate().rev() {
+            pending.push((*edge, prefix.to_owned(), index + 1 == edges.len()));
+        }
+    };
+    push_dependencies(&mut pending, root_path, "");
+    while let Some((edge, prefix, is_last)) = pending.pop() {
+        let (branch, indent) = if is_last { ("└───", "    ") } else { ("├───", "│   ") };
+        println!("{prefix}{branch}{} ({})", edge.to, edge.input.output_names().join(", "));
+        if expanded.insert(edge.to) {
+            push_dependencies(&mut pending, edge.to, &format!("{prefix}{indent}"));
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// Prints the derivations that produce the outputs matching `queries`, which
+/// are paths inside the outputs or prefixes of their hash parts, indexing the
+/// `.drv` files in `directory`.
+fn deriver(directory: &Path, queries: &[PathBuf]) -> ExitCode {
+    let index = match OutputIndex::load_directory(directory) {
+        Ok((index, skipped)) => {
+            for err in skipped {
+                eprintln!("skipping {err}");
+            }
+            index
+        },
+        Err(err) => {
+            eprintln!("{}: {err}", directory.display());
+            return ExitCode::FAILURE;
+        },
+    };
+    let mut status = ExitCode::SUCCESS;
+    for query in queries {
+        let prefix = query.to_string_lossy();
+        let found = if query.is_absolute() {
+            index.find_containing(query).into_iter().collect::<Vec<_>>()
+        } else {
+            index.find_by_hash_prefix(&prefix).collect::<Vec<_>>()
+        };
+        if found.is_empty() {
+            eprintln!("{}: no deriver found", query.display());
+            status = ExitCode::FAILURE;
+        }
+        for (output, derivers) in found {
+            for deriver in derivers {
+                println!("{output}\t{}\t{}", deriver.drv_path, deriver.output);
+            }
+        }
+    }
+    status
+}
+
+fn main() -> ExitCode {
+    let args = env::args_os().skip(1).map(PathBuf::from).collect::<Vec<_>>();
+    let Some((command, arguments)) = args.split_first() else {
+        return usage_error("missing command");
+    };
+    let command = command.to_string_lossy();
+    match &*command {
+        "parse" | "show" | "render" | "json" | "from-json" => {
+            let (format, arguments) = match arguments.split_first() {
+                Some((flag, rest)) if command == "json" && flag.as_os_str() == "--format" => {
+                    let format = match rest.first().and_then(|format| format.to_str()) {
+                        Some("unversioned") => JsonFormat::Unversioned,
+                        Some("3") => JsonFormat::Version3,
+                        Some("4") => JsonFormat::Version4,
+                        _ => return usage_error("expected `--format unversioned`, `--format 3` or `--format 4`"),
+                    };
+                    (format, rest.get(1..).unwrap_or_default())
+                },
+                _ => (JsonFormat::Unversioned, arguments),
+            };
+            let Some(paths) = expand_globs(arguments) else {
+                return ExitCode::FAILURE;
+            };
+            let paths = if paths.is_empty() { vec![PathBuf::from("-")] } else { paths };
+            match &*command {
+                "parse" => parse(&paths),
+                "show" => show(&paths),
+                "render" => render(&paths),
+                "json" => json(&paths, format),
+                _ => from_json(&paths),
+            }
+        },
+        "check" | "closure" => {
+            if arguments.is_empty() {
+                return usage_error(&format!("usage: {command} FILES..."));
+            }
+            let Some(paths) = expand_globs(arguments) else {
+                return ExitCode::FAILURE;
+            };
+            if command == "check" { check(&paths) } else { closure(&paths) }
+        },
+        "graph" => {
+            let (mut options, mut arguments) = (ExportOptions::new(), arguments);
+            loop {
+                match *arguments {
+                    [ref flag, ref depth, ref rest @ ..] if flag.as_os_str() == "--depth" => {
+                        let Some(depth) = depth.to_str().and_then(|depth| depth.parse().ok()) else {
+                            return usage_error("expected `--depth N` with a non-negative number N");
+                        };
+                        options = options.with_max_depth(depth);
+                        arguments = rest;
+                    },
+                    [ref flag, ref pattern, ref rest @ ..] if flag.as_os_str() == "--pattern" => {
+                        options = options.with_pattern(pattern.to_string_lossy());
+                        arguments = rest;
+                    },
+                    _ => break,
+                }
+            }
+            let Some((format, files)) = arguments.split_first().filter(|&(_, files)| !files.is_empty()) else {
+                return usage_error("usage: graph [--depth N] [--pattern GLOB] FORMAT FILES...");
+            };
+            expand_globs(files).map_or(ExitCode::FAILURE, |paths| graph(format, &paths, &options))
+        },
+        "diff" => match *arguments {
+            [ref old, ref new] => diff(old, new, false),
+            [ref flag, ref old, ref new] if flag.as_os_str() == "--recursive" => diff(old, new, true),
+            _ => usage_error("usage: diff [--recursive] OLD NEW"),
+        },
+        "rebuilds" => match *arguments {
+            [ref old, ref new] => rebuilds(old, new),
+            _ => usage_error("usage: rebuilds OLD NEW"),
+        },
+        "why-depends" => {
+            let (all, arguments) = match arguments.split_first() {
+                Some((flag, rest)) if flag.as_os_str() == "--all" => (true, rest),
+                _ => (false, arguments),
+            };
+            match *arguments {
+                [ref root, ref target] => why_depends(root, &target.to_string_lossy(), all),
+                _ => usage_error("usage: why-depends [--all] ROOT TARGET"),
+            }
+        },
+        "deriver" => match arguments.split_first() {
+            Some((directory, queries)) if !queries.is_empty() => deriver(directory, queries),
+            _ => usage_error("usage: deriver DIRECTORY QUERIES..."),
+        },
+        "help" | "--help" | "-h" => {
+            print!("{USAGE}");
+            ExitCode::SUCCESS
+        },
+        _ => usage_error(&format!("unknown command `{command}`")),
+    }
 }
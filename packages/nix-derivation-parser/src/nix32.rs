@@ -0,0 +1,135 @@
+//! Nix's base-32 encoding, which store path hash parts and many hashes are
+//! written in.
+//!
+//! Unlike RFC 4648 base-32, the alphabet leaves out `e`, `o`, `u` and `t`,
+//! encoding starts from the last 5 bits of the last byte, and nothing is padded.
+//! This follows `libutil/hash.cc` in Nix.
+pub mod errors;
+
+use crate::nix32::errors::Nix32Error;
+
+/// The characters of Nix's base-32 encoding, in order of their values.
+pub const ALPHABET: &[u8; 32] = b"0123456789abcdfghijklmnpqrsvwxyz";
+
+/// Returns the number of characters `size` bytes are encoded as.
+#[inline]
+#[must_use]
+pub const fn encoded_len(size: usize) -> usize {
+    (size * 8).div_ceil(5)
+}
+
+/// Returns the number of bytes `length` characters decode to.
+#[inline]
+#[must_use]
+pub const fn decoded_len(length: usize) -> usize {
+    length * 5 / 8
+}
+
+/// Returns whether `character` is in Nix's base-32 alphabet.
+#[inline]
+#[must_use]
+pub fn is_valid_char(character: char) -> bool {
+    u8::try_from(character).is_ok_and(|byte| ALPHABET.contains(&byte))
+}
+
+/// Encodes bytes in Nix's base-32.
+#[inline]
+#[must_use]
+pub fn encode(bytes: &[u8]) -> String {
+    (0..encoded_len(bytes.len()))
+        .rev()
+        .map(|index| {
+            let (byte, bit) = (index * 5 / 8, index * 5 % 8);
+            let pair = u16::from(bytes[byte]) | u16::from(bytes.get(byte + 1).copied().unwrap_or(0)) << 8;
+            char::from(ALPHABET[usize::from((pair >> bit) & 0x1f)])
+        })
+        .collect()
+}
+
+/// Decodes Nix's base-32 into `decoded_len(input.len())` bytes.
+///
+/// # Errors
+///
+/// Returns a `Nix32Error` if `input` has a character that is not in the
+/// alphabet, or if the bits left over after the last whole byte are not zero.
+#[inline]
+pub fn decode(input: &str) -> Result<Vec<u8>, Nix32Error> {
+    let mut bytes = vec![0_u8; decoded_len(input.len())];
+    for (index, character) in input.chars().rev().enumerate() {
+        let digit = u8::try_from(character)
+            .ok()
+            .and_then(|byte| ALPHABET.iter().position(|&candidate| candidate == byte))
+            .ok_or(Nix32Error::InvalidCharacter(character))?;
+        let (byte, bit) = (index * 5 / 8, index * 5 % 8);
+        let [low, high] = (u16::try_from(digit).unwrap_or_default() << bit).to_le_bytes();
+        for (position, part) in [(byte, low), (byte + 1, high)] {
+            match bytes.get_mut(position) {
+                Some(target) => *target |= part,
+                None if part == 0 => {},
+                None => return Err(Nix32Error::NonZeroPadding),
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+/// Checks that `input` is valid Nix base-32, as `decode` would.
+///
+/// # Errors
+///
+/// Returns a `Nix32Error` if `decode` would fail.
+#[inline]
+pub fn validate(input: &str) -> Result<(), Nix32Error> {
+    decode(input).map(drop)
+}
+
+/// Folds `hash` into `size` bytes by XOR-ing every byte into position
+/// `index % size`.
+///
+/// Nix uses this to shorten a SHA-256 digest to the 20 bytes of a store path
+/// hash part, see `compressHash` in Nix.
+#[inline]
+#[must_use]
+pub fn compress(hash: &[u8], size: usize) -> Vec<u8> {
+    let mut compressed = vec![0_u8; size];
+    for (index, &byte) in hash.iter().enumerate() {
+        if let Some(target) = compressed.get_mut(index % size) {
+            *target ^= byte;
+        }
+    }
+    compressed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashes::encodings::decode_base16;
+
+    // The sha256 of the empty string.
+    const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    #[test]
+    fn round_trip() {
+        let digest = decode_base16(EMPTY_SHA256).unwrap();
+        let encoded = "0mdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c73";
+        assert_eq!(encoded_len(digest.len()), encoded.len());
+        assert_eq!(decoded_len(encoded.len()), digest.len());
+        assert_eq!(encode(&digest), encoded);
+        assert_eq!(decode(encoded), Ok(digest));
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(decode("0e"), Err(Nix32Error::InvalidCharacter('e')));
+        assert_eq!(decode("z0"), Err(Nix32Error::NonZeroPadding));
+        assert_eq!(validate("0mdqa9w1p6cmli6976v4wi0sw9r4p5pr"), Ok(()));
+        assert!(!is_valid_char('u'));
+    }
+
+    #[test]
+    fn compress_to_hash_part() {
+        let digest = decode_base16(EMPTY_SHA256).unwrap();
+        assert_eq!(encode(&compress(&digest, 20)), "wi0sw9r4p5prk7acm7i0z1b97h75faw7");
+        assert_eq!(compress(&[1, 2, 3], 2), vec![2, 2]);
+    }
+}
@@ -0,0 +1,25 @@
+use core::fmt;
+use std::error::Error;
+
+/// An error produced when a string is not valid Nix base-32.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Nix32Error {
+    /// The string contains a character that is not in the alphabet.
+    InvalidCharacter(char),
+    /// The bits left over after the last whole byte are not zero, so the string
+    /// is not the encoding of any bytes.
+    NonZeroPadding,
+}
+
+impl fmt::Display for Nix32Error {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::InvalidCharacter(character) => write!(formatter, "invalid base-32 character `{character}`"),
+            Self::NonZeroPadding => formatter.write_str("base-32 string has leftover bits set"),
+        }
+    }
+}
+
+impl Error for Nix32Error {}
@@ -0,0 +1,85 @@
+//! Positions in the input of a parser, as lines and columns for error messages.
+use core::fmt;
+
+/// A position in the input of a parser.
+#[expect(clippy::exhaustive_structs, reason = "A position is fully described by these fields.")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Position {
+    /// The offset in bytes from the start of the input, starting at 0.
+    pub offset: usize,
+    /// The line number, starting at 1.
+    pub line: usize,
+    /// The column in bytes from the start of the line, starting at 1.
+    pub column: usize,
+}
+
+impl Position {
+    /// The position of the first byte of an input.
+    pub const START: Self = Self {
+        offset: 0,
+        line: 1,
+        column: 1,
+    };
+
+    /// Finds the line and column of `offset` in `input`.
+    #[inline]
+    #[must_use]
+    pub fn new(input: &[u8], offset: usize) -> Self {
+        Self::START.advance(input.get(..offset).unwrap_or(input))
+    }
+
+    /// Returns the position reached by reading `input` from this position.
+    #[inline]
+    #[must_use]
+    pub fn advance(self, input: &[u8]) -> Self {
+        Self {
+            offset: self.offset + input.len(),
+            line: self.line + input.iter().filter(|&&byte| byte == b'\n').count(),
+            column: match input.iter().rposition(|&byte| byte == b'\n') {
+                Some(newline) => input.len() - newline,
+                None => self.column + input.len(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "line {}, column {} (byte {})", self.line, self.column, self.offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn position() {
+        assert_eq!(Position::new(b"Derive([", 0), Position {
+            offset: 0,
+            line: 1,
+            column: 1,
+        });
+        assert_eq!(Position::new(b"Derive(\n[(\"out\"", 10), Position {
+            offset: 10,
+            line: 2,
+            column: 3,
+        });
+    }
+
+    #[test]
+    fn advance() {
+        let position = Position::new(b"Derive(\n[(", 10);
+        assert_eq!(position.advance(b"\"out\""), Position {
+            offset: 15,
+            line: 2,
+            column: 8,
+        });
+        assert_eq!(position.advance(b"\n\n("), Position {
+            offset: 13,
+            line: 4,
+            column: 2,
+        });
+    }
+}
@@ -0,0 +1,51 @@
+use crate::store_paths::types::MAX_NAME_LENGTH;
+
+use core::fmt;
+use std::error::Error;
+
+/// Why a path is not a valid store path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StorePathError {
+    /// The path is not an absolute path to an entry directly inside a store
+    /// directory.
+    NotInStore,
+    /// The last component of the path is too short to hold a hash and a name.
+    TooShort,
+    /// The hash part contains a character that is not in Nix's base-32 alphabet.
+    InvalidHashCharacter(char),
+    /// The hash part is not followed by a `-`.
+    MissingDash,
+    /// The name is empty.
+    EmptyName,
+    /// The name is longer than `MAX_NAME_LENGTH` bytes.
+    NameTooLong(usize),
+    /// The name contains a character Nix does not allow in names.
+    InvalidNameCharacter(char),
+    /// The name is `.` or `..`, or starts with `.-` or `..-`.
+    ReservedName,
+}
+
+impl fmt::Display for StorePathError {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::NotInStore => formatter.write_str("not an absolute path directly inside a store directory"),
+            Self::TooShort => formatter.write_str("too short to contain a hash and a name"),
+            Self::InvalidHashCharacter(character) => {
+                write!(formatter, "invalid character `{}` in the hash part", character.escape_debug())
+            },
+            Self::MissingDash => formatter.write_str("the hash part is not followed by `-`"),
+            Self::EmptyName => formatter.write_str("the name is empty"),
+            Self::NameTooLong(length) => {
+                write!(formatter, "the name is {length} bytes long, more than the maximum of {MAX_NAME_LENGTH}")
+            },
+            Self::InvalidNameCharacter(character) => {
+                write!(formatter, "invalid character `{}` in the name", character.escape_debug())
+            },
+            Self::ReservedName => formatter.write_str("the name must not be `.` or `..`, or start with `.-` or `..-`"),
+        }
+    }
+}
+
+impl Error for StorePathError {}
@@ -0,0 +1,314 @@
+use crate::hashes::types::{
+    Hash,
+    HashEncoding,
+};
+use crate::nix32;
+use crate::nix32::errors::Nix32Error;
+use crate::store_paths::errors::StorePathError;
+
+use core::borrow::Borrow;
+use core::fmt;
+use core::str::FromStr;
+use std::path::Path;
+
+/// The store directory Nix uses by default.
+pub const DEFAULT_STORE_DIR: &str = "/nix/store";
+
+/// The size of the digest in the hash part of a store path, in bytes.
+pub const HASH_PART_SIZE: usize = 20;
+
+/// The length of the hash part of a store path, in base-32 characters.
+pub const HASH_PART_LENGTH: usize = nix32::encoded_len(HASH_PART_SIZE);
+
+/// The longest name a store path can have, in bytes.
+pub const MAX_NAME_LENGTH: usize = 211;
+
+/// A path to an entry directly inside a Nix store, e.g.
+/// `/nix/store/dwkzl7flwsi6rjginyhv9driwvyqf1s4-hello-2.12.1.drv`.
+///
+/// The last component of the path is a hash part of 32 base-32 characters, a
+/// `-`, and a name. The store directory is usually `/nix/store`, but any
+/// absolute directory is accepted.
+///
+/// Store paths are ordered by their bytes, the same way Nix orders them.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StorePath(String);
+
+impl StorePath {
+    /// Parses a store path.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StorePathError` if `path` is not an absolute path directly inside
+    /// a store directory, or if its hash part or name are invalid.
+    #[inline]
+    pub fn new(path: impl Into<String>) -> Result<Self, StorePathError> {
+        let path = path.into();
+        let (store_dir, base_name) = path.rsplit_once('/').ok_or(StorePathError::NotInStore)?;
+        if !store_dir.starts_with('/') {
+            return Err(StorePathError::NotInStore);
+        }
+        let (hash_part, name) = base_name.split_at_checked(HASH_PART_LENGTH).ok_or(StorePathError::TooShort)?;
+        // 32 characters hold exactly 20 bytes, so only the characters can be invalid.
+        if let Err(Nix32Error::InvalidCharacter(character)) = nix32::validate(hash_part) {
+            return Err(StorePathError::InvalidHashCharacter(character));
+        }
+        Self::check_name(name.strip_prefix('-').ok_or(StorePathError::MissingDash)?)?;
+        Ok(Self(path))
+    }
+
+    /// Makes the path Nix gives to a store entry, following `makeStorePath` in
+    /// Nix.
+    ///
+    /// The hash part is a digest of `kind`, `hash`, `store_dir` and `name`, where
+    /// `kind` says what was hashed, e.g. `source` for a NAR or `output:out` for
+    /// the `out` output of a derivation.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StorePathError` if `store_dir` is not absolute or `name` is not
+    /// a valid name.
+    #[inline]
+    pub fn make(store_dir: &Path, kind: &str, hash: &Hash, name: &str) -> Result<Self, StorePathError> {
+        Self::check_name(name)?;
+        let store_dir = store_dir.display();
+        let base16 = hash.encode(HashEncoding::Base16);
+        let fingerprint = format!("{kind}:{}:{base16}:{store_dir}:{name}", hash.algo());
+        let hash_part = nix32::encode(&nix32::compress(Hash::sha256(fingerprint.as_bytes()).digest(), HASH_PART_SIZE));
+        Self::new(format!("{store_dir}/{hash_part}-{name}"))
+    }
+
+    /// Checks that `name` follows Nix's rules for the names of store paths.
+    ///
+    /// Names are made of ASCII letters and digits and the characters `+-._?=`,
+    /// and are at most `MAX_NAME_LENGTH` bytes long.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StorePathError` describing the first rule `name` breaks.
+    #[inline]
+    pub fn check_name(name: &str) -> Result<(), StorePathError> {
+        if name.is_empty() {
+            return Err(StorePathError::EmptyName);
+        }
+        if name.len() > MAX_NAME_LENGTH {
+            return Err(StorePathError::NameTooLong(name.len()));
+        }
+        let is_allowed = |character: char| character.is_ascii_alphanumeric() || "+-._?=".contains(character);
+        if let Some(character) = name.chars().find(|&character| !is_allowed(character)) {
+            return Err(StorePathError::InvalidNameCharacter(character));
+        }
+        let without_dots = name.strip_prefix("..").or_else(|| name.strip_prefix('.'));
+        if matches!(without_dots, Some(rest) if rest.is_empty() || rest.starts_with('-')) {
+            return Err(StorePathError::ReservedName);
+        }
+        Ok(())
+    }
+
+    /// Returns the whole store path as a string.
+    #[inline]
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns the whole store path as a `Path`.
+    #[inline]
+    #[must_use]
+    pub fn as_path(&self) -> &Path {
+        Path::new(&self.0)
+    }
+
+    /// Consumes the `StorePath`, returning the whole store path as a string.
+    #[inline]
+    #[must_use]
+    pub fn into_string(self) -> String {
+        self.0
+    }
+
+    /// Returns the last component of the store path, e.g.
+    /// `dwkzl7flwsi6rjginyhv9driwvyqf1s4-hello-2.12.1.drv`.
+    #[inline]
+    #[must_use]
+    pub fn base_name(&self) -> &str {
+        self.0.rsplit_once('/').map_or(&*self.0, |(_, base_name)| base_name)
+    }
+
+    /// Returns the store directory the store path is in, e.g. `/nix/store`.
+    #[inline]
+    #[must_use]
+    pub fn store_dir(&self) -> &Path {
+        Path::new(self.0.rsplit_once('/').map_or("", |(store_dir, _)| store_dir))
+    }
+
+    /// Returns the hash part of the store path, e.g.
+    /// `dwkzl7flwsi6rjginyhv9driwvyqf1s4`.
+    #[inline]
+    #[must_use]
+    pub fn hash_part(&self) -> &str {
+        self.base_name().get(..HASH_PART_LENGTH).unwrap_or_default()
+    }
+
+    /// Returns the `HASH_PART_SIZE` bytes the hash part encodes.
+    #[inline]
+    #[must_use]
+    pub fn hash_digest(&self) -> Vec<u8> {
+        nix32::decode(self.hash_part()).expect("The hash part was validated when the store path was created.")
+    }
+
+    /// Returns the name of the store path, e.g. `hello-2.12.1.drv`.
+    #[inline]
+    #[must_use]
+    pub fn name(&self) -> &str {
+        self.base_name().get(HASH_PART_LENGTH + 1..).unwrap_or_default()
+    }
+
+    /// Returns whether the store path is a derivation, i.e. whether its name ends
+    /// in `.drv`.
+    #[inline]
+    #[must_use]
+    pub fn is_derivation(&self) -> bool {
+        self.name().ends_with(".drv")
+    }
+}
+
+impl fmt::Display for StorePath {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(&self.0)
+    }
+}
+
+impl FromStr for StorePath {
+    type Err = StorePathError;
+
+    #[inline]
+    fn from_str(path: &str) -> Result<Self, StorePathError> {
+        Self::new(path)
+    }
+}
+
+impl TryFrom<String> for StorePath {
+    type Error = StorePathError;
+
+    #[inline]
+    fn try_from(path: String) -> Result<Self, StorePathError> {
+        Self::new(path)
+    }
+}
+
+impl TryFrom<&str> for StorePath {
+    type Error = StorePathError;
+
+    #[inline]
+    fn try_from(path: &str) -> Result<Self, StorePathError> {
+        Self::new(path)
+    }
+}
+
+impl AsRef<str> for StorePath {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for StorePath {
+    #[inline]
+    fn as_ref(&self) -> &Path {
+        self.as_path()
+    }
+}
+
+impl Borrow<str> for StorePath {
+    #[inline]
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for StorePath {
+    #[inline]
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for StorePath {
+    #[inline]
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parts() {
+        let path = StorePath::new("/nix/store/dwkzl7flwsi6rjginyhv9driwvyqf1s4-hello-2.12.1.drv").unwrap();
+        assert_eq!(path.store_dir(), Path::new("/nix/store"));
+        assert_eq!(path.base_name(), "dwkzl7flwsi6rjginyhv9driwvyqf1s4-hello-2.12.1.drv");
+        assert_eq!(path.hash_part(), "dwkzl7flwsi6rjginyhv9driwvyqf1s4");
+        assert_eq!(nix32::encode(&path.hash_digest()), path.hash_part());
+        assert_eq!(path.name(), "hello-2.12.1.drv");
+        assert!(path.is_derivation());
+        assert_eq!(path.to_string(), "/nix/store/dwkzl7flwsi6rjginyhv9driwvyqf1s4-hello-2.12.1.drv");
+    }
+
+    #[test]
+    fn make() {
+        let hash = Hash::sha256(b"");
+        assert_eq!(
+            StorePath::make(Path::new(DEFAULT_STORE_DIR), "source", &hash, "hello"),
+            StorePath::new("/nix/store/m2bg6dwgvhmmpr5jsjn5adw9cwqpvpf7-hello")
+        );
+        assert_eq!(StorePath::make(Path::new(DEFAULT_STORE_DIR), "source", &hash, ""), Err(StorePathError::EmptyName));
+    }
+
+    #[test]
+    fn other_store_dir() {
+        let path = StorePath::new("/home/user/store/dwkzl7flwsi6rjginyhv9driwvyqf1s4-hello").unwrap();
+        assert_eq!(path.store_dir(), Path::new("/home/user/store"));
+        assert!(!path.is_derivation());
+    }
+
+    #[test]
+    fn invalid_paths() {
+        assert_eq!(StorePath::new("dwkzl7flwsi6rjginyhv9driwvyqf1s4-hello"), Err(StorePathError::NotInStore));
+        assert_eq!(StorePath::new("/dwkzl7flwsi6rjginyhv9driwvyqf1s4-hello"), Err(StorePathError::NotInStore));
+        assert_eq!(
+            StorePath::new("nix/store/dwkzl7flwsi6rjginyhv9driwvyqf1s4-hello"),
+            Err(StorePathError::NotInStore)
+        );
+        assert_eq!(StorePath::new("/nix/store/dwkzl7flwsi6rjginyhv9driwvyq"), Err(StorePathError::TooShort));
+        assert_eq!(
+            StorePath::new("/nix/store/ewkzl7flwsi6rjginyhv9driwvyqf1s4-hello"),
+            Err(StorePathError::InvalidHashCharacter('e'))
+        );
+        assert_eq!(
+            StorePath::new("/nix/store/dwkzl7flwsi6rjginyhv9driwvyqf1s4_hello"),
+            Err(StorePathError::MissingDash)
+        );
+        assert_eq!(StorePath::new("/nix/store/dwkzl7flwsi6rjginyhv9driwvyqf1s4-"), Err(StorePathError::EmptyName));
+        assert_eq!(
+            StorePath::new("/nix/store/dwkzl7flwsi6rjginyhv9driwvyqf1s4-hello world"),
+            Err(StorePathError::InvalidNameCharacter(' '))
+        );
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(StorePath::check_name("hello-2.12.1+really?=_"), Ok(()));
+        assert_eq!(StorePath::check_name(".hello"), Ok(()));
+        assert_eq!(StorePath::check_name("..hello"), Ok(()));
+        assert_eq!(StorePath::check_name("."), Err(StorePathError::ReservedName));
+        assert_eq!(StorePath::check_name(".."), Err(StorePathError::ReservedName));
+        assert_eq!(StorePath::check_name(".-hello"), Err(StorePathError::ReservedName));
+        assert_eq!(StorePath::check_name("..-hello"), Err(StorePathError::ReservedName));
+        assert_eq!(StorePath::check_name(&"a".repeat(MAX_NAME_LENGTH)), Ok(()));
+        assert_eq!(StorePath::check_name(&"a".repeat(MAX_NAME_LENGTH + 1)), Err(StorePathError::NameTooLong(212)));
+        assert_eq!(StorePath::check_name("héllo"), Err(StorePathError::InvalidNameCharacter('é')));
+    }
+}
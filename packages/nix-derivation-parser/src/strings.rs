@@ -1,2 +1,3 @@
 pub mod parsers;
+pub mod renderers;
 pub mod types;
@@ -0,0 +1,57 @@
+use std::io::{
+    self,
+    Write,
+};
+
+/// Render a string, surrounded by double quotes, using the escaping rules Nix
+/// applies when it writes a `.drv` file.
+///
+/// Only `"`, `\`, newlines, carriage returns and tabs are escaped; every other
+/// byte is written as is, so the string does not need to be valid UTF-8.
+#[inline]
+pub fn write_string<W: Write>(writer: &mut W, string: &[u8]) -> io::Result<()> {
+    writer.write_all(b"\"")?;
+    for &byte in string {
+        match byte {
+            b'"' => writer.write_all(b"\\\"")?,
+            b'\\' => writer.write_all(b"\\\\")?,
+            b'\n' => writer.write_all(b"\\n")?,
+            b'\r' => writer.write_all(b"\\r")?,
+            b'\t' => writer.write_all(b"\\t")?,
+            _ => writer.write_all(&[byte])?,
+        }
+    }
+    writer.write_all(b"\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(string: &[u8]) -> Vec<u8> {
+        let mut rendered = Vec::new();
+        write_string(&mut rendered, string).unwrap();
+        rendered
+    }
+
+    #[test]
+    fn plain() {
+        assert_eq!(render(b"hello"), br#""hello""#);
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(render(b""), br#""""#);
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(render(b"a\"b\\c\nd\re\tf"), br#""a\"b\\c\nd\re\tf""#);
+    }
+
+    #[test]
+    fn unescaped_bytes() {
+        assert_eq!(render("/ \u{08} \u{0C} ¬ \u{FF}".as_bytes()), "\"/ \u{08} \u{0C} ¬ \u{FF}\"".as_bytes());
+        assert_eq!(render(b"\xff\xfe"), b"\"\xff\xfe\"");
+    }
+}
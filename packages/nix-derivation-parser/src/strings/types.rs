@@ -1,10 +1,205 @@
+extern crate alloc;
+
+use alloc::borrow::Cow;
+use core::borrow::Borrow;
+use core::fmt::{
+    self,
+    Write,
+};
+use core::ops::Deref;
+use core::str::{
+    self,
+    Utf8Error,
+};
+
 /// A string fragment contains a fragment of a string being parsed: either a
-/// non-empty Literal (a series of non-escaped characters), a single parsed escaped
-/// character, or a block of escaped whitespace.
+/// non-empty Literal (a series of non-escaped bytes) or a single parsed escaped
+/// byte.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[non_exhaustive]
 pub enum StringFragment<'input> {
-    Literal(&'input str),
-    EscapedChar(char),
-    EscapedWS,
+    Literal(&'input [u8]),
+    EscapedByte(u8),
+}
+
+/// An owned string of arbitrary bytes.
+///
+/// Nix does not require the strings in a derivation to be valid UTF-8, so
+/// environment variables and builder arguments can contain any bytes. Use
+/// `to_str` to get at the contents when they are expected to be text.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ByteString(Vec<u8>);
+
+impl ByteString {
+    /// Creates an empty `ByteString`.
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    /// Returns the contents as a byte slice.
+    #[inline]
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Consumes the `ByteString`, returning the underlying bytes.
+    #[inline]
+    #[must_use]
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+
+    /// Returns the contents as a string slice if they are valid UTF-8.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Utf8Error` if the contents are not valid UTF-8.
+    #[inline]
+    pub fn to_str(&self) -> Result<&str, Utf8Error> {
+        str::from_utf8(&self.0)
+    }
+
+    /// Returns the contents as a string, replacing any invalid UTF-8 sequences
+    /// with `U+FFFD REPLACEMENT CHARACTER`.
+    #[inline]
+    #[must_use]
+    pub fn to_str_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.0)
+    }
+}
+
+impl Deref for ByteString {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for ByteString {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Borrow<[u8]> for ByteString {
+    #[inline]
+    fn borrow(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for ByteString {
+    #[inline]
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<&[u8]> for ByteString {
+    #[inline]
+    fn from(bytes: &[u8]) -> Self {
+        Self(bytes.to_vec())
+    }
+}
+
+impl From<String> for ByteString {
+    #[inline]
+    fn from(string: String) -> Self {
+        Self(string.into_bytes())
+    }
+}
+
+impl From<&str> for ByteString {
+    #[inline]
+    fn from(string: &str) -> Self {
+        Self(string.as_bytes().to_vec())
+    }
+}
+
+impl PartialEq<[u8]> for ByteString {
+    #[inline]
+    fn eq(&self, other: &[u8]) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<str> for ByteString {
+    #[inline]
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other.as_bytes()
+    }
+}
+
+impl PartialEq<&str> for ByteString {
+    #[inline]
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == other.as_bytes()
+    }
+}
+
+/// Formats the contents as a quoted string, escaping any bytes that are not
+/// valid UTF-8 as `\xNN`.
+impl fmt::Debug for ByteString {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_char('"')?;
+        for chunk in self.0.utf8_chunks() {
+            for character in chunk.valid().chars() {
+                // Like `str`'s `Debug` implementation, single quotes are left alone.
+                if character == '\'' {
+                    formatter.write_char(character)?;
+                } else {
+                    write!(formatter, "{}", character.escape_debug())?;
+                }
+            }
+            for byte in chunk.invalid() {
+                write!(formatter, "\\x{byte:02x}")?;
+            }
+        }
+        formatter.write_char('"')
+    }
+}
+
+/// Formats the contents lossily, replacing invalid UTF-8 sequences with
+/// `U+FFFD REPLACEMENT CHARACTER`.
+impl fmt::Display for ByteString {
+    #[inline]
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in self.0.utf8_chunks() {
+            formatter.write_str(chunk.valid())?;
+            if !chunk.invalid().is_empty() {
+                formatter.write_char(char::REPLACEMENT_CHARACTER)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_escapes_invalid_utf8() {
+        let string = ByteString::from(b"caf\xc3\xa9 \xff\"it's\"\n".as_slice());
+        assert_eq!(format!("{string:?}"), r#""café \xff\"it's\"\n""#);
+    }
+
+    #[test]
+    fn display_is_lossy() {
+        let string = ByteString::from(b"caf\xc3\xa9 \xff".as_slice());
+        assert_eq!(string.to_string(), "café \u{FFFD}");
+    }
+
+    #[test]
+    fn to_str() {
+        assert_eq!(ByteString::from("hello").to_str(), Ok("hello"));
+        assert!(ByteString::from(b"\xff".as_slice()).to_str().is_err());
+    }
 }
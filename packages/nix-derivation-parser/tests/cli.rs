@@ -0,0 +1,133 @@
+use std::env;
+use std::fs;
+use std::io::Write as _;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::process::{
+    self,
+    Command,
+    Output,
+    Stdio,
+};
+
+const HELLO: &str = "qhh9g08r8f6an2hly0p4rjh7q73myz93-hello-2.12.1.drv";
+
+fn edge_case_directory() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("src/derivations/edge_case_derivations")
+}
+
+/// Returns an empty directory for the test called `name`.
+fn temp_directory(name: &str) -> PathBuf {
+    let directory = env::temp_dir().join(format!("nix-derivation-parser-{name}-{}", process::id()));
+    if directory.exists() {
+        fs::remove_dir_all(&directory).unwrap();
+    }
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+/// Runs the command-line tool with `arguments`, writing `stdin` to its
+/// standard input.
+fn run(arguments: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_nix-derivation-parser"))
+        .args(arguments)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+#[test]
+fn usage_errors() {
+    let output = run(&[], b"");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).starts_with("missing command\n\nusage: nix-derivation-parser"));
+    assert!(output.stdout.is_empty());
+
+    let output = run(&["frobnicate"], b"");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).starts_with("unknown command `frobnicate`\n"));
+
+    let output = run(&["check"], b"");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).starts_with("usage: check FILES...\n"));
+}
+
+#[test]
+fn invalid_derivation() {
+    let output = run(&["render", "-"], b"Derive([(\"out\"");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).starts_with("-: "));
+    assert!(output.stdout.is_empty());
+
+    // A derivation renamed without updating its outputs fails the check.
+    let directory = temp_directory("invalid-derivation");
+    let renamed = directory.join("qhh9g08r8f6an2hly0p4rjh7q73myz93-hello-2.12.2.drv");
+    fs::copy(edge_case_directory().join(HELLO), &renamed).unwrap();
+    let output = run(&["check", renamed.to_str().unwrap()], b"");
+    fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty(), "{}", stdout(&output));
+    assert!(stderr(&output).starts_with(&format!("{}: ", renamed.display())));
+}
+
+#[test]
+fn globs() {
+    let pattern = edge_case_directory().join("*-hello-*.drv");
+    let output = run(&["check", pattern.to_str().unwrap()], b"");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let directory = edge_case_directory();
+    assert_eq!(
+        stdout(&output),
+        format!(
+            "{0}/0dkjx5z90qw7ci7qfhnl1p5l2ny9ba75-hello-2.12.1.tar.gz.drv: ok\n{0}/{HELLO}: ok\n",
+            directory.display(),
+        ),
+    );
+
+    let pattern = edge_case_directory().join("*-goodbye-*.drv");
+    let output = run(&["check", pattern.to_str().unwrap()], b"");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output), format!("{}: no files match\n", pattern.display()));
+}
+
+#[test]
+fn standard_input() {
+    let hello = fs::read(edge_case_directory().join(HELLO)).unwrap();
+    let output = run(&["render", "-"], &hello);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(output.stdout, hello);
+
+    let output = run(&["render"], &hello);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(output.stdout, hello);
+}
+
+#[test]
+fn closure_in_different_directories() {
+    let directory = temp_directory("closure");
+    fs::copy(edge_case_directory().join(HELLO), directory.join(HELLO)).unwrap();
+    let copy = directory.join(HELLO);
+    let empty_env = edge_case_directory().join("nkqgqq2l1fpyifm974rf8yhxc1gzmihx-empty-env.drv");
+    let output = run(&["closure", copy.to_str().unwrap(), empty_env.to_str().unwrap()], b"");
+    fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stderr(&output),
+        format!("{}: not in the same directory as `{}`\n", empty_env.display(), directory.display()),
+    );
+    assert!(output.stdout.is_empty());
+}